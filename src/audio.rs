@@ -0,0 +1,254 @@
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Seconds of audio kept by the sample tap
+const TAP_SECONDS: usize = 2;
+/// Samples collected by the audio thread before taking the tap lock
+const TAP_BATCH: usize = 1024;
+
+/// Plays only the `[start, end)` part of a source, as needed by CUE sheet tracks.
+///
+/// Positions given to `try_seek` are relative to `start`, so seeking inside a
+/// virtual track behaves exactly like seeking inside a regular file.
+pub struct Segment<S> {
+    inner: S,
+    start: Duration,
+    length: Option<Duration>,
+    remaining: Option<u64>,
+}
+
+impl<S: Source> Segment<S> {
+    pub fn new(mut inner: S, start: Duration, end: Option<Duration>) -> Self {
+        // A source that cannot seek is decoded up to the start instead: playing it
+        // from the beginning would play another track of the file
+        if !start.is_zero() && inner.try_seek(start).is_err() {
+            for _ in 0..samples_in(&inner, start) {
+                if inner.next().is_none() {
+                    break;
+                }
+            }
+        }
+
+        let length = end.map(|end| end.saturating_sub(start));
+        Self {
+            remaining: length.map(|length| samples_in(&inner, length)),
+            inner,
+            start,
+            length,
+        }
+    }
+}
+
+/// Number of samples (all channels) `source` plays in `duration`
+fn samples_in<S: Source>(source: &S, duration: Duration) -> u64 {
+    let samples_per_second = source.sample_rate() as f64 * source.channels() as f64;
+    (duration.as_secs_f64() * samples_per_second) as u64
+}
+
+impl<S: Source> Iterator for Segment<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if let Some(remaining) = &mut self.remaining {
+            if *remaining == 0 {
+                return None;
+            }
+            *remaining -= 1;
+        }
+        self.inner.next()
+    }
+}
+
+impl<S: Source> Source for Segment<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        match (self.inner.current_span_len(), self.remaining) {
+            (Some(span), Some(remaining)) => Some(span.min(remaining as usize)),
+            (None, Some(remaining)) => Some(remaining as usize),
+            (span, None) => span,
+        }
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.length
+            .or_else(|| Some(self.inner.total_duration()?.saturating_sub(self.start)))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(self.start + pos)?;
+        self.remaining = self
+            .length
+            .map(|length| samples_in(&self.inner, length.saturating_sub(pos)));
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct TapBuffer {
+    /// Most recent interleaved samples
+    samples: VecDeque<Sample>,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    /// Number of samples ever pushed, used as a cursor by sequential readers
+    total: u64,
+}
+
+/// Shared copy of the samples flowing to the sink, read by the visualizers and meters
+#[derive(Clone, Default)]
+pub struct SampleTap {
+    inner: Arc<Mutex<TapBuffer>>,
+}
+
+impl SampleTap {
+    fn push(&self, samples: &[Sample], channels: ChannelCount, sample_rate: SampleRate) {
+        let Ok(mut buffer) = self.inner.lock() else {
+            return;
+        };
+        buffer.channels = channels;
+        buffer.sample_rate = sample_rate;
+        buffer.samples.extend(samples);
+        buffer.total += samples.len() as u64;
+
+        // Drop whole frames only, so the oldest sample is always the first channel
+        let channels = channels.max(1) as usize;
+        let capacity = sample_rate as usize * channels * TAP_SECONDS;
+        let excess = buffer.samples.len().saturating_sub(capacity).div_ceil(channels) * channels;
+        let excess = excess.min(buffer.samples.len());
+        buffer.samples.drain(..excess);
+    }
+
+    /// Forget buffered samples, e.g. when playback stops
+    pub fn clear(&self) {
+        if let Ok(mut buffer) = self.inner.lock() {
+            buffer.samples.clear();
+        }
+    }
+
+    pub fn sample_rate(&self) -> SampleRate {
+        self.inner.lock().map_or(0, |buffer| buffer.sample_rate)
+    }
+
+    /// Every sample pushed since `cursor`, downmixed to mono, then move the cursor forward.
+    ///
+    /// Samples that already left the tap are skipped, so slow readers never fall behind.
+    pub fn read_mono_since(&self, cursor: &mut u64) -> Vec<Sample> {
+        let Ok(buffer) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let channels = buffer.channels.max(1) as usize;
+        let oldest = buffer.total - buffer.samples.len() as u64;
+        let skip = (cursor.saturating_sub(oldest) as usize / channels) * channels;
+        *cursor = buffer.total;
+
+        downmix(buffer.samples.iter().skip(skip).copied(), channels)
+    }
+
+    /// The latest `frames` frames, interleaved, with their channel count
+    pub fn latest_frames(&self, frames: usize) -> (Vec<Sample>, ChannelCount) {
+        let Ok(buffer) = self.inner.lock() else {
+            return (Vec::new(), 0);
+        };
+        let channels = buffer.channels.max(1) as usize;
+        let available = buffer.samples.len() / channels;
+        let skip = (available.saturating_sub(frames)) * channels;
+
+        let samples = buffer.samples.iter().skip(skip).copied().collect();
+        (samples, buffer.channels)
+    }
+
+    /// The latest `frames` frames downmixed to mono, oldest first
+    pub fn latest_mono(&self, frames: usize) -> Vec<Sample> {
+        let Ok(buffer) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let channels = buffer.channels.max(1) as usize;
+        let available = buffer.samples.len() / channels;
+        let skip = (available.saturating_sub(frames)) * channels;
+
+        downmix(buffer.samples.iter().skip(skip).copied(), channels)
+    }
+}
+
+fn downmix(samples: impl Iterator<Item = Sample>, channels: usize) -> Vec<Sample> {
+    samples
+        .collect::<Vec<_>>()
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<Sample>() / channels as Sample)
+        .collect()
+}
+
+/// Copies every sample of the wrapped source into a [`SampleTap`]
+pub struct Tap<S> {
+    inner: S,
+    tap: SampleTap,
+    batch: Vec<Sample>,
+}
+
+impl<S: Source> Tap<S> {
+    pub fn new(inner: S, tap: SampleTap) -> Self {
+        Self {
+            inner,
+            tap,
+            batch: Vec::with_capacity(TAP_BATCH),
+        }
+    }
+
+    fn flush(&mut self) {
+        self.tap
+            .push(&self.batch, self.inner.channels(), self.inner.sample_rate());
+        self.batch.clear();
+    }
+}
+
+impl<S: Source> Iterator for Tap<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.inner.next();
+        match sample {
+            Some(sample) => {
+                self.batch.push(sample);
+                if self.batch.len() >= TAP_BATCH {
+                    self.flush();
+                }
+            }
+            None if !self.batch.is_empty() => self.flush(),
+            None => {}
+        }
+        sample
+    }
+}
+
+impl<S: Source> Source for Tap<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.batch.clear();
+        self.inner.try_seek(pos)
+    }
+}
@@ -0,0 +1,101 @@
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc};
+
+use crate::audio::SampleTap;
+
+const FFT_SIZE: usize = 1024;
+const HOP_SIZE: usize = 512;
+/// Flux values used for the adaptive threshold (about half a second at 44.1 kHz)
+const HISTORY_LEN: usize = 43;
+/// How far above the local average the flux must go to count as an onset
+const THRESHOLD_FACTOR: f32 = 1.4;
+const THRESHOLD_OFFSET: f32 = 0.002;
+/// Minimum distance between two onsets, in seconds of audio
+const MIN_INTERVAL_SECS: f32 = 0.12;
+/// Samples kept when the reader falls behind (e.g. when the detector is first enabled)
+const MAX_PENDING: usize = FFT_SIZE * 32;
+
+/// Detects onsets in the tapped audio with spectral flux.
+///
+/// Time is measured in samples read from the tap, so the detector follows
+/// the music (and stops when it is paused) rather than the wall clock.
+pub struct BeatDetector {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    cursor: u64,
+    pending: Vec<f32>,
+    previous_spectrum: Vec<f32>,
+    history: VecDeque<f32>,
+    samples_since_onset: usize,
+}
+
+impl BeatDetector {
+    pub fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+
+        Self {
+            fft,
+            window,
+            cursor: 0,
+            pending: Vec::new(),
+            previous_spectrum: vec![0.0; FFT_SIZE / 2],
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            samples_since_onset: usize::MAX,
+        }
+    }
+
+    /// Consume the new samples and return the strength (`0.0..=1.0`) of each detected onset
+    pub fn process(&mut self, tap: &SampleTap) -> Vec<f32> {
+        self.pending.extend(tap.read_mono_since(&mut self.cursor));
+        let backlog = self.pending.len().saturating_sub(MAX_PENDING);
+        self.pending.drain(..backlog);
+        let min_interval = (tap.sample_rate() as f32 * MIN_INTERVAL_SECS) as usize;
+
+        let mut onsets = Vec::new();
+        while self.pending.len() >= FFT_SIZE {
+            let flux = self.spectral_flux();
+            self.pending.drain(..HOP_SIZE);
+            self.samples_since_onset = self.samples_since_onset.saturating_add(HOP_SIZE);
+
+            let average = if self.history.is_empty() {
+                f32::MAX
+            } else {
+                self.history.iter().sum::<f32>() / self.history.len() as f32
+            };
+            let threshold = average * THRESHOLD_FACTOR + THRESHOLD_OFFSET;
+
+            if self.history.len() == HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(flux);
+
+            if flux > threshold && self.samples_since_onset >= min_interval {
+                let strength = ((flux - threshold) / threshold).clamp(0.0, 1.0);
+                onsets.push(strength);
+                self.samples_since_onset = 0;
+            }
+        }
+        onsets
+    }
+
+    /// Sum of the increases in log magnitude since the previous frame
+    fn spectral_flux(&mut self) -> f32 {
+        let mut buffer: Vec<Complex<f32>> = self.pending[..FFT_SIZE]
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, w)| Complex::new(sample * w, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        let mut flux = 0.0;
+        for (bin, previous) in buffer.iter().zip(self.previous_spectrum.iter_mut()) {
+            let magnitude = (1.0 + bin.norm()).ln();
+            flux += (magnitude - *previous).max(0.0);
+            *previous = magnitude;
+        }
+        flux / (FFT_SIZE / 2) as f32
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{saved_playlists, storage};
+
+const BOOKMARKS_FILE: &str = "bookmarks.json";
+
+/// Last known position of a long track
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub title: String,
+    pub path: PathBuf,
+    pub position_ms: u64,
+    pub saved_at: u64,
+}
+
+impl Bookmark {
+    pub fn new(title: String, path: PathBuf, position: Duration) -> Self {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            title,
+            path,
+            position_ms: position.as_millis() as u64,
+            saved_at,
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_millis(self.position_ms)
+    }
+}
+
+/// Resume positions keyed by track identity, persisted in the data directory
+pub struct Bookmarks {
+    file: Option<PathBuf>,
+    entries: BTreeMap<String, Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn load() -> Self {
+        let file = storage::data_file(BOOKMARKS_FILE);
+        let entries = file
+            .as_deref()
+            .map(storage::load_json)
+            .unwrap_or_default();
+
+        Self { file, entries }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Bookmark> {
+        self.entries.get(id)
+    }
+
+    pub fn set(&mut self, id: String, bookmark: Bookmark) {
+        self.entries.insert(id, bookmark);
+        self.save();
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if self.entries.remove(id).is_some() {
+            self.save();
+        }
+    }
+
+    /// Follow files that were moved, keyed by their old absolute path
+    pub fn rename_paths(&mut self, renamed: &HashMap<PathBuf, PathBuf>) {
+        let mut changed = false;
+        for bookmark in self.entries.values_mut() {
+            if let Some(path) = renamed.get(&saved_playlists::absolute(&bookmark.path)) {
+                bookmark.path = path.clone();
+                changed = true;
+            }
+        }
+        if changed {
+            self.save();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.save();
+    }
+
+    /// All bookmarks, most recently saved first
+    pub fn list(&self) -> Vec<(&str, &Bookmark)> {
+        let mut list: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(id, bookmark)| (id.as_str(), bookmark))
+            .collect();
+        list.sort_by_key(|(_, bookmark)| Reverse(bookmark.saved_at));
+        list
+    }
+
+    fn save(&self) {
+        if let Some(file) = &self.file {
+            // Losing a bookmark is not worth interrupting playback
+            let _ = storage::save_json(file, &self.entries);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::{collections::HashSet, time::Duration};
+
+use crate::{jukebox_state::SongItem, sort::natural_cmp};
+
+const UNKNOWN_ARTIST: &str = "Unknown Artist";
+const UNKNOWN_ALBUM: &str = "Unknown Album";
+
+pub struct Album {
+    pub title: String,
+    pub year: Option<i32>,
+    /// Ordered by track number
+    pub tracks: Vec<SongItem>,
+}
+
+impl Album {
+    pub fn duration(&self) -> Duration {
+        self.tracks.iter().filter_map(SongItem::duration).sum()
+    }
+}
+
+pub struct Artist {
+    pub name: String,
+    pub albums: Vec<Album>,
+}
+
+impl Artist {
+    pub fn track_count(&self) -> usize {
+        self.albums.iter().map(|album| album.tracks.len()).sum()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.albums.iter().map(Album::duration).sum()
+    }
+}
+
+/// Row of the tree, as indices into the artists, their albums and the album tracks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node {
+    Artist(usize),
+    Album(usize, usize),
+    Track(usize, usize, usize),
+}
+
+/// Library grouped by album artist, album and track, with expandable nodes
+#[derive(Default)]
+pub struct Browser {
+    artists: Vec<Artist>,
+    /// Expanded artists, and albums as `artist/album`
+    expanded: HashSet<String>,
+    selected: usize,
+}
+
+impl Browser {
+    pub fn new(songs: &[SongItem]) -> Self {
+        let mut browser = Self::default();
+        browser.refresh(songs);
+        browser
+    }
+
+    /// Group the songs again, keeping the expanded nodes
+    pub fn refresh(&mut self, songs: &[SongItem]) {
+        self.artists = group(songs);
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+    }
+
+    pub fn artists(&self) -> &[Artist] {
+        &self.artists
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Visible rows, children of collapsed nodes left out
+    pub fn rows(&self) -> Vec<Node> {
+        let mut rows = Vec::new();
+        for (a, artist) in self.artists.iter().enumerate() {
+            rows.push(Node::Artist(a));
+            if !self.is_expanded(Node::Artist(a)) {
+                continue;
+            }
+            for (b, album) in artist.albums.iter().enumerate() {
+                rows.push(Node::Album(a, b));
+                if self.is_expanded(Node::Album(a, b)) {
+                    rows.extend((0..album.tracks.len()).map(|t| Node::Track(a, b, t)));
+                }
+            }
+        }
+        rows
+    }
+
+    pub fn is_expanded(&self, node: Node) -> bool {
+        self.key(node)
+            .is_some_and(|key| self.expanded.contains(&key))
+    }
+
+    pub fn move_selection(&mut self, direction: i32) {
+        let count = self.rows().len() as i32;
+        if count > 0 {
+            self.selected = (self.selected as i32 + direction).rem_euclid(count) as usize;
+        }
+    }
+
+    /// Expand or collapse the selected artist or album
+    pub fn toggle(&mut self) {
+        let Some(key) = self.selected_node().and_then(|node| self.key(node)) else {
+            return;
+        };
+        if !self.expanded.remove(&key) {
+            self.expanded.insert(key);
+        }
+    }
+
+    pub fn expand(&mut self) {
+        if let Some(key) = self.selected_node().and_then(|node| self.key(node)) {
+            self.expanded.insert(key);
+        }
+    }
+
+    /// Collapse the selected node, or move to its parent when already collapsed
+    pub fn collapse(&mut self) {
+        let Some(node) = self.selected_node() else {
+            return;
+        };
+        if self.is_expanded(node)
+            && let Some(key) = self.key(node)
+        {
+            self.expanded.remove(&key);
+            return;
+        }
+
+        let parent = match node {
+            Node::Artist(_) => return,
+            Node::Album(a, _) => Node::Artist(a),
+            Node::Track(a, b, _) => Node::Album(a, b),
+        };
+        if let Some(row) = self.rows().iter().position(|&row| row == parent) {
+            self.selected = row;
+        }
+    }
+
+    pub fn selected_node(&self) -> Option<Node> {
+        self.rows().get(self.selected).copied()
+    }
+
+    /// Songs under the selected node, in album and track order
+    pub fn selected_songs(&self) -> Vec<SongItem> {
+        match self.selected_node() {
+            Some(Node::Artist(a)) => self.artists[a]
+                .albums
+                .iter()
+                .flat_map(|album| album.tracks.iter().cloned())
+                .collect(),
+            Some(Node::Album(a, b)) => self.artists[a].albums[b].tracks.clone(),
+            Some(Node::Track(a, b, t)) => vec![self.artists[a].albums[b].tracks[t].clone()],
+            None => Vec::new(),
+        }
+    }
+
+    fn key(&self, node: Node) -> Option<String> {
+        match node {
+            Node::Artist(a) => Some(self.artists.get(a)?.name.clone()),
+            Node::Album(a, b) => {
+                let artist = self.artists.get(a)?;
+                Some(format!("{}/{}", artist.name, artist.albums.get(b)?.title))
+            }
+            Node::Track(..) => None,
+        }
+    }
+}
+
+/// Group songs by album artist (falling back to the track artist) and album
+fn group(songs: &[SongItem]) -> Vec<Artist> {
+    let mut artists: Vec<Artist> = Vec::new();
+
+    for song in songs.iter().filter(|song| !song.is_stream()) {
+        let tags = song.tags();
+        let artist_name = tags
+            .album_artist
+            .as_deref()
+            .or(tags.artist.as_deref())
+            .or(song.performer())
+            .unwrap_or(UNKNOWN_ARTIST);
+        let album_title = tags.album.as_deref().unwrap_or(UNKNOWN_ALBUM);
+
+        let artist = match artists.iter().position(|artist| artist.name == artist_name) {
+            Some(i) => &mut artists[i],
+            None => {
+                artists.push(Artist {
+                    name: artist_name.to_string(),
+                    albums: Vec::new(),
+                });
+                artists.last_mut().unwrap()
+            }
+        };
+        let album = match artist.albums.iter().position(|album| album.title == album_title) {
+            Some(i) => &mut artist.albums[i],
+            None => {
+                artist.albums.push(Album {
+                    title: album_title.to_string(),
+                    year: tags.year,
+                    tracks: Vec::new(),
+                });
+                artist.albums.last_mut().unwrap()
+            }
+        };
+        album.year = album.year.or(tags.year);
+        album.tracks.push(song.clone());
+    }
+
+    // Unknown artists and albums go last
+    artists.sort_by(|a, b| {
+        (a.name == UNKNOWN_ARTIST)
+            .cmp(&(b.name == UNKNOWN_ARTIST))
+            .then_with(|| natural_cmp(&a.name, &b.name))
+    });
+    for artist in &mut artists {
+        artist.albums.sort_by(|a, b| {
+            (a.title == UNKNOWN_ALBUM)
+                .cmp(&(b.title == UNKNOWN_ALBUM))
+                .then_with(|| a.year.cmp(&b.year))
+                .then_with(|| natural_cmp(&a.title, &b.title))
+        });
+        for album in &mut artist.albums {
+            // Stable sort: untagged tracks keep their playlist order
+            album
+                .tracks
+                .sort_by_key(|song| song.tags().track_number.unwrap_or(u32::MAX));
+        }
+    }
+    artists
+}
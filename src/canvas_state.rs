@@ -1,8 +1,29 @@
 use ratatui::text::Span;
 use std::time::{Duration, Instant};
 use std::fs;
+use std::sync::Arc;
+use image::imageops::FilterType;
 use image::{GenericImageView, Pixel};
 use rand::Rng;
+use ratatui::style::{Color, Style};
+
+use crate::{audio::SampleTap, beat::BeatDetector, cover_art::CoverArt, spectrum::SpectrumAnalyzer};
+
+/// Partial block characters used to draw the top of spectrum bars, in eighths
+const BAR_EIGHTHS: [&str; 8] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+/// Columns taken by each spectrum bar, followed by one empty column
+const BAR_WIDTH: u16 = 2;
+/// Height of the album cover, in percent of the jukebox pane
+const COVER_SIZE_PERCENT: u16 = 60;
+
+/// What is drawn on top of the jukebox background
+#[derive(Clone, Copy, PartialEq)]
+pub enum Visualizer {
+    /// Floating notes spawned on the beats of the song
+    Notes,
+    /// Spectrum bars following the audio
+    Spectrum,
+}
 
 #[derive(Clone)]
 pub struct FloatingNote {
@@ -12,8 +33,20 @@ pub struct FloatingNote {
     spawn_time: Instant,
 }
 
+/// Blend a note pixel toward a colour going from cool blue (soft onset) to red (strong onset)
+fn tint(r: u8, g: u8, b: u8, strength: f32) -> Color {
+    let lerp = |from: f32, to: f32, t: f32| from + (to - from) * t;
+    let target = [
+        lerp(80.0, 255.0, strength),
+        lerp(160.0, 60.0, strength),
+        lerp(255.0, 40.0, strength),
+    ];
+    let mix = |pixel: u8, target: f32| lerp(pixel as f32, target, 0.6) as u8;
+    Color::Rgb(mix(r, target[0]), mix(g, target[1]), mix(b, target[2]))
+}
+
 impl FloatingNote {
-    fn new(x: u16, y: u16, note_images: &[image::DynamicImage]) -> Option<Self> {
+    fn new(x: u16, y: u16, note_images: &[image::DynamicImage], strength: f32) -> Option<Self> {
         if note_images.is_empty() {
             return None;
         }
@@ -22,8 +55,8 @@ impl FloatingNote {
         let note_index = rng.random_range(0..note_images.len());
         let note_image = &note_images[note_index];
         
-        // Random size between 6x6 and 10x10
-        let size = rng.random_range(6..=10);
+        // Size between 6x6 (soft onset) and 10x10 (strong onset)
+        let size = 6 + (strength * 4.0).round() as u16;
         let resized = note_image.resize_exact(
             size as u32,
             size as u32,
@@ -39,10 +72,7 @@ impl FloatingNote {
                 let [r, g, b, a] = pixel.0;
                 
                 if a > 128 {
-                    row.push(Span::styled(
-                        "█",
-                        ratatui::style::Style::default().fg(ratatui::style::Color::Rgb(r, g, b)),
-                    ));
+                    row.push(Span::styled("█", Style::default().fg(tint(r, g, b, strength))));
                 } else { // Transparent
                     row.push(Span::raw(" "));
                 }
@@ -69,6 +99,12 @@ pub struct CanvasState {
     floating_notes: Vec<FloatingNote>,
     note_images: Vec<image::DynamicImage>,
     last_size: (u16, u16),
+    visualizer: Visualizer,
+    spectrum: SpectrumAnalyzer,
+    spectrum_bars: Vec<f32>,
+    beat_detector: BeatDetector,
+    /// Album art of the playing song, drawn over the jukebox
+    cover: Option<CoverArt>,
 }
 
 impl CanvasState {
@@ -89,9 +125,46 @@ impl CanvasState {
             floating_notes: Vec::new(),
             note_images,
             last_size: (0, 0),
+            visualizer: Visualizer::Notes,
+            spectrum: SpectrumAnalyzer::new(),
+            spectrum_bars: Vec::new(),
+            beat_detector: BeatDetector::new(),
+            cover: None,
         }
     }
+
+    pub fn toggle_visualizer(&mut self) {
+        self.visualizer = match self.visualizer {
+            Visualizer::Notes => Visualizer::Spectrum,
+            Visualizer::Spectrum => Visualizer::Notes,
+        };
+        self.floating_notes.clear();
+    }
+
+    pub fn visualizer(&self) -> Visualizer {
+        self.visualizer
+    }
+
+    /// Analyze the samples currently being played
+    pub fn update_spectrum(&mut self, width: u16, tap: &SampleTap) {
+        let bar_count = (width.saturating_sub(1) / (BAR_WIDTH + 1)) as usize;
+        self.spectrum_bars = self.spectrum.update(tap, bar_count).to_vec();
+    }
     
+    /// Show the cover of the playing song, redrawing the background when it changes
+    pub fn set_cover(&mut self, cover: Option<CoverArt>) {
+        let same = match (&self.cover, &cover) {
+            (Some(current), Some(new)) => Arc::ptr_eq(current, new),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            self.cover = cover;
+            self.cached_background = None;
+            self.last_size = (0, 0);
+        }
+    }
+
     pub fn update_is_playing(&mut self, is_playing: bool) {
         // If not playing, clear notes
         if !is_playing {
@@ -99,8 +172,10 @@ impl CanvasState {
         }
     }
     
-    pub fn update_notes(&mut self, width: u16, height: u16, is_playing: bool) {
-        if !is_playing {
+    pub fn update_notes(&mut self, width: u16, height: u16, is_playing: bool, tap: &SampleTap) {
+        // Always consume the tap, so stale beats do not burst out when notes come back
+        let onsets = self.beat_detector.process(tap);
+        if !is_playing || self.visualizer != Visualizer::Notes {
             return;
         }
         
@@ -108,8 +183,12 @@ impl CanvasState {
         
         self.floating_notes.retain(|note| !note.is_expired());
         
-        // Add a note with 50% probability
-        if rng.random_bool(0.5) && self.floating_notes.len() < 6 {
+        // Add a note on every detected onset
+        for strength in onsets {
+            if self.floating_notes.len() >= 6 {
+                break;
+            }
+
             let actual_width = width.saturating_sub(2);
             let actual_height = height.saturating_sub(2);
             
@@ -117,7 +196,7 @@ impl CanvasState {
                 let x = rng.random_range(0..actual_width);
                 let y = rng.random_range(0..actual_height);
                 
-                if let Some(note) = FloatingNote::new(x, y, &self.note_images) {
+                if let Some(note) = FloatingNote::new(x, y, &self.note_images, strength) {
                     self.floating_notes.push(note);
                 }
             }
@@ -150,6 +229,10 @@ impl CanvasState {
             }
         };
         
+        if self.visualizer == Visualizer::Spectrum {
+            self.draw_spectrum(&mut canvas, actual_width, actual_height);
+        }
+
         // Add notes to the canvas
         for note in &self.floating_notes {
             // Disegna la nota sul canvas
@@ -176,34 +259,100 @@ impl CanvasState {
         canvas
     }
     
-    fn create_background(&self, width: u16, height: u16) -> Vec<Vec<Span<'static>>> {
-        match image::open("img/jukebox.png") {
-            Ok(img) => {
-                let resized = img.resize_exact(
-                    width as u32,
-                    height as u32,
-                    image::imageops::FilterType::Nearest,
-                );
-                
-                (0..height)
-                    .map(|y| {
-                        (0..width)
-                            .map(|x| {
-                                let pixel = resized.get_pixel(x as u32, y as u32).to_rgb();
-                                let [r, g, b] = pixel.0;
-                                Span::styled(
-                                    "█",
-                                    ratatui::style::Style::default().fg(ratatui::style::Color::Rgb(r, g, b)),
-                                )
-                            })
-                            .collect()
-                    })
-                    .collect()
+    /// Draw the spectrum bars from the bottom of the canvas, over the background
+    fn draw_spectrum(&self, canvas: &mut [Vec<Span<'static>>], width: u16, height: u16) {
+        for (i, level) in self.spectrum_bars.iter().enumerate() {
+            let bar_x = i as u16 * (BAR_WIDTH + 1) + 1;
+            let eighths = (level * height as f32 * 8.0) as u16;
+
+            for row in 0..height {
+                // Rows counted from the bottom
+                let filled = eighths.saturating_sub(row * 8).min(8);
+                if filled == 0 {
+                    break;
+                }
+
+                let fraction = row as f32 / height as f32;
+                let color = if fraction < 0.5 {
+                    Color::Green
+                } else if fraction < 0.8 {
+                    Color::Yellow
+                } else {
+                    Color::Red
+                };
+                let cell = Span::styled(BAR_EIGHTHS[filled as usize - 1], Style::default().fg(color));
+
+                let y = (height - 1 - row) as usize;
+                for x in bar_x..(bar_x + BAR_WIDTH).min(width) {
+                    canvas[y][x as usize] = cell.clone();
+                }
             }
+        }
+    }
+
+    fn create_background(&self, width: u16, height: u16) -> Vec<Vec<Span<'static>>> {
+        let mut background = match image::open("img/jukebox.png") {
+            Ok(img) => pixel_blocks(&img, width, height, FilterType::Nearest),
             Err(_) => {
                 // Simple fallback
                 vec![vec![Span::styled("█", ratatui::style::Style::default().fg(ratatui::style::Color::DarkGray)); width as usize]; height as usize]
             }
+        };
+
+        if let Some(cover) = &self.cover {
+            draw_cover(&mut background, cover, width, height);
+        }
+        background
+    }
+}
+
+/// One "█" per pixel of the image resized to the area
+fn pixel_blocks(
+    img: &image::DynamicImage,
+    width: u16,
+    height: u16,
+    filter: FilterType,
+) -> Vec<Vec<Span<'static>>> {
+    let resized = img.resize_exact(width as u32, height as u32, filter);
+
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let pixel = resized.get_pixel(x as u32, y as u32).to_rgb();
+                    let [r, g, b] = pixel.0;
+                    Span::styled(
+                        "█",
+                        ratatui::style::Style::default().fg(ratatui::style::Color::Rgb(r, g, b)),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Overlay the album cover in the middle of the background, as a square
+fn draw_cover(
+    background: &mut [Vec<Span<'static>>],
+    cover: &image::DynamicImage,
+    width: u16,
+    height: u16,
+) {
+    // Cells are about twice as tall as wide: a square cover is twice as many columns as rows
+    let rows = (height * COVER_SIZE_PERCENT / 100).min(width / 2);
+    let columns = rows * 2;
+    if rows == 0 {
+        return;
+    }
+
+    let top = (height - rows) / 2;
+    let left = (width - columns) / 2;
+    // Photos shrink much better averaged than with nearest neighbour
+    let blocks = pixel_blocks(cover, columns, rows, FilterType::Triangle);
+    for (y, row) in blocks.into_iter().enumerate() {
+        let target = &mut background[top as usize + y];
+        for (x, cell) in row.into_iter().enumerate() {
+            target[left as usize + x] = cell;
         }
     }
 }
@@ -0,0 +1,441 @@
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+};
+use symphonia::core::{codecs::DecoderOptions, errors::Error as SymphoniaError};
+
+use crate::{jukebox_state::SongItem, metadata};
+
+/// Decoded audio may fall this short of the announced duration before the file counts as
+/// truncated, encoder delay and padding take a few dozen milliseconds
+const TRUNCATION_TOLERANCE_SECS: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// Not recognised as audio, or no decoder for its codec
+    Unreadable,
+    DecodeError,
+    /// Less audio than the file announces
+    Truncated,
+    /// No audio at all
+    Empty,
+    MissingDuration,
+    /// The content is of another format than the extension says
+    ExtensionMismatch,
+}
+
+impl ProblemKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProblemKind::Unreadable => "unreadable",
+            ProblemKind::DecodeError => "decode errors",
+            ProblemKind::Truncated => "truncated",
+            ProblemKind::Empty => "empty",
+            ProblemKind::MissingDuration => "no duration",
+            ProblemKind::ExtensionMismatch => "wrong extension",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(kind: ProblemKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+/// Result of checking one file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// Duration the file announces
+    pub duration_secs: Option<f64>,
+    /// Length of the audio actually decoded
+    pub decoded_secs: f64,
+    pub problems: Vec<Problem>,
+}
+
+/// Files of the songs to check, once each: CUE sheet tracks share theirs, streams have none
+pub fn files(songs: &[SongItem]) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    songs
+        .iter()
+        .filter(|song| !song.is_stream())
+        .map(|song| song.as_path().to_path_buf())
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
+/// Decode a whole file, reporting what keeps it from playing fully
+pub fn check_file(path: &Path) -> FileReport {
+    let mut report = FileReport {
+        path: path.to_path_buf(),
+        duration_secs: None,
+        decoded_secs: 0.0,
+        problems: Vec::new(),
+    };
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if let Some(container) = sniff(path)
+        && container.extension() != extension
+    {
+        report.problems.push(Problem::new(
+            ProblemKind::ExtensionMismatch,
+            format!("{} file named .{}", container.name(), extension),
+        ));
+    }
+
+    decode(path, &mut report);
+    report
+}
+
+fn decode(path: &Path, report: &mut FileReport) {
+    let Some(probed) = metadata::open_format(path) else {
+        report.problems.push(Problem::new(ProblemKind::Unreadable, "not a readable audio file"));
+        return;
+    };
+    let mut format = probed.format;
+    let Some(track) = format.default_track() else {
+        report.problems.push(Problem::new(ProblemKind::Unreadable, "no audio track"));
+        return;
+    };
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+
+    report.duration_secs = params.time_base.zip(params.n_frames).map(|(time_base, frames)| {
+        frames as f64 * time_base.numer as f64 / time_base.denom as f64
+    });
+    if report.duration_secs.is_none() {
+        report.problems.push(Problem::new(
+            ProblemKind::MissingDuration,
+            "the file does not announce its duration",
+        ));
+    }
+
+    let mut decoder =
+        match symphonia::default::get_codecs().make(&params, &DecoderOptions::default()) {
+            Ok(decoder) => decoder,
+            Err(error) => {
+                report.problems.push(Problem::new(
+                    ProblemKind::Unreadable,
+                    format!("no decoder for the codec: {}", error),
+                ));
+                return;
+            }
+        };
+
+    let mut frames = 0u64;
+    let mut sample_rate = params.sample_rate;
+    // Damaged packets are skipped, other errors end decoding before the end of the file
+    let mut errors = 0;
+    let mut first_error = None;
+    let mut stopped = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // The normal end of the stream
+            Err(SymphoniaError::IoError(error))
+                if error.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                break;
+            }
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(error) => {
+                stopped = Some(error.to_string());
+                break;
+            }
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(buffer) => {
+                frames += buffer.frames() as u64;
+                sample_rate = sample_rate.or(Some(buffer.spec().rate));
+            }
+            // A damaged packet, decoding goes on with the next one
+            Err(SymphoniaError::DecodeError(error)) => {
+                errors += 1;
+                first_error.get_or_insert_with(|| error.to_string());
+            }
+            Err(error) => {
+                stopped = Some(error.to_string());
+                break;
+            }
+        }
+    }
+    report.decoded_secs = sample_rate.map_or(0.0, |rate| frames as f64 / rate as f64);
+
+    if let Some(error) = first_error {
+        report.problems.push(Problem::new(
+            ProblemKind::DecodeError,
+            format!("{} packets failed: {}", errors, error),
+        ));
+    }
+    if let Some(error) = stopped {
+        report.problems.push(Problem::new(
+            ProblemKind::DecodeError,
+            format!("decoding stopped: {}", error),
+        ));
+    }
+    if frames == 0 {
+        report.problems.push(Problem::new(ProblemKind::Empty, "no audio could be decoded"));
+    } else if let Some(duration) = report.duration_secs
+        && report.decoded_secs + TRUNCATION_TOLERANCE_SECS < duration
+    {
+        report.problems.push(Problem::new(
+            ProblemKind::Truncated,
+            format!("{:.2}s of {:.2}s decoded", report.decoded_secs, duration),
+        ));
+    }
+}
+
+/// Container formats told apart by their first bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Container {
+    Mp3,
+    Flac,
+    Ogg,
+    Wav,
+    Mp4,
+}
+
+impl Container {
+    fn name(&self) -> &'static str {
+        match self {
+            Container::Mp3 => "MP3",
+            Container::Flac => "FLAC",
+            Container::Ogg => "Ogg",
+            Container::Wav => "WAV",
+            Container::Mp4 => "MP4",
+        }
+    }
+
+    /// Extension the library expects for the format
+    fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp3 => "mp3",
+            Container::Flac => "flac",
+            Container::Ogg => "ogg",
+            Container::Wav => "wav",
+            Container::Mp4 => "m4a",
+        }
+    }
+}
+
+/// Format of a file from its first bytes, past a leading ID3v2 tag. `None` when unknown
+fn sniff(path: &Path) -> Option<Container> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header).ok()?;
+
+    if header.starts_with(b"ID3") {
+        // Syncsafe size, plus the header and a footer when flagged
+        let size = header[6..10]
+            .iter()
+            .fold(0u64, |size, &byte| (size << 7) | (byte & 0x7f) as u64);
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        file.seek(SeekFrom::Start(10 + size + footer)).ok()?;
+        file.read_exact(&mut header).ok()?;
+    }
+
+    if header.starts_with(b"fLaC") {
+        Some(Container::Flac)
+    } else if header.starts_with(b"OggS") {
+        Some(Container::Ogg)
+    } else if header.starts_with(b"RIFF") && &header[8..12] == b"WAVE" {
+        Some(Container::Wav)
+    } else if &header[4..8] == b"ftyp" {
+        Some(Container::Mp4)
+    } else if header[0] == 0xff && header[1] & 0xe0 == 0xe0 {
+        Some(Container::Mp3)
+    } else {
+        None
+    }
+}
+
+/// Check of the library files running in the background
+pub struct LibraryCheck {
+    checked: Arc<AtomicUsize>,
+    total: usize,
+    result: Receiver<Vec<FileReport>>,
+}
+
+impl LibraryCheck {
+    pub fn start(files: Vec<PathBuf>) -> Self {
+        let checked = Arc::new(AtomicUsize::new(0));
+        let total = files.len();
+        let (sender, result) = mpsc::channel();
+
+        let progress = checked.clone();
+        thread::spawn(move || {
+            let reports = files
+                .iter()
+                .map(|path| {
+                    let report = check_file(path);
+                    progress.fetch_add(1, Ordering::Relaxed);
+                    report
+                })
+                .collect();
+            let _ = sender.send(reports);
+        });
+        Self {
+            checked,
+            total,
+            result,
+        }
+    }
+
+    /// Files checked so far, out of the total
+    pub fn progress(&self) -> (usize, usize) {
+        (self.checked.load(Ordering::Relaxed), self.total)
+    }
+
+    /// The reports once every file was checked
+    pub fn poll(&self) -> Option<Vec<FileReport>> {
+        self.result.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ScratchDir, flac, flac_without_length, wav};
+    use std::fs;
+
+    fn kinds(report: &FileReport) -> Vec<ProblemKind> {
+        report.problems.iter().map(|problem| problem.kind).collect()
+    }
+
+    #[test]
+    fn sniff_reads_past_id3_tags() {
+        let dir = ScratchDir::new("check-sniff");
+        let path = dir.join("song");
+
+        fs::write(&path, flac(&[])).unwrap();
+        assert_eq!(sniff(&path), Some(Container::Flac));
+
+        // ID3v2.4 tag of 20 bytes, the size split in 7 bit bytes
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        data.extend([0; 20]);
+        data.extend(wav(&[0; 16]));
+        fs::write(&path, data).unwrap();
+        assert_eq!(sniff(&path), Some(Container::Wav));
+
+        // Same tag with the footer flag: 10 more bytes before the audio
+        let mut data = b"ID3\x04\x00\x10\x00\x00\x00\x14".to_vec();
+        data.extend([0; 30]);
+        data.extend(b"OggS\x00\x02\x00\x00\x00\x00\x00\x00");
+        fs::write(&path, data).unwrap();
+        assert_eq!(sniff(&path), Some(Container::Ogg));
+
+        // Syncsafe sizes use 7 bits per byte: 0x01 0x00 is 128 bytes
+        let mut data = b"ID3\x03\x00\x00\x00\x00\x01\x00".to_vec();
+        data.extend([0; 128]);
+        data.extend(b"\xff\xfb\x90\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+        fs::write(&path, data).unwrap();
+        assert_eq!(sniff(&path), Some(Container::Mp3));
+
+        let mut data = vec![0, 0, 0, 0x18];
+        data.extend(b"ftypM4A \x00\x00\x00\x00");
+        fs::write(&path, data).unwrap();
+        assert_eq!(sniff(&path), Some(Container::Mp4));
+
+        fs::write(&path, b"plain text, not audio").unwrap();
+        assert_eq!(sniff(&path), None);
+        fs::write(&path, b"short").unwrap();
+        assert_eq!(sniff(&path), None);
+    }
+
+    #[test]
+    fn sound_files_have_no_problems() {
+        let dir = ScratchDir::new("check-sound");
+        let path = dir.join("song.wav");
+        fs::write(&path, wav(&[100; 44_100])).unwrap();
+
+        let report = check_file(&path);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+        assert_eq!(report.duration_secs, Some(1.0));
+        assert_eq!(report.decoded_secs, 1.0);
+
+        let path = dir.join("song.flac");
+        fs::write(&path, flac(&[])).unwrap();
+        assert!(check_file(&path).problems.is_empty());
+    }
+
+    #[test]
+    fn extension_mismatch() {
+        let dir = ScratchDir::new("check-extension");
+        let path = dir.join("song.mp3");
+        fs::write(&path, flac(&[])).unwrap();
+
+        let report = check_file(&path);
+        assert_eq!(kinds(&report), [ProblemKind::ExtensionMismatch]);
+        assert_eq!(report.problems[0].message, "FLAC file named .mp3");
+    }
+
+    #[test]
+    fn empty() {
+        let dir = ScratchDir::new("check-empty");
+        let path = dir.join("song.wav");
+        fs::write(&path, wav(&[])).unwrap();
+
+        assert!(kinds(&check_file(&path)).contains(&ProblemKind::Empty));
+    }
+
+    #[test]
+    fn truncated() {
+        let dir = ScratchDir::new("check-truncated");
+        let path = dir.join("song.wav");
+        // One second announced, half of it present
+        let mut data = wav(&[100; 44_100]);
+        data.truncate(data.len() - 44_100);
+        fs::write(&path, data).unwrap();
+
+        let report = check_file(&path);
+        assert_eq!(kinds(&report), [ProblemKind::Truncated]);
+        assert_eq!(report.duration_secs, Some(1.0));
+        assert_eq!(report.decoded_secs, 0.5);
+    }
+
+    #[test]
+    fn missing_duration() {
+        let dir = ScratchDir::new("check-duration");
+        let path = dir.join("song.flac");
+        fs::write(&path, flac_without_length()).unwrap();
+
+        let report = check_file(&path);
+        assert_eq!(kinds(&report), [ProblemKind::MissingDuration]);
+        assert!(report.decoded_secs > 0.0);
+    }
+
+    #[test]
+    fn unreadable() {
+        let dir = ScratchDir::new("check-unreadable");
+        let path = dir.join("song.mp3");
+        fs::write(&path, b"plain text, not audio").unwrap();
+
+        assert_eq!(kinds(&check_file(&path)), [ProblemKind::Unreadable]);
+    }
+}
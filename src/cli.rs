@@ -0,0 +1,295 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    path::Path,
+    sync::atomic::AtomicUsize,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    bookmarks::Bookmarks,
+    check,
+    duplicates::{self, HashCache},
+    jukebox_state::SongItem,
+    library,
+    library_index::LibraryIndex,
+    listen_log::{ListenLog, Period, Report, format_hours},
+    metadata::TrackTags,
+    organizer,
+    path_pattern::{self, PathPattern},
+    playlist_file,
+    query::Query,
+    saved_playlists::SavedPlaylists,
+};
+
+/// Directory used when no sources are given, as in the jukebox itself
+const DEFAULT_SOURCE: &str = "example_music";
+
+/// The sources given, or the default one
+fn sources_or_default(sources: &[String]) -> Vec<String> {
+    if sources.is_empty() {
+        vec![DEFAULT_SOURCE.to_string()]
+    } else {
+        sources.to_vec()
+    }
+}
+
+/// Read the sources through the library index, as the jukebox does on startup
+fn load_library(sources: &[String], patterns: &[PathPattern], recursive: bool) -> Vec<SongItem> {
+    let mut index = LibraryIndex::load();
+    let songs =
+        library::load_sources(&sources_or_default(sources), &mut index, patterns, recursive);
+    index.save();
+    songs
+}
+
+/// Print the files of the songs matching `query`, or save them as a playlist to `output`
+pub fn query(
+    query: &str,
+    sources: &[String],
+    patterns: &[PathPattern],
+    recursive: bool,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let query = Query::parse(query)?;
+    let songs: Vec<SongItem> = load_library(sources, patterns, recursive)
+        .into_iter()
+        .filter(|song| query.matches(song))
+        .collect();
+
+    match output {
+        Some(path) => {
+            let entries = playlist_file::entries_for(&songs);
+            playlist_file::save(path, &entries)?;
+            eprintln!("Saved {} songs to {}", entries.len(), path.display());
+            if entries.len() < songs.len() {
+                eprintln!("CUE tracks were saved as their whole file");
+            }
+        }
+        None => {
+            for song in &songs {
+                println!("{}", song.url().map_or(song.as_path().display().to_string(), str::to_string));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the tags the patterns guess from the path of each file, and those the file already has
+pub fn patterns(
+    sources: &[String],
+    patterns: &[PathPattern],
+    recursive: bool,
+) -> Result<(), Box<dyn Error>> {
+    if patterns.is_empty() {
+        return Err("no pattern given, add --pattern \"{artist}/{album}/{track} - {title}\"".into());
+    }
+
+    // Tags read from the files alone, to tell them apart from the guessed ones
+    let songs = load_library(sources, &[], recursive);
+    let roots = organizer::roots(&sources_or_default(sources));
+    let mut seen = HashSet::new();
+    let mut matched = 0;
+    for song in songs.iter().filter(|song| !song.is_stream()) {
+        // CUE tracks share their file
+        if !seen.insert(song.as_path()) {
+            continue;
+        }
+        println!("{}", song.as_path().display());
+        let root = library::pattern_root(song.as_path(), &roots);
+        let Some((pattern, guessed)) = path_pattern::infer(patterns, song.as_path(), &root) else {
+            println!("    no pattern matches");
+            continue;
+        };
+
+        matched += 1;
+        println!("    {}", pattern.as_str());
+        let fields = tag_fields(&guessed).into_iter().zip(tag_fields(song.tags()));
+        for ((name, guessed), (_, tagged)) in fields {
+            match (guessed, tagged) {
+                (Some(guessed), Some(tagged)) => {
+                    println!("    {:<12} {} (file tag kept: {})", name, guessed, tagged)
+                }
+                (Some(guessed), None) => println!("    {:<12} {}", name, guessed),
+                (None, _) => {}
+            }
+        }
+    }
+    eprintln!("{} of {} files match a pattern", matched, seen.len());
+    Ok(())
+}
+
+fn tag_fields(tags: &TrackTags) -> [(&'static str, Option<String>); 7] {
+    [
+        ("artist", tags.artist.clone()),
+        ("albumartist", tags.album_artist.clone()),
+        ("album", tags.album.clone()),
+        ("year", tags.year.map(|year| year.to_string())),
+        ("track", tags.track_number.map(|track| track.to_string())),
+        ("title", tags.title.clone()),
+        ("genre", tags.genre.clone()),
+    ]
+}
+
+/// Move the files of the music directories to the paths `pattern` makes from their tags,
+/// or only print the moves with `dry_run`
+pub fn organize(
+    sources: &[String],
+    pattern: &PathPattern,
+    tags_from: &[PathPattern],
+    dry_run: bool,
+) -> Result<(), Box<dyn Error>> {
+    if !pattern.can_name_files() {
+        return Err("{*} only skips text when reading paths, it cannot name files".into());
+    }
+    let roots = organizer::roots(sources);
+    if roots.is_empty() {
+        return Err("no music directory to organize".into());
+    }
+
+    // The files are laid out in folders, those of earlier runs included
+    let plan = organizer::plan(&load_library(sources, tags_from, true), &roots, pattern);
+    for (path, reason) in &plan.skipped {
+        eprintln!("Skipped {}: {}", path.display(), reason);
+    }
+    if dry_run {
+        for file in &plan.moves {
+            println!("{} -> {}", file.from.display(), file.to.display());
+        }
+        eprintln!(
+            "{} files would be moved, {} are in place, {} skipped",
+            plan.moves.len(),
+            plan.in_place,
+            plan.skipped.len()
+        );
+        return Ok(());
+    }
+
+    let (moved, failed) = organizer::apply(&plan);
+    for file in &moved {
+        println!("{} -> {}", file.from.display(), file.to.display());
+    }
+    for (file, error) in &failed {
+        eprintln!("Failed to move {}: {}", file.from.display(), error);
+    }
+
+    let renamed = organizer::renamed_paths(&moved);
+    SavedPlaylists::load().rename_paths(&renamed);
+    Bookmarks::load().rename_paths(&renamed);
+    let mut index = LibraryIndex::load();
+    for file in &moved {
+        index.rename(&file.from, &file.to);
+    }
+    index.save();
+
+    eprintln!(
+        "{} files moved, {} are in place, {} skipped",
+        moved.len(),
+        plan.in_place,
+        plan.skipped.len()
+    );
+    if !failed.is_empty() {
+        return Err(format!("{} files could not be moved", failed.len()).into());
+    }
+    Ok(())
+}
+
+/// Print the groups of files holding the same track, the copy suggested to keep marked with `*`
+pub fn duplicates(
+    sources: &[String],
+    patterns: &[PathPattern],
+    recursive: bool,
+) -> Result<(), Box<dyn Error>> {
+    let songs = load_library(sources, patterns, recursive);
+    let groups = duplicates::find(&songs, &mut HashCache::load(), &AtomicUsize::new(0));
+    for group in &groups {
+        println!("{}", group.reason.label());
+        for (i, copy) in group.copies.iter().enumerate() {
+            let duration = copy
+                .song
+                .duration()
+                .map(|d| format!("{}:{:02}", d.as_secs() / 60, d.as_secs() % 60))
+                .unwrap_or_default();
+            println!(
+                "  {} {}  ({} {} {})",
+                if i == 0 { "*" } else { " " },
+                copy.song.as_path().display(),
+                duration,
+                copy.song.codec().unwrap_or("?"),
+                duplicates::format_size(copy.size)
+            );
+        }
+    }
+    let extra: usize = groups.iter().map(|group| group.copies.len() - 1).sum();
+    eprintln!("{} duplicated tracks, {} extra copies", groups.len(), extra);
+    Ok(())
+}
+
+/// Decode every file of the sources, printing the problems found as text or every
+/// report as JSON. Fails when some file has problems, so scripts can rely on the exit code
+pub fn check(sources: &[String], recursive: bool, json: bool) -> Result<(), Box<dyn Error>> {
+    let files = check::files(&load_library(sources, &[], recursive));
+    let mut reports = Vec::new();
+    for path in &files {
+        let report = check::check_file(path);
+        if !json && !report.problems.is_empty() {
+            println!("{}", report.path.display());
+            for problem in &report.problems {
+                println!("  {}: {}", problem.kind.label(), problem.message);
+            }
+        }
+        reports.push(report);
+    }
+
+    let broken = reports.iter().filter(|report| !report.problems.is_empty()).count();
+    if json {
+        let output = serde_json::json!({
+            "checked": reports.len(),
+            "broken": broken,
+            "files": reports,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        eprintln!("{} files checked, {} with problems", reports.len(), broken);
+    }
+    if broken > 0 {
+        return Err(format!("{} of {} files have problems", broken, reports.len()).into());
+    }
+    Ok(())
+}
+
+/// Print listening statistics from the listen log, as text or as JSON
+pub fn stats(period: Period, json: bool) -> Result<(), Box<dyn Error>> {
+    let listens = ListenLog::open().load();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let report = Report::build(&listens, period, now);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "{}: {} listens, {}",
+        report.period,
+        report.listens,
+        format_hours(report.listened_secs)
+    );
+    for (title, ranked) in [
+        ("Top tracks", &report.top_tracks),
+        ("Top artists", &report.top_artists),
+        ("Top albums", &report.top_albums),
+    ] {
+        println!("\n{}", title);
+        for (i, entry) in ranked.iter().enumerate() {
+            println!(
+                "{:>3}. {} ({} listens, {})",
+                i + 1,
+                entry.name,
+                entry.listens,
+                format_hours(entry.listened_secs)
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use std::time::Duration;
+
+use crate::{path_pattern::PathPattern, scrobbler::ScrobbleConfig};
+
+/// Runtime settings derived from the command line
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Tracks longer than this remember their last position
+    pub bookmark_threshold: Duration,
+    /// Set when listens are submitted to ListenBrainz
+    pub scrobble: Option<ScrobbleConfig>,
+    /// Guess the tags missing from files from their path, the first fitting pattern wins
+    pub patterns: Vec<PathPattern>,
+    /// Read the subdirectories of the music directories too
+    pub recursive: bool,
+    /// Layout the music directories are organized in, editable before moving anything
+    pub organize_pattern: PathPattern,
+}
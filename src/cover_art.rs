@@ -0,0 +1,192 @@
+use image::DynamicImage;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+use symphonia::core::meta::{MetadataRevision, StandardVisualKey, Visual};
+
+use crate::{jukebox_state::SongItem, metadata};
+
+/// Image files looked for next to the audio file, in order of preference
+const FOLDER_IMAGES: [&str; 6] = [
+    "cover.jpg",
+    "cover.png",
+    "folder.jpg",
+    "folder.png",
+    "front.jpg",
+    "front.png",
+];
+
+/// Covers are kept at most this many pixels a side. They are drawn one pixel per
+/// block, and no terminal gives the cover more blocks than that
+const MAX_SIDE: u32 = 256;
+/// Covers kept in memory, those used least recently are dropped first
+const MAX_ENTRIES: usize = 32;
+
+pub type CoverArt = Arc<DynamicImage>;
+
+enum Entry {
+    Loading,
+    Loaded(Option<CoverArt>),
+}
+
+#[derive(Default)]
+struct Cache {
+    /// Entries with the time they were last asked for
+    entries: HashMap<PathBuf, (Entry, u64)>,
+    clock: u64,
+}
+
+impl Cache {
+    fn insert(&mut self, path: PathBuf, entry: Entry) {
+        self.clock += 1;
+        self.entries.insert(path, (entry, self.clock));
+        while self.entries.len() > MAX_ENTRIES {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+/// Album art loaded in the background and kept in memory, by file, shrunk to the size
+/// it is drawn at
+#[derive(Default)]
+pub struct CoverArts {
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl CoverArts {
+    /// Cover of a song if loaded, starting to load it on the first request
+    pub fn get(&self, song: &SongItem) -> Option<CoverArt> {
+        if song.is_stream() {
+            return None;
+        }
+
+        let path = song.as_path().to_path_buf();
+        let mut cache = self.cache.lock().ok()?;
+        cache.clock += 1;
+        let now = cache.clock;
+        match cache.entries.get_mut(&path) {
+            Some((entry, used)) => {
+                *used = now;
+                return match entry {
+                    Entry::Loaded(cover) => cover.clone(),
+                    Entry::Loading => None,
+                };
+            }
+            None => cache.insert(path.clone(), Entry::Loading),
+        }
+
+        let shared = self.cache.clone();
+        thread::spawn(move || {
+            let cover = load(&path).map(|cover| Arc::new(shrink(cover)));
+            if let Ok(mut cache) = shared.lock()
+                && let Some((entry, _)) = cache.entries.get_mut(&path)
+            {
+                // Unless dropped from the cache in the meantime
+                *entry = Entry::Loaded(cover);
+            }
+        });
+        None
+    }
+}
+
+/// Embedded picture of the file, or an image from its folder
+fn load(path: &Path) -> Option<DynamicImage> {
+    embedded_picture(path)
+        .and_then(|data| image::load_from_memory(&data).ok())
+        .or_else(|| folder_image(path))
+}
+
+/// Cover no larger than `MAX_SIDE`, without the alpha channel the blocks do not draw
+fn shrink(cover: DynamicImage) -> DynamicImage {
+    let cover = if cover.width() > MAX_SIDE || cover.height() > MAX_SIDE {
+        cover.thumbnail(MAX_SIDE, MAX_SIDE)
+    } else {
+        cover
+    };
+    DynamicImage::ImageRgb8(cover.to_rgb8())
+}
+
+/// APIC frame, FLAC picture block or MP4 `covr` atom, front cover preferred
+fn embedded_picture(path: &Path) -> Option<Box<[u8]>> {
+    let mut probed = metadata::open_format(path)?;
+
+    let mut visuals: Vec<Visual> = Vec::new();
+    let mut collect = |revision: &MetadataRevision| visuals.extend_from_slice(revision.visuals());
+    if let Some(revision) = probed.format.metadata().current() {
+        collect(revision);
+    }
+    if let Some(metadata) = probed.metadata.get()
+        && let Some(revision) = metadata.current()
+    {
+        collect(revision);
+    }
+
+    let front = visuals
+        .iter()
+        .position(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+        .unwrap_or(0);
+    visuals.into_iter().nth(front).map(|visual| visual.data)
+}
+
+/// `cover.jpg`, `folder.png`... next to the file, whatever their case
+fn folder_image(path: &Path) -> Option<DynamicImage> {
+    let names: Vec<PathBuf> = fs::read_dir(path.parent()?)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+
+    FOLDER_IMAGES.iter().find_map(|wanted| {
+        names
+            .iter()
+            .find(|name| {
+                name.file_name()
+                    .is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(wanted))
+            })
+            .and_then(|name| image::open(name).ok())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_covers_are_dropped() {
+        let mut cache = Cache::default();
+        for i in 0..MAX_ENTRIES {
+            cache.insert(PathBuf::from(i.to_string()), Entry::Loaded(None));
+        }
+        // Asked for again, the first one is now the most recent
+        cache.clock += 1;
+        cache.entries.get_mut(Path::new("0")).unwrap().1 = cache.clock;
+
+        cache.insert(PathBuf::from("new"), Entry::Loading);
+        assert_eq!(cache.entries.len(), MAX_ENTRIES);
+        assert!(cache.entries.contains_key(Path::new("0")));
+        assert!(!cache.entries.contains_key(Path::new("1")));
+        assert!(cache.entries.contains_key(Path::new("new")));
+    }
+
+    #[test]
+    fn large_covers_are_shrunk() {
+        let cover = shrink(DynamicImage::new_rgba8(1200, 600));
+        assert_eq!((cover.width(), cover.height()), (MAX_SIDE, MAX_SIDE / 2));
+        assert!(matches!(cover, DynamicImage::ImageRgb8(_)));
+
+        let cover = shrink(DynamicImage::new_rgb8(100, 80));
+        assert_eq!((cover.width(), cover.height()), (100, 80));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// CUE sheets count time in frames of 1/75 of a second
+const FRAMES_PER_SECOND: u64 = 75;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub start: Duration,
+}
+
+/// Audio file referenced by a `FILE` command, with the tracks it contains
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueFile {
+    pub path: PathBuf,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub files: Vec<CueFile>,
+}
+
+impl CueSheet {
+    /// Read and parse a `.cue` file, resolving audio paths relative to its folder
+    pub fn load(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        let content = String::from_utf8_lossy(&bytes);
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        Some(Self::parse(&content, base_dir))
+    }
+
+    pub fn parse(content: &str, base_dir: &Path) -> Self {
+        let mut sheet = CueSheet::default();
+
+        for line in content.trim_start_matches('\u{feff}').lines() {
+            let line = line.trim();
+            let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            // Commands before the first TRACK belong to the whole album
+            let current_track = sheet
+                .files
+                .last_mut()
+                .and_then(|file| file.tracks.last_mut());
+
+            match command.to_ascii_uppercase().as_str() {
+                "TITLE" => {
+                    let title = Some(unquote(rest));
+                    match current_track {
+                        Some(track) => track.title = title,
+                        None => sheet.title = title,
+                    }
+                }
+                "PERFORMER" => {
+                    let performer = Some(unquote(rest));
+                    match current_track {
+                        Some(track) => track.performer = performer,
+                        None => sheet.performer = performer,
+                    }
+                }
+                "FILE" => {
+                    sheet.files.push(CueFile {
+                        path: base_dir.join(unquote(rest)),
+                        tracks: Vec::new(),
+                    });
+                }
+                "TRACK" => {
+                    let number = rest
+                        .split_whitespace()
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(0);
+                    if let Some(file) = sheet.files.last_mut() {
+                        file.tracks.push(CueTrack {
+                            number,
+                            title: None,
+                            performer: None,
+                            start: Duration::ZERO,
+                        });
+                    }
+                }
+                "INDEX" => {
+                    let mut parts = rest.split_whitespace();
+                    let number = parts.next().and_then(|n| n.parse::<u32>().ok());
+                    let time = parts.next().and_then(parse_timestamp);
+
+                    // INDEX 00 (pregap) always precedes INDEX 01, which wins when present
+                    if let (Some(track), Some(0 | 1), Some(time)) = (current_track, number, time) {
+                        track.start = time;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        sheet
+    }
+}
+
+/// Remove surrounding quotes from a CUE argument, dropping a trailing file type if unquoted
+fn unquote(value: &str) -> String {
+    if let Some(stripped) = value.strip_prefix('"') {
+        stripped
+            .split_once('"')
+            .map_or(stripped, |(inner, _)| inner)
+            .to_string()
+    } else {
+        value.split_whitespace().next().unwrap_or("").to_string()
+    }
+}
+
+/// Parse a `mm:ss:ff` timestamp
+fn parse_timestamp(value: &str) -> Option<Duration> {
+    let mut parts = value.split(':').map(|p| p.parse::<u64>().ok());
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    let frames = parts.next()??;
+
+    let millis = (minutes * 60 + seconds) * 1000 + frames * 1000 / FRAMES_PER_SECOND;
+    Some(Duration::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(number: u32, title: &str, performer: Option<&str>, millis: u64) -> CueTrack {
+        CueTrack {
+            number,
+            title: Some(title.to_string()),
+            performer: performer.map(str::to_string),
+            start: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn parses_album_and_tracks() {
+        let content = "\u{feff}REM GENRE Rock\r\n\
+            PERFORMER \"The Band\"\r\n\
+            TITLE \"Live Album\"\r\n\
+            FILE \"Live Album.wav\" WAVE\r\n  \
+              TRACK 01 AUDIO\r\n    \
+                TITLE \"Intro\"\r\n    \
+                INDEX 01 00:00:00\r\n  \
+              TRACK 02 AUDIO\r\n    \
+                TITLE \"Song\"\r\n    \
+                PERFORMER \"Guest\"\r\n    \
+                INDEX 00 03:58:50\r\n    \
+                INDEX 01 04:00:15\r\n";
+        let sheet = CueSheet::parse(content, Path::new("music/album"));
+
+        assert_eq!(sheet.title.as_deref(), Some("Live Album"));
+        assert_eq!(sheet.performer.as_deref(), Some("The Band"));
+        assert_eq!(
+            sheet.files,
+            vec![CueFile {
+                path: PathBuf::from("music/album/Live Album.wav"),
+                tracks: vec![
+                    track(1, "Intro", None, 0),
+                    track(2, "Song", Some("Guest"), 240_200),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn pregap_is_the_start_without_index_01() {
+        let sheet = CueSheet::parse(
+            "FILE a.flac WAVE\nTRACK 1 AUDIO\nINDEX 00 00:01:00\n",
+            Path::new(""),
+        );
+        assert_eq!(sheet.files[0].path, PathBuf::from("a.flac"));
+        assert_eq!(sheet.files[0].tracks[0].start, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn tracks_belong_to_the_last_file() {
+        let sheet = CueSheet::parse(
+            "file \"one.flac\" WAVE\n\
+             track 01 audio\n\
+             index 01 00:00:00\n\
+             FILE \"two.flac\" WAVE\n\
+             TRACK 02 AUDIO\n\
+             TITLE \"Second\"\n\
+             INDEX 01 00:00:74\n",
+            Path::new("/cd"),
+        );
+        assert_eq!(sheet.files.len(), 2);
+        assert_eq!(sheet.files[0].tracks.len(), 1);
+        assert_eq!(sheet.files[1].path, PathBuf::from("/cd/two.flac"));
+        assert_eq!(sheet.files[1].tracks, vec![track(2, "Second", None, 986)]);
+    }
+
+    #[test]
+    fn ignores_tracks_before_any_file_and_bad_timestamps() {
+        let sheet = CueSheet::parse(
+            "TRACK 01 AUDIO\nTITLE \"Album\"\nFILE x.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 1:xx:00\n",
+            Path::new(""),
+        );
+        assert_eq!(sheet.title.as_deref(), Some("Album"));
+        assert_eq!(sheet.files[0].tracks[0].start, Duration::ZERO);
+    }
+
+    #[test]
+    fn unquotes_arguments() {
+        assert_eq!(unquote("\"A \"B\" C\""), "A ");
+        assert_eq!(unquote("\"Quoted name.wav\" WAVE"), "Quoted name.wav");
+        assert_eq!(unquote("plain.wav WAVE"), "plain.wav");
+        assert_eq!(unquote(""), "");
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_timestamp("01:02:75"), Some(Duration::from_millis(63_000)));
+        assert_eq!(parse_timestamp("100:00:00"), Some(Duration::from_secs(6000)));
+        assert_eq!(parse_timestamp("01:02"), None);
+        assert_eq!(parse_timestamp("aa:00:00"), None);
+    }
+}
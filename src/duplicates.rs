@@ -0,0 +1,554 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    jukebox_state::SongItem,
+    organizer::{self, Move, Plan},
+    storage, track_id,
+};
+
+const HASHES_FILE: &str = "audio_hashes.json";
+/// Folder of each music directory the duplicates are moved to, hidden so it is not scanned
+pub const QUARANTINE_DIR: &str = ".quarantine";
+/// Songs with the same artist and title are the same recording when their lengths differ less
+const DURATION_TOLERANCE: Duration = Duration::from_secs(3);
+/// Words in brackets telling another recording or cut of a song apart from the original
+const QUALIFIERS: [&str; 9] = [
+    "live",
+    "remix",
+    "demo",
+    "edit",
+    "acoustic",
+    "instrumental",
+    "unplugged",
+    "karaoke",
+    "rehearsal",
+];
+
+/// Hash of the encoded audio of a file, without its tags: copies differing only
+/// by their tags or by the layout of their container hash the same
+pub fn audio_hash(path: &Path) -> Option<String> {
+    track_id::hash_packets(path, usize::MAX)
+}
+
+/// Audio hash of a file, as long as its size and modification time stay the same
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedHash {
+    size: u64,
+    mtime_ms: u64,
+    hash: String,
+}
+
+/// Audio hashes of the files seen by earlier searches, keyed by path
+pub struct HashCache {
+    file: Option<PathBuf>,
+    entries: HashMap<PathBuf, CachedHash>,
+}
+
+impl HashCache {
+    pub fn load() -> Self {
+        let file = storage::cache_file(HASHES_FILE);
+        let entries = file.as_deref().map(storage::load_json).unwrap_or_default();
+        Self { file, entries }
+    }
+
+    /// Audio hash of the file at `path`, hashed again only if the file changed
+    fn hash(&mut self, path: &Path) -> Option<String> {
+        let metadata = fs::metadata(path).ok()?;
+        let size = metadata.len();
+        let mtime_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_millis() as u64);
+
+        if let Some(cached) = self.entries.get(path)
+            && cached.size == size
+            && cached.mtime_ms == mtime_ms
+        {
+            return Some(cached.hash.clone());
+        }
+        let hash = audio_hash(path)?;
+        let cached = CachedHash {
+            size,
+            mtime_ms,
+            hash: hash.clone(),
+        };
+        self.entries.insert(path.to_path_buf(), cached);
+        Some(hash)
+    }
+
+    /// Write the hashes of the files in `paths`, forgetting the others
+    fn save(&mut self, paths: &HashSet<&Path>) {
+        self.entries.retain(|path, _| paths.contains(path.as_path()));
+        if let Some(file) = &self.file {
+            let _ = storage::save_json(file, &self.entries);
+        }
+    }
+}
+
+/// File size in megabytes, as shown next to each copy
+pub fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// The same audio, tagged or packaged differently
+    SameAudio,
+    /// The same artist and title with about the same length, maybe another encoding
+    SimilarTags,
+}
+
+impl Reason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Reason::SameAudio => "Identical audio",
+            Reason::SimilarTags => "Same artist, title and length",
+        }
+    }
+}
+
+/// Copy of a track and the size of its file
+#[derive(Debug, Clone)]
+pub struct Duplicate {
+    pub song: SongItem,
+    pub size: u64,
+}
+
+/// Copies of the same track, the one suggested to keep first
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub reason: Reason,
+    pub copies: Vec<Duplicate>,
+}
+
+/// Group the files of the library holding the same track.
+///
+/// Files are grouped when their audio hashes the same, or when their artist and
+/// title match once lowercased and stripped of bracketed text other than version
+/// qualifiers, and their durations are close. Hashes are kept in `cache` for the
+/// files that do not change, `hashed` counts the files done.
+pub fn find(songs: &[SongItem], cache: &mut HashCache, hashed: &AtomicUsize) -> Vec<Group> {
+    // CUE sheet tracks share their file, streams have none
+    let mut seen = HashSet::new();
+    let songs: Vec<&SongItem> = songs
+        .iter()
+        .filter(|song| !song.is_stream() && !song.is_segment())
+        .filter(|song| seen.insert(song.as_path()))
+        .collect();
+
+    let hashes: Vec<Option<String>> = songs
+        .iter()
+        .map(|song| {
+            let hash = cache.hash(song.as_path());
+            hashed.fetch_add(1, Ordering::Relaxed);
+            hash
+        })
+        .collect();
+    cache.save(&seen);
+
+    let mut sets = DisjointSets::new(songs.len());
+    let mut by_hash: HashMap<&str, usize> = HashMap::new();
+    for (i, hash) in hashes.iter().enumerate() {
+        if let Some(hash) = hash {
+            let first = *by_hash.entry(hash).or_insert(i);
+            sets.union(first, i);
+        }
+    }
+    let mut by_tags: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (i, song) in songs.iter().enumerate() {
+        if let Some(key) = tag_key(song) {
+            by_tags.entry(key).or_default().push(i);
+        }
+    }
+    for indices in by_tags.values() {
+        let durations = indices
+            .iter()
+            .filter_map(|&i| Some((i, songs[i].duration()?)))
+            .collect();
+        for cluster in duration_clusters(durations) {
+            for &i in &cluster[1..] {
+                sets.union(cluster[0], i);
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..songs.len() {
+        members.entry(sets.find(i)).or_default().push(i);
+    }
+    let mut groups: Vec<Group> = members
+        .into_values()
+        .filter(|indices| indices.len() > 1)
+        .map(|indices| {
+            let first_hash = &hashes[indices[0]];
+            let same_audio =
+                first_hash.is_some() && indices.iter().all(|&i| hashes[i] == *first_hash);
+            let reason = if same_audio { Reason::SameAudio } else { Reason::SimilarTags };
+            let mut copies: Vec<Duplicate> = indices
+                .iter()
+                .map(|&i| Duplicate {
+                    song: songs[i].clone(),
+                    size: fs::metadata(songs[i].as_path()).map_or(0, |m| m.len()),
+                })
+                .collect();
+            // The best tagged copy, then the largest one, then the shortest path
+            copies.sort_by_key(|copy| {
+                (
+                    Reverse(tag_count(&copy.song)),
+                    Reverse(copy.size),
+                    copy.song.as_path().as_os_str().len(),
+                    copy.song.as_path().to_path_buf(),
+                )
+            });
+            Group { reason, copies }
+        })
+        .collect();
+    groups.sort_by(|a, b| a.copies[0].song.as_path().cmp(b.copies[0].song.as_path()));
+    groups
+}
+
+/// Indices of the songs with durations, grouped with the shortest song of their group
+/// when they last at most `DURATION_TOLERANCE` longer. Each song is compared with the
+/// first of its group, so a chain of small differences does not add up into one group.
+fn duration_clusters(mut durations: Vec<(usize, Duration)>) -> Vec<Vec<usize>> {
+    durations.sort_by_key(|&(i, duration)| (duration, i));
+    let mut clusters: Vec<(Duration, Vec<usize>)> = Vec::new();
+    for (i, duration) in durations {
+        match clusters.last_mut() {
+            Some((first, cluster)) if duration - *first <= DURATION_TOLERANCE => cluster.push(i),
+            _ => clusters.push((duration, vec![i])),
+        }
+    }
+    clusters.into_iter().map(|(_, cluster)| cluster).collect()
+}
+
+/// Artist and title reduced to lowercase words
+fn tag_key(song: &SongItem) -> Option<(String, String)> {
+    let artist = normalize(song.tags().artist.as_deref()?);
+    let title = normalize(song.tags().title.as_deref()?);
+    (!artist.is_empty() && !title.is_empty()).then_some((artist, title))
+}
+
+/// Lowercase words of `text`. Text in brackets is left out ("(2011 Remaster)",
+/// "[Bonus Track]") unless it names another version of the song ("(Live)", "[Demo]")
+fn normalize(text: &str) -> String {
+    let mut depth = 0usize;
+    let mut words = String::new();
+    let mut bracketed = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | '[' => {
+                depth += 1;
+                bracketed.push(' ');
+            }
+            ')' | ']' if depth > 0 => {
+                depth -= 1;
+                bracketed.push(' ');
+                if depth == 0 {
+                    if bracketed.split_whitespace().any(|word| QUALIFIERS.contains(&word)) {
+                        words.push_str(&bracketed);
+                    }
+                    bracketed.clear();
+                }
+            }
+            c if c.is_alphanumeric() => {
+                let target = if depth > 0 { &mut bracketed } else { &mut words };
+                target.extend(c.to_lowercase());
+            }
+            _ if depth > 0 => bracketed.push(' '),
+            _ => words.push(' '),
+        }
+    }
+    words.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn tag_count(song: &SongItem) -> usize {
+    let tags = song.tags();
+    [
+        tags.title.is_some(),
+        tags.artist.is_some(),
+        tags.album.is_some(),
+        tags.album_artist.is_some(),
+        tags.track_number.is_some(),
+        tags.year.is_some(),
+        tags.genre.is_some(),
+    ]
+    .iter()
+    .filter(|&&set| set)
+    .count()
+}
+
+/// Union-find over song indices
+struct DisjointSets {
+    parents: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let parent = self.parents[i];
+        if parent == i {
+            return i;
+        }
+        let root = self.find(parent);
+        self.parents[i] = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[b] = a;
+    }
+}
+
+/// Duplicate search running in the background, hashing every file of the library
+pub struct DuplicateScan {
+    hashed: Arc<AtomicUsize>,
+    total: usize,
+    result: Receiver<Vec<Group>>,
+}
+
+impl DuplicateScan {
+    pub fn start(songs: Vec<SongItem>) -> Self {
+        let hashed = Arc::new(AtomicUsize::new(0));
+        let total = songs
+            .iter()
+            .filter(|song| !song.is_stream() && !song.is_segment())
+            .map(SongItem::as_path)
+            .collect::<HashSet<_>>()
+            .len();
+        let (sender, result) = mpsc::channel();
+
+        let progress = hashed.clone();
+        thread::spawn(move || {
+            let _ = sender.send(find(&songs, &mut HashCache::load(), &progress));
+        });
+        Self {
+            hashed,
+            total,
+            result,
+        }
+    }
+
+    /// Files hashed so far, out of the total
+    pub fn progress(&self) -> (usize, usize) {
+        (self.hashed.load(Ordering::Relaxed), self.total)
+    }
+
+    /// The groups once the search is over
+    pub fn poll(&self) -> Option<Vec<Group>> {
+        self.result.try_recv().ok()
+    }
+}
+
+/// Moves of files to the quarantine folder of their music directory, under the
+/// path they had in it. Files outside of the music directories are skipped.
+pub fn quarantine_plan(files: &[PathBuf], roots: &[PathBuf]) -> Plan {
+    let mut plan = Plan::default();
+    let mut taken = HashSet::new();
+    for from in files {
+        let Some((root, relative)) = roots
+            .iter()
+            .filter_map(|root| Some((root, from.strip_prefix(root).ok()?)))
+            .max_by_key(|(root, _)| root.components().count())
+        else {
+            plan.skipped.push((from.clone(), "not in a music directory".to_string()));
+            continue;
+        };
+
+        let target = root.join(QUARANTINE_DIR).join(relative);
+        let to = organizer::free_path(&target, from, &taken);
+        taken.insert(to.clone());
+        plan.moves.push(Move {
+            from: from.clone(),
+            to,
+            root: root.clone(),
+        });
+    }
+    plan
+}
+
+/// Groups being reviewed, with the copy to keep in each
+pub struct Review {
+    groups: Vec<Group>,
+    keep: Vec<usize>,
+    /// Group and copy under the cursor
+    selected: (usize, usize),
+}
+
+impl Review {
+    pub fn new(groups: Vec<Group>) -> Self {
+        Self {
+            keep: vec![0; groups.len()],
+            groups,
+            selected: (0, 0),
+        }
+    }
+
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    pub fn keep(&self, group: usize) -> usize {
+        self.keep[group]
+    }
+
+    pub fn selected(&self) -> (usize, usize) {
+        self.selected
+    }
+
+    /// Move the cursor to the next (or previous) copy, across groups and wrapping around
+    pub fn move_selection(&mut self, direction: i32) {
+        let rows: Vec<(usize, usize)> = self
+            .groups
+            .iter()
+            .enumerate()
+            .flat_map(|(g, group)| (0..group.copies.len()).map(move |c| (g, c)))
+            .collect();
+        if let Some(current) = rows.iter().position(|&row| row == self.selected) {
+            let next = (current as i32 + direction).rem_euclid(rows.len() as i32) as usize;
+            self.selected = rows[next];
+        }
+    }
+
+    /// Keep the copy under the cursor instead of the suggested one
+    pub fn keep_selected(&mut self) {
+        let (group, copy) = self.selected;
+        if let Some(keep) = self.keep.get_mut(group) {
+            *keep = copy;
+        }
+    }
+
+    /// Copy to keep and the other copies of the selected group
+    pub fn selected_group(&self) -> Option<(SongItem, Vec<SongItem>)> {
+        let group = self.groups.get(self.selected.0)?;
+        let keep = self.keep[self.selected.0];
+        let others = group
+            .copies
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != keep)
+            .map(|(_, copy)| copy.song.clone())
+            .collect();
+        Some((group.copies[keep].song.clone(), others))
+    }
+
+    /// Drop the selected group once dealt with
+    pub fn remove_selected(&mut self) {
+        let group = self.selected.0;
+        if group < self.groups.len() {
+            self.groups.remove(group);
+            self.keep.remove(group);
+            self.selected = (group.min(self.groups.len().saturating_sub(1)), 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metadata::TrackInfo,
+        testing::{ScratchDir, wav},
+        track_id::track_id,
+    };
+
+    fn song(path: PathBuf) -> SongItem {
+        let info = TrackInfo {
+            file_id: track_id(&path),
+            ..TrackInfo::default()
+        };
+        SongItem::new(path, String::new(), 0, info)
+    }
+
+    #[test]
+    fn files_opening_the_same_way_are_hashed_whole() {
+        let dir = ScratchDir::new("duplicates");
+        // Four seconds of silence, longer than the start hashed for track ids
+        let mut first = vec![0; 4 * 44_100];
+        let mut second = first.clone();
+        first.extend((0..44_100).map(|i| (i % 100) as i16));
+        second.extend((0..44_100).map(|i| (i % 300) as i16));
+        fs::write(dir.join("a.wav"), wav(&first)).unwrap();
+        fs::write(dir.join("b.wav"), wav(&second)).unwrap();
+        fs::write(dir.join("copy of a.wav"), wav(&first)).unwrap();
+        let songs: Vec<SongItem> = ["a.wav", "b.wav", "copy of a.wav"]
+            .iter()
+            .map(|name| song(dir.join(name)))
+            .collect();
+        assert_eq!(songs[0].track_id(), songs[1].track_id());
+
+        let mut cache = HashCache {
+            file: None,
+            entries: HashMap::new(),
+        };
+        // Hashed the first time, then read from the cache
+        for _ in 0..2 {
+            let groups = find(&songs, &mut cache, &AtomicUsize::new(0));
+            assert_eq!(groups.len(), 1);
+            assert_eq!(groups[0].reason, Reason::SameAudio);
+            let paths: Vec<&Path> = groups[0].copies.iter().map(|c| c.song.as_path()).collect();
+            assert_eq!(paths, [dir.join("a.wav"), dir.join("copy of a.wav")]);
+        }
+        assert_eq!(cache.entries.len(), 3);
+
+        // A file changed since it was hashed is hashed again
+        fs::write(dir.join("copy of a.wav"), wav(&second)).unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let file = fs::File::options().write(true).open(dir.join("copy of a.wav")).unwrap();
+        file.set_modified(modified).unwrap();
+        let groups = find(&songs, &mut cache, &AtomicUsize::new(0));
+        let paths: Vec<&Path> = groups[0].copies.iter().map(|c| c.song.as_path()).collect();
+        assert_eq!(paths, [dir.join("b.wav"), dir.join("copy of a.wav")]);
+    }
+
+    #[test]
+    fn normalize_drops_brackets_but_keeps_version_qualifiers() {
+        assert_eq!(normalize("Hello, World! (2011 Remaster)"), "hello world");
+        assert_eq!(normalize("Song [Bonus Track]"), "song");
+        assert_eq!(normalize("Song (Live)"), "song live");
+        assert_eq!(normalize("Song (Live at Wembley) [Remastered]"), "song live at wembley");
+        assert_eq!(normalize("Song [Radio EDIT]"), "song radio edit");
+        assert_eq!(normalize("Song (DJ Remix (Extended))"), "song dj remix extended");
+        assert_eq!(normalize("Song - Demo"), "song demo");
+        // Unbalanced brackets do not swallow the rest
+        assert_eq!(normalize("Song) Two (unclosed"), "song two");
+        assert_ne!(normalize("Song (Live)"), normalize("Song"));
+        assert_ne!(normalize("Song (Remix)"), normalize("Song (Demo)"));
+    }
+
+    fn secs(durations: &[u64]) -> Vec<(usize, Duration)> {
+        durations
+            .iter()
+            .enumerate()
+            .map(|(i, &secs)| (i, Duration::from_secs(secs)))
+            .collect()
+    }
+
+    #[test]
+    fn durations_are_compared_with_the_first_of_their_group() {
+        assert_eq!(duration_clusters(secs(&[180, 182, 184, 186])), [vec![0, 1], vec![2, 3]]);
+        assert_eq!(duration_clusters(secs(&[300, 183, 180])), [vec![2, 1], vec![0]]);
+        assert_eq!(duration_clusters(secs(&[200, 200, 204])), [vec![0, 1], vec![2]]);
+        assert!(duration_clusters(Vec::new()).is_empty());
+    }
+}
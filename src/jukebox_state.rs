@@ -1,56 +1,173 @@
-use rodio::{OutputStream, OutputStreamBuilder, Sink};
+use rodio::{Decoder, OutputStream, OutputStreamBuilder, Sink};
 use std::{
+    collections::{HashMap, VecDeque},
     fs::File,
-    io::BufReader,
+    io,
     path::{Path, PathBuf},
-    time::{Duration, Instant},
-    vec,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use symphonia::core::{io::MediaSourceStream, probe::Hint};
+
+use crate::{
+    audio::{SampleTap, Segment, Tap},
+    bookmarks::{Bookmark, Bookmarks},
+    check::{self, LibraryCheck, Problem},
+    config::Config,
+    cover_art::{CoverArt, CoverArts},
+    duplicates,
+    library,
+    library_index::LibraryIndex,
+    library_watcher::{self, LibraryChanges, LibraryWatcher},
+    listen_log::{Listen, ListenLog},
+    lyrics::{Lyrics, LyricsCache},
+    play_stats::{PlayStats, TrackStats},
+    scrobbler::{self, Scrobbler, Track},
+    query::Query,
+    saved_playlists::{self, SavedPlaylists, SavedSong, View},
+    sort::{self, SortMode},
+    metadata::{TrackInfo, TrackTags},
+    organizer::{self, Move, Plan},
+    path_pattern::PathPattern,
+    stream::{self, StreamHandle, StreamSource, StreamStatus},
+    tag_writer,
+    waveform::{Waveform, Waveforms},
+};
+
+/// Positions closer than this to the start or the end of a track are not worth a bookmark
+const BOOKMARK_MARGIN: Duration = Duration::from_secs(10);
+/// Playback time between two saves of the bookmark, so a crash loses little of it
+const BOOKMARK_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Playbacks shorter than this are skips, not listens, unless the song was counted as played
+const MIN_LISTEN: Duration = Duration::from_secs(30);
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SongItem {
+    /// Empty for streams, which only have their `url`
     full_path: PathBuf,
     title: String,
     position: usize,
     duration: Option<Duration>,
+    performer: Option<String>,
+    /// Offset of the song inside its file, non-zero for CUE sheet tracks
+    start: Duration,
+    /// Where the song stops inside its file, `None` means the end of the file
+    end: Option<Duration>,
+    /// Set for `http://` streams, which have no local file
+    url: Option<String>,
+    tags: TrackTags,
+    codec: Option<String>,
+    /// Identity of the file when known from the library index
+    file_id: Option<String>,
 }
 
 #[allow(dead_code)]
 impl SongItem {
-    fn new(full_path: PathBuf, title: String, position: usize) -> Self {
+    pub fn new(full_path: PathBuf, title: String, position: usize, info: TrackInfo) -> Self {
         Self {
-            full_path: full_path.clone(),
+            full_path,
             title,
             position,
-            duration: Self::calculate_duration(full_path),
+            duration: info.duration,
+            performer: None,
+            start: Duration::ZERO,
+            end: None,
+            url: None,
+            tags: info.tags,
+            codec: info.codec,
+            file_id: info.file_id,
+        }
+    }
+
+    /// Network stream or internet radio, titled after the playlist entry or the URL
+    pub fn stream(url: String, title: Option<String>, position: usize) -> Self {
+        Self {
+            full_path: PathBuf::new(),
+            title: title.unwrap_or_else(|| url.clone()),
+            position,
+            duration: None,
+            performer: None,
+            start: Duration::ZERO,
+            end: None,
+            url: Some(url),
+            tags: TrackTags::default(),
+            codec: None,
+            file_id: None,
         }
     }
 
-    fn calculate_duration(full_path: PathBuf) -> Option<Duration> {
-        use std::fs::File;
-        use symphonia::default::get_probe;
+    pub fn with_position(mut self, position: usize) -> Self {
+        self.position = position;
+        self
+    }
 
-        let file = File::open(&full_path).ok()?;
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
-        let mut hint = Hint::new();
-        hint.with_extension("mp3");
+    /// Take the title and length a playlist gives for the song where its file has none
+    pub fn with_playlist_info(mut self, title: Option<String>, duration: Option<Duration>) -> Self {
+        self.tags.title = self.tags.title.or(title);
+        self.duration = self.duration.or(duration);
+        self
+    }
 
-        let format = get_probe()
-            .format(&hint, mss, &Default::default(), &Default::default())
-            .ok()?
-            .format;
+    /// Virtual song covering the `(start, end)` part of a file, as described by a CUE sheet
+    pub fn segment(
+        full_path: PathBuf,
+        title: String,
+        performer: Option<String>,
+        position: usize,
+        (start, end): (Duration, Option<Duration>),
+        file_info: &TrackInfo,
+    ) -> Self {
+        let duration = end
+            .or(file_info.duration)
+            .map(|end| end.saturating_sub(start));
+        // The file tags describe the whole album, the CUE sheet describes the track
+        let tags = TrackTags {
+            title: Some(title.clone()),
+            artist: performer.clone().or_else(|| file_info.tags.artist.clone()),
+            track_number: None,
+            ..file_info.tags.clone()
+        };
 
-        if let Some(track) = format.tracks().iter().next() {
-            if let Some(time_base) = track.codec_params.time_base {
-                if let Some(n_frames) = track.codec_params.n_frames {
-                    let duration_secs =
-                        n_frames as f64 / time_base.denom as f64 * time_base.numer as f64;
-                    return Some(Duration::from_secs_f64(duration_secs));
-                }
-            }
+        Self {
+            full_path,
+            title,
+            position,
+            duration,
+            performer,
+            start,
+            end,
+            url: None,
+            tags,
+            codec: file_info.codec.clone(),
+            file_id: file_info.file_id.clone(),
         }
-        None
+    }
+
+    /// Take the new duration, tags and identity of the file after it was modified
+    pub fn refresh(&mut self, info: &TrackInfo) {
+        self.duration = info.duration;
+        self.tags = info.tags.clone();
+        self.codec = info.codec.clone();
+        self.file_id = info.file_id.clone();
+    }
+
+    /// Follow the file to its new path, a title taken from the file name following too
+    pub fn relocate(&mut self, path: &Path) {
+        if self.full_path.file_name().is_some_and(|name| name.to_string_lossy() == self.title)
+            && let Some(name) = path.file_name()
+        {
+            self.title = name.to_string_lossy().to_string();
+        }
+        self.full_path = path.to_path_buf();
     }
 
     pub fn as_path(&self) -> &Path {
@@ -68,6 +185,64 @@ impl SongItem {
     pub fn position(&self) -> usize {
         self.position
     }
+
+    pub fn performer(&self) -> Option<&str> {
+        self.performer.as_deref()
+    }
+
+    pub fn tags(&self) -> &TrackTags {
+        &self.tags
+    }
+
+    pub fn codec(&self) -> Option<&str> {
+        self.codec.as_deref()
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    pub fn is_stream(&self) -> bool {
+        self.url.is_some()
+    }
+
+    /// Name shown in the playlist: file name without extension, or the CUE/stream title
+    pub fn display_title(&self) -> String {
+        if self.is_stream() {
+            self.title.clone()
+        } else if self.is_segment() {
+            match &self.performer {
+                Some(performer) => format!("{} - {}", performer, self.title),
+                None => self.title.clone(),
+            }
+        } else {
+            self.title.split('.').next().unwrap_or("").to_string()
+        }
+    }
+
+    /// Start and end of the song inside its file
+    pub fn bounds(&self) -> (Duration, Option<Duration>) {
+        (self.start, self.end)
+    }
+
+    pub fn is_segment(&self) -> bool {
+        !self.start.is_zero() || self.end.is_some()
+    }
+
+    /// Stable identity of the track, independent of its path (streams have none)
+    pub fn track_id(&self) -> Option<String> {
+        if self.is_stream() {
+            return None;
+        }
+        // Songs get it from the library index, files it could not read have none
+        let id = self.file_id.clone()?;
+        if self.is_segment() {
+            // Tracks sharing a file are told apart by their offset
+            Some(format!("{}@{}", id, self.start.as_millis()))
+        } else {
+            Some(id)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -76,16 +251,39 @@ pub struct PlaybackState {
     start_time: Instant,
     elapsed_before_pause: Duration,
     is_paused: bool,
+    /// Set once the play was added to the play count
+    counted: bool,
+    /// Set once the listen was submitted to ListenBrainz
+    scrobbled: bool,
+    /// Position playback started from, or was last seeked to, not part of the listened time
+    offset: Duration,
+    /// Time heard before the last seek
+    listened_before_seek: Duration,
+    /// Time heard when the bookmark was last saved
+    bookmarked_at: Duration,
+    /// Wall clock time playback started, in seconds since the epoch
+    started_at: u64,
 }
 
 #[allow(dead_code)]
 impl PlaybackState {
     pub fn new(song: SongItem) -> Self {
+        Self::starting_at(song, Duration::ZERO)
+    }
+
+    /// Playback that begins at `offset` into the song (e.g. after a seek)
+    pub fn starting_at(song: SongItem, offset: Duration) -> Self {
         Self {
             song,
             start_time: Instant::now(),
-            elapsed_before_pause: Duration::ZERO,
+            elapsed_before_pause: offset,
             is_paused: false,
+            counted: false,
+            scrobbled: false,
+            offset,
+            listened_before_seek: Duration::ZERO,
+            bookmarked_at: Duration::ZERO,
+            started_at: unix_now(),
         }
     }
 
@@ -115,53 +313,131 @@ impl PlaybackState {
         &self.song
     }
 
+    /// Move to `position`, still the same listen: the time heard so far is kept
+    pub fn seek(&mut self, position: Duration) {
+        self.listened_before_seek = self.listened();
+        self.offset = position;
+        self.elapsed_before_pause = position;
+        self.start_time = Instant::now();
+    }
+
+    /// Time the song was actually heard, pauses and skipped parts left out
+    pub fn listened(&self) -> Duration {
+        self.listened_before_seek + self.current_position().saturating_sub(self.offset)
+    }
+
     pub fn is_paused(&self) -> bool {
         self.is_paused
     }
 }
 
+/// A song waiting for the user to choose whether to resume from its bookmark
+#[derive(Debug, Clone)]
+pub struct PendingResume {
+    pub song: SongItem,
+    pub position: Duration,
+}
+
 #[allow(unused)]
 pub struct JukeboxState {
-    initial_path: PathBuf,
+    sources: Vec<String>,
+    patterns: Vec<PathPattern>,
+    /// Subdirectories of the music directories are read too
+    recursive: bool,
     current_selection: SongItem,
+    /// Songs in the order of the sources, the playlist is a sorted copy
+    library: Vec<SongItem>,
     playlist: Vec<SongItem>,
+    sort_mode: SortMode,
+    saved_playlists: SavedPlaylists,
+    view: View,
+    /// Songs played next, ahead of the playlist order
+    queue: VecDeque<SongItem>,
     current_playback: Option<PlaybackState>,
     volume: u8,
     stream_handle: OutputStream,
     sink: Option<Sink>,
+    bookmarks: Bookmarks,
+    play_stats: PlayStats,
+    listen_log: ListenLog,
+    scrobbler: Option<Scrobbler>,
+    bookmark_threshold: Duration,
+    pending_resume: Option<PendingResume>,
+    stream: Option<StreamHandle>,
+    /// Stream being probed in the background, appended to the sink once ready
+    pending_stream: Option<Receiver<Result<StreamSource, String>>>,
+    sample_tap: SampleTap,
+    waveforms: Waveforms,
+    cover_arts: CoverArts,
+    lyrics: LyricsCache,
+    library_index: LibraryIndex,
+    library_watcher: Option<LibraryWatcher>,
+    /// Problems found by the last check of the library files, by path
+    problems: HashMap<PathBuf, Vec<Problem>>,
+    library_check: Option<LibraryCheck>,
+    /// Why the last song could not be played, until it is shown
+    play_error: Option<String>,
 }
 
 #[allow(dead_code)]
 impl JukeboxState {
-    pub fn new(initial_path: &Path) -> Self {
-        // Read directory and get all mp3 files
-        let mut playlist = vec![];
-        if let Ok(entries) = std::fs::read_dir(initial_path) {
-            for entry in entries.flatten() {
-                if let Some(ext) = entry.path().extension() {
-                    if ext == "mp3" {
-                        let title = entry.file_name().to_string_lossy().to_string();
-                        playlist.push(SongItem::new(entry.path(), title, playlist.len()));
-                    }
-                }
-            }
-        }
+    pub fn new(sources: &[String], config: &Config) -> Self {
+        // Read directories, playlists and URLs given on the command line,
+        // files unchanged since the last launch are not probed again
+        let mut library_index = LibraryIndex::load();
+        let library = library::load_sources(
+            sources,
+            &mut library_index,
+            &config.patterns,
+            config.recursive,
+        );
+        library_index.save();
+        let playlist = library.clone();
 
         let initial_selection = playlist.first().cloned().unwrap_or_else(|| {
-            SongItem::new(PathBuf::from("."), "No songs available".to_string(), 0)
+            SongItem::new(
+                PathBuf::from("."),
+                "No songs available".to_string(),
+                0,
+                TrackInfo::default(),
+            )
         });
 
         let stream_handle =
             OutputStreamBuilder::open_default_stream().expect("Failed to open audio stream");
 
         Self {
-            initial_path: initial_path.to_path_buf(),
+            sources: sources.to_vec(),
+            patterns: config.patterns.clone(),
+            recursive: config.recursive,
             current_selection: initial_selection,
+            library,
             playlist,
+            sort_mode: SortMode::default(),
+            saved_playlists: SavedPlaylists::load(),
+            view: View::Library,
+            queue: VecDeque::new(),
             current_playback: None,
             volume: 50, // Default volume
             stream_handle,
             sink: None,
+            bookmarks: Bookmarks::load(),
+            play_stats: PlayStats::load(),
+            listen_log: ListenLog::open(),
+            scrobbler: config.scrobble.clone().map(Scrobbler::start),
+            bookmark_threshold: config.bookmark_threshold,
+            pending_resume: None,
+            stream: None,
+            pending_stream: None,
+            sample_tap: SampleTap::default(),
+            waveforms: Waveforms::default(),
+            cover_arts: CoverArts::default(),
+            lyrics: LyricsCache::default(),
+            library_index,
+            library_watcher: LibraryWatcher::new(sources, config.recursive),
+            problems: HashMap::new(),
+            library_check: None,
+            play_error: None,
         }
     }
 
@@ -187,29 +463,243 @@ impl JukeboxState {
             // If it's a different song, we'll start the new one (fall through to start new song)
         }
 
+        // Long songs with a bookmark ask the user where to start from
+        if let Some(position) = self.bookmark_position(&self.current_selection) {
+            self.pending_resume = Some(PendingResume {
+                song: self.current_selection.clone(),
+                position,
+            });
+            return;
+        }
+
         // Start new song (use current_selection)
         self.play_song_at_position(self.current_selection.position);
     }
-    
+
+    pub fn pending_resume(&self) -> Option<&PendingResume> {
+        self.pending_resume.as_ref()
+    }
+
+    /// Answer the resume prompt: start from the bookmark or from the beginning
+    pub fn confirm_resume(&mut self, resume: bool) {
+        let Some(pending) = self.pending_resume.take() else {
+            return;
+        };
+
+        self.play_song_at_position(pending.song.position);
+
+        if resume {
+            self.seek(pending.position);
+        } else if let Some(id) = pending.song.track_id() {
+            self.bookmarks.remove(&id);
+        }
+    }
+
+    pub fn cancel_resume(&mut self) {
+        self.pending_resume = None;
+    }
+
+    fn seek(&mut self, position: Duration) {
+        if let Some(sink) = &self.sink
+            && sink.try_seek(position).is_ok()
+            && let Some(playback) = &mut self.current_playback
+        {
+            playback.seek(position);
+        }
+    }
+
+    fn is_long(&self, song: &SongItem) -> bool {
+        song.duration.is_some_and(|d| d >= self.bookmark_threshold)
+    }
+
+    fn bookmark_position(&self, song: &SongItem) -> Option<Duration> {
+        if !self.is_long(song) {
+            return None;
+        }
+        let id = song.track_id()?;
+        self.bookmarks.get(&id).map(|b| b.position())
+    }
+
+    /// Remember where the current long song was left, or forget it if it was finished
+    fn save_bookmark(&mut self) {
+        let Some(playback) = &self.current_playback else {
+            return;
+        };
+        let song = playback.song();
+        if !self.is_long(song) {
+            return;
+        }
+        let Some(id) = song.track_id() else {
+            return;
+        };
+
+        let position = playback.current_position();
+        let finished = self.sink.as_ref().is_none_or(|sink| sink.empty())
+            || song
+                .duration
+                .is_some_and(|d| position + BOOKMARK_MARGIN >= d);
+
+        if finished || position < BOOKMARK_MARGIN {
+            self.bookmarks.remove(&id);
+        } else {
+            let bookmark = Bookmark::new(song.display_title(), song.full_path.clone(), position);
+            self.bookmarks.set(id, bookmark);
+        }
+    }
+
+    /// Save the bookmark again once the song played for a while since the last save
+    fn save_bookmark_periodically(&mut self) {
+        let Some(playback) = &mut self.current_playback else {
+            return;
+        };
+        let listened = playback.listened();
+        if listened < playback.bookmarked_at + BOOKMARK_SAVE_INTERVAL {
+            return;
+        }
+        playback.bookmarked_at = listened;
+        self.save_bookmark();
+    }
+
+    pub fn bookmarks(&self) -> Vec<(&str, &Bookmark)> {
+        self.bookmarks.list()
+    }
+
+    pub fn remove_bookmark(&mut self, id: &str) {
+        self.bookmarks.remove(id);
+    }
+
+    pub fn clear_bookmarks(&mut self) {
+        self.bookmarks.clear();
+    }
+
     fn play_song_at_position(&mut self, position: usize) {
         if let Some(song) = self.playlist.get(position) {
-            // Clone the needed data before calling self.stop()
-            let song_full_path = song.full_path.clone();
-            let song_clone = song.clone();
+            self.start_song(song.clone());
+        }
+    }
 
-            // Stop current playback if any
-            self.stop();
+    /// Play a song, keeping why it cannot be played for the user. Returns whether it plays
+    fn start_song(&mut self, song: SongItem) -> bool {
+        match self.play_song(song) {
+            Ok(()) => true,
+            Err(error) => {
+                self.play_error = Some(error);
+                false
+            }
+        }
+    }
 
-            let file =
-                BufReader::new(File::open(&song_full_path).expect("Failed to open song file"));
-            let sink = rodio::play(&self.stream_handle.mixer(), file).expect("Failed to play song");
-            sink.set_volume(self.volume as f32 / 100.0);
+    /// Take why the last song could not be played, to show it once
+    pub fn take_play_error(&mut self) -> Option<String> {
+        self.play_error.take()
+    }
+
+    fn play_song(&mut self, song: SongItem) -> Result<(), String> {
+        // Stop current playback if any
+        self.stop();
+
+        if let Some(url) = song.url() {
+            self.start_stream(url.to_string());
+            self.current_playback = Some(PlaybackState::new(song));
+            return Ok(());
+        }
+
+        let cannot_play = |error: &dyn std::fmt::Display| {
+            format!("Cannot play {}: {}", song.display_title(), error)
+        };
+        let file = File::open(&song.full_path).map_err(|error| cannot_play(&error))?;
+        let decoder = Decoder::try_from(file).map_err(|error| cannot_play(&error))?;
+        let sink = Sink::connect_new(self.stream_handle.mixer());
+        let segment = Segment::new(decoder, song.start, song.end);
+        sink.append(Tap::new(segment, self.sample_tap.clone()));
+        sink.set_volume(self.volume as f32 / 100.0);
+
+        if let Some(scrobbler) = &self.scrobbler
+            && let Some(track) = Track::from_song(&song)
+        {
+            scrobbler.now_playing(track);
+        }
 
-            self.sink = Some(sink);
-            self.current_playback = Some(PlaybackState::new(song_clone));
+        self.sink = Some(sink);
+        self.current_playback = Some(PlaybackState::new(song));
+        Ok(())
+    }
+
+    /// Play the first song right away and queue the others after it
+    pub fn play_songs(&mut self, songs: Vec<SongItem>) {
+        self.queue = VecDeque::from(songs);
+        self.play_queued();
+    }
+
+    /// Play the next queued song, skipping those that cannot be played
+    fn play_queued(&mut self) {
+        while let Some(song) = self.queue.pop_front() {
+            if self.start_song(song) {
+                return;
+            }
+        }
+    }
+
+    /// Songs played next, before going on with the playlist
+    pub fn enqueue(&mut self, songs: Vec<SongItem>) {
+        self.queue.extend(songs);
+    }
+
+    pub fn queue(&self) -> &VecDeque<SongItem> {
+        &self.queue
+    }
+
+    /// Connect to a stream; decoding starts in the background once enough data is buffered
+    fn start_stream(&mut self, url: String) {
+        let (handle, reader) = stream::open(&url);
+        let (tx, rx) = mpsc::channel();
+        let url_path = url.split(['?', '#']).next().unwrap_or(&url);
+        let extension = Path::new(url_path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_string());
+        thread::spawn(move || {
+            let _ = tx.send(StreamSource::new(reader, extension.as_deref()));
+        });
+
+        let sink = Sink::connect_new(self.stream_handle.mixer());
+        sink.set_volume(self.volume as f32 / 100.0);
+
+        self.sink = Some(sink);
+        self.stream = Some(handle);
+        self.pending_stream = Some(rx);
+    }
+
+    /// Hand a stream to the sink once its decoder is ready
+    fn poll_stream(&mut self) {
+        let Some(rx) = &self.pending_stream else {
+            return;
+        };
+
+        match rx.try_recv() {
+            Ok(Ok(source)) => {
+                if let Some(sink) = &self.sink {
+                    sink.append(Tap::new(source, self.sample_tap.clone()));
+                }
+                self.pending_stream = None;
+            }
+            Ok(Err(error)) => {
+                if let Some(stream) = &self.stream {
+                    stream.set_error(error);
+                }
+                self.pending_stream = None;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.pending_stream = None,
         }
     }
 
+    /// Status of the stream being played, if any
+    pub fn stream_status(&self) -> Option<StreamStatus> {
+        self.stream.as_ref().map(|stream| stream.status())
+    }
+
+    #[allow(clippy::collapsible_if)]
     pub fn pause(&mut self) {
         if let Some(playback) = &mut self.current_playback {
             if !playback.is_paused() {
@@ -222,12 +712,20 @@ impl JukeboxState {
     }
 
     pub fn stop(&mut self) {
+        self.save_bookmark();
+        self.log_listen();
+        // Closing the stream first unblocks a decoder waiting for data
+        if let Some(stream) = self.stream.take() {
+            stream.close();
+        }
+        self.pending_stream = None;
         if let Some(sink) = &self.sink {
             sink.stop();
             sink.sleep_until_end();
         }
         self.sink = None;
         self.current_playback = None;
+        self.sample_tap.clear();
     }
 
     pub fn add_volume(&mut self, amount: u8) {
@@ -274,6 +772,320 @@ impl JukeboxState {
         }
     }
 
+    /// Select the song at `position` in the playlist
+    pub fn select(&mut self, position: usize) {
+        if let Some(song) = self.playlist.get(position) {
+            self.current_selection = song.clone();
+        }
+    }
+
+    /// Rescan the sources once the watched directories changed, returning what changed
+    pub fn poll_library(&mut self) -> Option<LibraryChanges> {
+        if !self.library_watcher.as_mut()?.poll() {
+            return None;
+        }
+
+        let library = library::load_sources(
+            &self.sources,
+            &mut self.library_index,
+            &self.patterns,
+            self.recursive,
+        );
+        self.library_index.save();
+
+        let changes = LibraryChanges::between(&self.library, &library);
+        if changes.is_empty() {
+            return None;
+        }
+
+        self.library = library;
+        self.rebuild_playlist();
+        Some(changes)
+    }
+
+    /// Decode every library file in the background, unless a check is running already
+    pub fn start_check(&mut self) -> bool {
+        if self.library_check.is_some() {
+            return false;
+        }
+        self.library_check = Some(LibraryCheck::start(check::files(&self.library)));
+        true
+    }
+
+    /// Files checked so far and their total while a check runs
+    pub fn check_progress(&self) -> Option<(usize, usize)> {
+        self.library_check.as_ref().map(LibraryCheck::progress)
+    }
+
+    /// Keep the results of a finished check, returning how many files have problems
+    pub fn poll_check(&mut self) -> Option<usize> {
+        let reports = self.library_check.as_ref()?.poll()?;
+        self.library_check = None;
+        self.problems = reports
+            .into_iter()
+            .filter(|report| !report.problems.is_empty())
+            .map(|report| (report.path, report.problems))
+            .collect();
+        Some(self.problems.len())
+    }
+
+    /// Problems the last check found in the file of a song
+    pub fn problems(&self, song: &SongItem) -> Option<&[Problem]> {
+        self.problems.get(song.as_path()).map(Vec::as_slice)
+    }
+
+    pub fn sort_mode(&self) -> SortMode {
+        self.sort_mode
+    }
+
+    /// Switch to the next sort mode, keeping the selection on the same song
+    pub fn cycle_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+        self.rebuild_playlist();
+    }
+
+    pub fn saved_playlists(&self) -> &SavedPlaylists {
+        &self.saved_playlists
+    }
+
+    pub fn view(&self) -> View {
+        self.view
+    }
+
+    /// Name of what the playlist shows
+    pub fn view_name(&self) -> &str {
+        match self.view {
+            View::Saved(index) => self
+                .saved_playlists
+                .get(index)
+                .map_or("Available Songs", |playlist| playlist.name.as_str()),
+            View::Library => "Available Songs",
+        }
+    }
+
+    /// Show the whole library or a saved playlist
+    pub fn set_view(&mut self, view: View) {
+        self.view = view;
+        self.rebuild_playlist();
+    }
+
+    pub fn create_playlist(&mut self, name: String) -> usize {
+        self.saved_playlists.create(name)
+    }
+
+    /// Save a query as a smart playlist named after it
+    pub fn create_smart_playlist(&mut self, query: String) -> usize {
+        self.saved_playlists.create_smart(query.clone(), query)
+    }
+
+    pub fn rename_playlist(&mut self, index: usize, name: String) {
+        self.saved_playlists.rename(index, name);
+    }
+
+    /// Delete a saved playlist, going back to the library if it was shown
+    pub fn delete_playlist(&mut self, index: usize) {
+        self.saved_playlists.delete(index);
+        match self.view {
+            View::Saved(shown) if shown == index => self.set_view(View::Library),
+            View::Saved(shown) if shown > index => self.view = View::Saved(shown - 1),
+            _ => {}
+        }
+    }
+
+    /// Append a song to a saved playlist, returning why it cannot be when it is refused
+    pub fn add_to_playlist(&mut self, index: usize, song: &SongItem) -> Result<(), &'static str> {
+        if song.is_stream() {
+            return Err("Streams cannot be added to a playlist");
+        }
+        if self.saved_playlists.get(index).is_some_and(|p| p.is_smart()) {
+            return Err("Smart playlists are filled by their query");
+        }
+        self.saved_playlists.add(index, SavedSong::from(song));
+        if self.view == View::Saved(index) {
+            self.rebuild_playlist();
+        }
+        Ok(())
+    }
+
+    /// Remove the selected song from the saved playlist being shown
+    pub fn remove_selected_from_view(&mut self) {
+        let View::Saved(index) = self.view else {
+            return;
+        };
+        if let Some(song_index) = self.selected_saved_index() {
+            self.saved_playlists.remove(index, song_index);
+            self.rebuild_playlist();
+        }
+    }
+
+    /// Move the selected song up or down the saved playlist being shown.
+    ///
+    /// Moves are made in the playlist order, so sorting goes back to the source order.
+    pub fn move_selected_in_view(&mut self, direction: i32) {
+        let View::Saved(index) = self.view else {
+            return;
+        };
+        if self.saved_playlists.get(index).is_none_or(|p| p.is_smart()) {
+            return;
+        }
+        if self.sort_mode != SortMode::Source {
+            self.sort_mode = SortMode::Source;
+            self.rebuild_playlist();
+        }
+
+        let resolved = self.resolve_saved(index);
+        let position = self.current_selection.position;
+        let Some(target) = position
+            .checked_add_signed(direction as isize)
+            .filter(|&target| target < resolved.len())
+        else {
+            return;
+        };
+        if let (Some((from, _)), Some((to, _))) = (resolved.get(position), resolved.get(target)) {
+            self.saved_playlists.swap(index, *from, *to);
+            self.rebuild_playlist();
+            self.select(target);
+        }
+    }
+
+    /// Index in the saved playlist of the selected song, smart playlists have no order of their own
+    fn selected_saved_index(&mut self) -> Option<usize> {
+        let View::Saved(index) = self.view else {
+            return None;
+        };
+        if self.saved_playlists.get(index)?.is_smart() {
+            return None;
+        }
+        let key = library_watcher::song_key(&self.current_selection);
+        let resolved = self.resolve_saved(index);
+        // With the source order, the position tells duplicates apart
+        if self.sort_mode == SortMode::Source
+            && let Some((saved_index, song)) = resolved.get(self.current_selection.position)
+            && library_watcher::song_key(song) == key
+        {
+            return Some(*saved_index);
+        }
+        resolved
+            .iter()
+            .find(|(_, song)| library_watcher::song_key(song) == key)
+            .map(|(saved_index, _)| *saved_index)
+    }
+
+    /// Songs of a saved playlist with their index in it.
+    ///
+    /// Songs are taken from the library when found there, files outside of it
+    /// are probed, and files that no longer exist are left out. Smart playlists
+    /// are the library songs matching their query, indexed by library order.
+    fn resolve_saved(&mut self, index: usize) -> Vec<(usize, SongItem)> {
+        let Some(playlist) = self.saved_playlists.get(index) else {
+            return Vec::new();
+        };
+        if let Some(query) = &playlist.query {
+            let Ok(query) = Query::parse(query) else {
+                return Vec::new();
+            };
+            return self
+                .library
+                .iter()
+                .filter(|song| query.matches(song))
+                .cloned()
+                .enumerate()
+                .collect();
+        }
+
+        let mut resolved = Vec::new();
+        for (saved_index, saved) in playlist.songs.iter().enumerate() {
+            let found = self.library.iter().find(|song| {
+                saved_playlists::absolute(song.as_path()) == saved.path
+                    && song.bounds().0 == saved.start()
+            });
+            let song = match found {
+                Some(song) => song.clone(),
+                None if saved.start_ms == 0 && saved.path.is_file() => {
+                    library::file_song(
+                        saved.path.clone(),
+                        0,
+                        &library::pattern_root(&saved.path, &organizer::roots(&self.sources)),
+                        &mut self.library_index,
+                        &self.patterns,
+                    )
+                }
+                None => continue,
+            };
+            resolved.push((saved_index, song));
+        }
+        resolved
+    }
+
+    /// Songs of the current view, in their source order
+    fn view_songs(&mut self) -> Vec<SongItem> {
+        match self.view {
+            View::Library => self.library.clone(),
+            View::Saved(index) => self
+                .resolve_saved(index)
+                .into_iter()
+                .map(|(_, song)| song)
+                .collect(),
+        }
+    }
+
+    /// Sort the songs of the view into the playlist and renumber them.
+    ///
+    /// The selection and the playing song are carried over to their new entries,
+    /// so playback goes on undisturbed.
+    fn rebuild_playlist(&mut self) {
+        let mut playlist = self.view_songs();
+        sort::sort_songs(&mut playlist, self.sort_mode, &self.library_index);
+        let playlist: Vec<SongItem> = playlist
+            .into_iter()
+            .enumerate()
+            .map(|(position, song)| song.with_position(position))
+            .collect();
+
+        let find = |song: &SongItem| {
+            let key = library_watcher::song_key(song);
+            playlist
+                .iter()
+                .find(|candidate| library_watcher::song_key(candidate) == key)
+                .cloned()
+        };
+
+        if let Some(song) = find(&self.current_selection) {
+            self.current_selection = song;
+        } else if !playlist.is_empty() {
+            // The selected song is gone: select whatever took its place
+            let position = self.current_selection.position.min(playlist.len() - 1);
+            self.current_selection = playlist[position].clone();
+        }
+        if let Some(playback) = &mut self.current_playback
+            && let Some(song) = find(&playback.song)
+        {
+            playback.song = song;
+        }
+        if let Some(pending) = &mut self.pending_resume
+            && let Some(song) = find(&pending.song)
+        {
+            pending.song = song;
+        }
+        // Queued songs that disappeared from the library are dropped
+        let library = &self.library;
+        self.queue = self
+            .queue
+            .iter()
+            .filter_map(|song| {
+                let key = library_watcher::song_key(song);
+                find(song).or_else(|| {
+                    library
+                        .iter()
+                        .any(|candidate| library_watcher::song_key(candidate) == key)
+                        .then(|| song.clone())
+                })
+            })
+            .collect();
+
+        self.playlist = playlist;
+    }
+
     pub fn playlist(&self) -> &[SongItem] {
         &self.playlist
     }
@@ -287,24 +1099,40 @@ impl JukeboxState {
             && self
                 .current_playback
                 .as_ref()
-                .map_or(false, |p| !p.is_paused())
+                .is_some_and(|p| !p.is_paused())
     }
 
     pub fn is_song_finished(&self) -> bool {
+        // A failed stream stays selected so its error remains visible
+        let stream_failed = self
+            .stream_status()
+            .is_some_and(|status| status.error.is_some());
+
         if let Some(sink) = &self.sink {
-            sink.empty()
+            sink.empty() && self.pending_stream.is_none() && !stream_failed
         } else {
             false
         }
     }
 
     pub fn handle_song_end(&mut self) {
+        self.poll_stream();
+        self.save_bookmark_periodically();
+        self.count_play();
+        self.scrobble();
+
+        // Queued songs come before the rest of the playlist
+        if self.is_song_finished() && !self.queue.is_empty() {
+            self.play_queued();
+            return;
+        }
+
         if self.is_song_finished() {
             // Find position of currently playing song
             if let Some(current_playback) = &self.current_playback {
                 let playing_position = current_playback.song().position;
 
-                if playing_position >= self.playlist.len() - 1 {
+                if playing_position + 1 >= self.playlist.len() {
                     self.stop();
                     return; // No more songs to play
                 }
@@ -312,10 +1140,201 @@ impl JukeboxState {
                 // Next song
                 let next_position = (playing_position + 1) % self.playlist.len();
                 
-                // Play next song
-                self.play_song_at_position(next_position);
+                // Play next song, skipping those that cannot be played
+                for position in next_position..self.playlist.len() {
+                    if self.start_song(self.playlist[position].clone()) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Add the playback being stopped to the listen log, unless it was barely heard
+    fn log_listen(&self) {
+        let Some(playback) = &self.current_playback else {
+            return;
+        };
+        let listened = playback.listened();
+        if playback.song.is_stream() || (listened < MIN_LISTEN && !playback.counted) {
+            return;
+        }
+        let listen = Listen::new(&playback.song, playback.started_at, listened.as_secs());
+        self.listen_log.record(&listen);
+    }
+
+    pub fn listen_log(&self) -> &ListenLog {
+        &self.listen_log
+    }
+
+    /// Add the playing song to its play count once heard as long as for a scrobble,
+    /// whatever was skipped by seeking
+    fn count_play(&mut self) {
+        let Some(playback) = &mut self.current_playback else {
+            return;
+        };
+        if playback.counted
+            || playback.listened() < scrobbler::listen_threshold(playback.song.duration())
+        {
+            return;
+        }
+        playback.counted = true;
+        if let Some(id) = playback.song.track_id() {
+            self.play_stats.record_play(&id);
+        }
+    }
+
+    /// Submit the playing song as a listen once heard for half of it or 4 minutes
+    fn scrobble(&mut self) {
+        let Some(scrobbler) = &self.scrobbler else {
+            return;
+        };
+        let Some(playback) = &mut self.current_playback else {
+            return;
+        };
+        if playback.scrobbled
+            || playback.listened() < scrobbler::listen_threshold(playback.song.duration())
+        {
+            return;
+        }
+        playback.scrobbled = true;
+        if let Some(track) = Track::from_song(&playback.song) {
+            scrobbler.listen(playback.started_at, track);
+        }
+    }
+
+    /// Write tags into an audio file and refresh its song wherever it is listed
+    pub fn edit_tags(&mut self, path: &Path, tags: &TrackTags) -> io::Result<()> {
+        tag_writer::write_tags(path, tags)?;
+        let root = library::pattern_root(path, &organizer::roots(&self.sources));
+        let info = library::probe(path, &root, &mut self.library_index, &self.patterns);
+        self.library_index.save();
+
+        let refresh = |song: &mut SongItem| {
+            if song.as_path() != path {
+                return;
+            }
+            // CUE sheet tracks keep the tags of their sheet, only their identity follows
+            if song.is_segment() {
+                song.file_id = info.file_id.clone();
+            } else {
+                song.refresh(&info);
+            }
+        };
+        self.library.iter_mut().for_each(refresh);
+        self.queue.iter_mut().for_each(refresh);
+        refresh(&mut self.current_selection);
+        if let Some(playback) = &mut self.current_playback {
+            refresh(&mut playback.song);
+        }
+        if let Some(pending) = &mut self.pending_resume {
+            refresh(&mut pending.song);
+        }
+        // Sorted by the new tags
+        self.rebuild_playlist();
+        Ok(())
+    }
+
+    /// Library songs, in the order of the sources
+    pub fn library(&self) -> &[SongItem] {
+        &self.library
+    }
+
+    /// Whether the subdirectories of the music directories are read
+    pub fn is_recursive(&self) -> bool {
+        self.recursive
+    }
+
+    /// Where organizing the music directories with `pattern` would move each file
+    pub fn plan_organize(&self, pattern: &PathPattern) -> Plan {
+        organizer::plan(&self.library, &organizer::roots(&self.sources), pattern)
+    }
+
+    /// Move files as planned and have everything that refers to them follow:
+    /// saved playlists, bookmarks, the library index and the songs listed.
+    /// Returns the moves that failed.
+    pub fn organize(&mut self, plan: &Plan) -> Vec<(Move, io::Error)> {
+        let (moved, failed) = organizer::apply(plan);
+        for file in &moved {
+            self.library_index.rename(&file.from, &file.to);
+        }
+        let redirected: Vec<(PathBuf, PathBuf)> =
+            moved.into_iter().map(|file| (file.from, file.to)).collect();
+        self.redirect(&redirected);
+        failed
+    }
+
+    /// Move the other copies of a duplicated track to the quarantine folder of
+    /// their music directory. Saved playlists, bookmarks and the songs listed that
+    /// pointed to them take `keep` instead. Returns the copies that could not be moved.
+    pub fn quarantine(&mut self, keep: &Path, others: &[PathBuf]) -> Vec<(PathBuf, String)> {
+        let plan = duplicates::quarantine_plan(others, &organizer::roots(&self.sources));
+        let (moved, failed) = organizer::apply(&plan);
+        let redirected: Vec<(PathBuf, PathBuf)> =
+            moved.into_iter().map(|file| (file.from, keep.to_path_buf())).collect();
+        self.redirect(&redirected);
+
+        let failed = failed
+            .into_iter()
+            .map(|(file, error)| (file.from, error.to_string()));
+        plan.skipped.into_iter().chain(failed).collect()
+    }
+
+    /// Have everything that pointed to moved files follow them: saved playlists,
+    /// bookmarks and the songs listed, then read the library again
+    fn redirect(&mut self, redirected: &[(PathBuf, PathBuf)]) {
+        if redirected.is_empty() {
+            return;
+        }
+        let renamed: HashMap<PathBuf, PathBuf> = redirected
+            .iter()
+            .map(|(from, to)| (saved_playlists::absolute(from), saved_playlists::absolute(to)))
+            .collect();
+        self.saved_playlists.rename_paths(&renamed);
+        self.bookmarks.rename_paths(&renamed);
+
+        let relocate = |song: &mut SongItem| {
+            if let Some((_, to)) = redirected.iter().find(|(from, _)| from == song.as_path()) {
+                song.relocate(to);
             }
+        };
+        self.queue.iter_mut().for_each(relocate);
+        relocate(&mut self.current_selection);
+        if let Some(playback) = &mut self.current_playback {
+            relocate(&mut playback.song);
         }
+        if let Some(pending) = &mut self.pending_resume {
+            relocate(&mut pending.song);
+        }
+
+        self.library = library::load_sources(
+            &self.sources,
+            &mut self.library_index,
+            &self.patterns,
+            self.recursive,
+        );
+        self.library_index.save();
+        self.rebuild_playlist();
+    }
+
+    /// Play count, rating and favourite flag of a song
+    pub fn track_stats(&self, song: &SongItem) -> Option<&TrackStats> {
+        self.play_stats.get(&song.track_id()?)
+    }
+
+    /// Rate the selected song from 1 to 5 stars, 0 clears the rating. Streams cannot be rated
+    pub fn rate_selection(&mut self, stars: u8) -> bool {
+        let Some(id) = self.current_selection.track_id() else {
+            return false;
+        };
+        self.play_stats.set_rating(&id, Some(stars));
+        true
+    }
+
+    /// Mark or unmark the selected song as a favourite, returning its new state
+    pub fn toggle_favourite_selection(&mut self) -> Option<bool> {
+        let id = self.current_selection.track_id()?;
+        Some(self.play_stats.toggle_favourite(&id))
     }
 
     pub fn current_playback_position(&self) -> Duration {
@@ -324,6 +1343,7 @@ impl JukeboxState {
             .map_or(Duration::ZERO, |p| p.current_position())
     }
 
+    #[allow(clippy::collapsible_if)]
     pub fn progress_ratio(&self) -> f32 {
         if let Some(playback) = &self.current_playback {
             if let Some(duration) = playback.song().duration {
@@ -336,7 +1356,53 @@ impl JukeboxState {
         0.0
     }
 
+    /// Waveform overview of the playing song, once computed in the background
+    pub fn current_waveform(&self) -> Option<Waveform> {
+        self.waveforms.get(self.currently_playing()?)
+    }
+
+    /// Album art of the playing song, once loaded in the background
+    pub fn current_cover(&self) -> Option<CoverArt> {
+        self.cover_arts.get(self.currently_playing()?)
+    }
+
+    /// Lyrics of the playing song, once loaded in the background
+    pub fn current_lyrics(&self) -> Option<Arc<Lyrics>> {
+        self.lyrics.get(self.currently_playing()?)
+    }
+
+    /// Samples being played, for visualizers and meters
+    pub fn sample_tap(&self) -> &SampleTap {
+        &self.sample_tap
+    }
+
     pub fn volume(&self) -> u8 {
         self.volume
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeking_keeps_the_time_listened() {
+        let song = SongItem::stream("http://localhost/radio".to_string(), None, 0);
+        let mut playback = PlaybackState::starting_at(song, Duration::from_secs(10));
+        playback.pause();
+        // 50s heard from 10s on
+        playback.elapsed_before_pause = Duration::from_secs(60);
+        assert_eq!(playback.listened(), Duration::from_secs(50));
+
+        playback.seek(Duration::from_secs(20));
+        assert!(playback.is_paused());
+        assert_eq!(playback.current_position(), Duration::from_secs(20));
+        assert_eq!(playback.listened(), Duration::from_secs(50));
+
+        playback.elapsed_before_pause = Duration::from_secs(45);
+        assert_eq!(playback.listened(), Duration::from_secs(75));
+
+        playback.seek(Duration::from_secs(200));
+        assert_eq!(playback.listened(), Duration::from_secs(75));
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use crate::audio::SampleTap;
+
+/// Audio analysed per update, about one UI frame
+const WINDOW: Duration = Duration::from_millis(50);
+/// How long the peak marker and the clip light stay up
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+/// Lowest level drawn on the meters
+pub const MIN_DB: f32 = -48.0;
+
+/// Levels of one channel, in dBFS after the volume is applied
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelLevel {
+    pub rms_db: f32,
+    pub peak_db: f32,
+    pub peak_hold_db: f32,
+    peak_hold_since: Instant,
+    /// Last time a sample would have exceeded full scale
+    clipped_at: Option<Instant>,
+}
+
+impl ChannelLevel {
+    fn silent() -> Self {
+        Self {
+            rms_db: MIN_DB,
+            peak_db: MIN_DB,
+            peak_hold_db: MIN_DB,
+            peak_hold_since: Instant::now(),
+            clipped_at: None,
+        }
+    }
+
+    fn update(&mut self, samples: impl Iterator<Item = f32>, gain: f32) {
+        let mut sum = 0.0;
+        let mut peak: f32 = 0.0;
+        let mut count = 0;
+        for sample in samples {
+            let sample = sample * gain;
+            sum += sample * sample;
+            peak = peak.max(sample.abs());
+            count += 1;
+        }
+
+        let rms = if count > 0 { (sum / count as f32).sqrt() } else { 0.0 };
+        self.rms_db = to_db(rms);
+        self.peak_db = to_db(peak);
+
+        if self.peak_db >= self.peak_hold_db || self.peak_hold_since.elapsed() > PEAK_HOLD {
+            self.peak_hold_db = self.peak_db;
+            self.peak_hold_since = Instant::now();
+        }
+        if peak > 1.0 {
+            self.clipped_at = Some(Instant::now());
+        }
+    }
+
+    pub fn is_clipping(&self) -> bool {
+        self.clipped_at.is_some_and(|at| at.elapsed() < PEAK_HOLD)
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(1e-6).log10()).max(MIN_DB)
+}
+
+/// Stereo RMS / peak-hold meter fed from the sample tap
+pub struct LevelMeter {
+    channels: Vec<ChannelLevel>,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            channels: vec![ChannelLevel::silent(); 2],
+        }
+    }
+
+    /// Measure the latest samples as they will sound at the given volume (0-100)
+    pub fn update(&mut self, tap: &SampleTap, volume: u8) {
+        let frames = (tap.sample_rate() as f32 * WINDOW.as_secs_f32()) as usize;
+        let (samples, channels) = tap.latest_frames(frames);
+        let channels = channels.max(1) as usize;
+        let gain = volume as f32 / 100.0;
+
+        // Mono sources drive both meters
+        for (i, level) in self.channels.iter_mut().enumerate() {
+            let channel = i.min(channels - 1);
+            level.update(samples.iter().skip(channel).step_by(channels).copied(), gain);
+        }
+    }
+
+    pub fn channels(&self) -> &[ChannelLevel] {
+        &self.channels
+    }
+}
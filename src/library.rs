@@ -0,0 +1,306 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cue::CueSheet,
+    jukebox_state::SongItem,
+    library_index::LibraryIndex,
+    metadata::TrackInfo,
+    organizer,
+    path_pattern::{self, PathPattern},
+    playlist_file::{self, Location},
+    saved_playlists, sort,
+};
+
+/// Audio file extensions the player can decode
+pub const SUPPORTED_EXTENSIONS: [&str; 5] = ["mp3", "flac", "ogg", "wav", "m4a"];
+
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn is_cue_sheet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// Build the playlist from the command line sources: directories, audio files,
+/// M3U/PLS playlists and `http://` stream URLs.
+///
+/// Tags missing from the files are guessed from their path with `patterns`. The
+/// subdirectories of the directories are only read when `recursive` is set.
+pub fn load_sources(
+    sources: &[String],
+    index: &mut LibraryIndex,
+    patterns: &[PathPattern],
+    recursive: bool,
+) -> Vec<SongItem> {
+    let mut playlist = Vec::new();
+    let roots = organizer::roots(sources);
+
+    for source in sources {
+        if playlist_file::is_url(source) {
+            playlist.push(SongItem::stream(source.clone(), None, 0));
+            continue;
+        }
+
+        let path = Path::new(source);
+        if path.is_dir() {
+            playlist.extend(scan_directory(path, &roots, index, patterns, recursive));
+        } else if playlist_file::is_playlist_file(path) {
+            for entry in playlist_file::load(path).unwrap_or_default() {
+                match entry.location {
+                    Location::Url(url) => playlist.push(SongItem::stream(url, entry.title, 0)),
+                    Location::File(file) if is_supported(&file) && file.is_file() => {
+                        let root = pattern_root(&file, &roots);
+                        let song = file_song(file, 0, &root, index, patterns);
+                        playlist.push(song.with_playlist_info(entry.title, entry.duration));
+                    }
+                    Location::File(_) => {}
+                }
+            }
+        } else if is_supported(path) && path.is_file() {
+            let root = pattern_root(path, &roots);
+            playlist.push(file_song(path.to_path_buf(), 0, &root, index, patterns));
+        }
+    }
+
+    // Number the songs in their final order
+    playlist
+        .into_iter()
+        .enumerate()
+        .map(|(position, song)| song.with_position(position))
+        .collect()
+}
+
+/// Folder the patterns read the path of `file` from: the deepest music directory
+/// holding it, or its own folder for files outside of them
+pub fn pattern_root(file: &Path, roots: &[PathBuf]) -> PathBuf {
+    let file_path = saved_playlists::absolute(file);
+    roots
+        .iter()
+        .map(|root| saved_playlists::absolute(root))
+        .filter(|root| file_path.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .unwrap_or_else(|| match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+}
+
+/// Probe a file through the index, filling the tags it lacks from the first pattern
+/// its path below `root` fits
+pub fn probe(
+    path: &Path,
+    root: &Path,
+    index: &mut LibraryIndex,
+    patterns: &[PathPattern],
+) -> TrackInfo {
+    let mut info = index.probe(path);
+    if let Some((_, inferred)) = path_pattern::infer(patterns, path, root) {
+        info.tags.fill_missing(inferred);
+    }
+    info
+}
+
+pub fn file_song(
+    path: PathBuf,
+    position: usize,
+    root: &Path,
+    index: &mut LibraryIndex,
+    patterns: &[PathPattern],
+) -> SongItem {
+    let title = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let info = probe(&path, root, index, patterns);
+    SongItem::new(path, title, position, info)
+}
+
+/// Read a directory, and its subdirectories when `recursive` is set, and build the playlist.
+///
+/// Audio files described by a CUE sheet are replaced by one virtual song per
+/// CUE track, so a single-file album shows up as separate tracks.
+pub fn scan_directory(
+    dir: &Path,
+    roots: &[PathBuf],
+    index: &mut LibraryIndex,
+    patterns: &[PathPattern],
+    recursive: bool,
+) -> Vec<SongItem> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    // `read_dir` order depends on the filesystem
+    entries.sort_by(|a, b| sort::natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    // Parse CUE sheets first, so the audio files they describe can be skipped
+    let sheets: Vec<(PathBuf, CueSheet)> = entries
+        .iter()
+        .filter(|path| is_cue_sheet(path))
+        .filter_map(|path| Some((path.clone(), load_cue_sheet(path)?)))
+        .collect();
+    let covered_by_cue: HashSet<&Path> = sheets
+        .iter()
+        .flat_map(|(_, sheet)| sheet.files.iter().map(|file| file.path.as_path()))
+        .filter(|path| path.is_file())
+        .collect();
+
+    let mut playlist = Vec::new();
+    for path in &entries {
+        if recursive && is_subdirectory(path) {
+            playlist.extend(scan_directory(path, roots, index, patterns, recursive));
+        } else if let Some((_, sheet)) = sheets.iter().find(|(cue_path, _)| cue_path == path) {
+            push_cue_tracks(&mut playlist, sheet, roots, index, patterns);
+        } else if is_supported(path) && !covered_by_cue.contains(path.as_path()) {
+            let root = pattern_root(path, roots);
+            playlist.push(file_song(path.clone(), playlist.len(), &root, index, patterns));
+        }
+    }
+
+    playlist
+}
+
+/// Folder to scan along with its parent. Hidden folders and symbolic links,
+/// which could lead back up the tree, are left out
+fn is_subdirectory(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    !hidden && fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
+}
+
+/// Read a CUE sheet, pointing each of its `FILE`s at the audio file actually on disk
+fn load_cue_sheet(path: &Path) -> Option<CueSheet> {
+    let mut sheet = CueSheet::load(path)?;
+    for file in &mut sheet.files {
+        if !file.path.is_file()
+            && let Some(found) = same_stem_audio_file(&file.path)
+        {
+            file.path = found;
+        }
+    }
+    Some(sheet)
+}
+
+/// Audio file next to `path` with the same name but another extension. Sheets keep naming
+/// the file they were ripped to ("album.wav") once it is converted to another format
+fn same_stem_audio_file(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?;
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let mut candidates: Vec<PathBuf> = fs::read_dir(dir.unwrap_or(Path::new(".")))
+        .ok()?
+        .flatten()
+        .map(|entry| path.with_file_name(entry.file_name()))
+        .filter(|candidate| candidate.file_stem() == Some(stem))
+        .filter(|candidate| is_supported(candidate) && candidate.is_file())
+        .collect();
+    // Several conversions of the same rip: pick one the same way on every scan
+    candidates.sort();
+    candidates.into_iter().next()
+}
+
+/// Add one virtual song per CUE track, each ending where the next one starts
+fn push_cue_tracks(
+    playlist: &mut Vec<SongItem>,
+    sheet: &CueSheet,
+    roots: &[PathBuf],
+    index: &mut LibraryIndex,
+    patterns: &[PathPattern],
+) {
+    for file in sheet.files.iter().filter(|file| file.path.is_file()) {
+        let root = pattern_root(&file.path, roots);
+        let file_info = probe(&file.path, &root, index, patterns);
+
+        for (i, track) in file.tracks.iter().enumerate() {
+            let end = file.tracks.get(i + 1).map(|next| next.start);
+            let title = track
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("Track {:02}", track.number));
+            let performer = track.performer.clone().or_else(|| sheet.performer.clone());
+
+            playlist.push(SongItem::segment(
+                file.path.clone(),
+                title,
+                performer,
+                playlist.len(),
+                (track.start, end),
+                &file_info,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ScratchDir, wav};
+    use std::time::Duration;
+
+    #[test]
+    fn patterns_read_paths_from_the_deepest_music_directory() {
+        let roots = [PathBuf::from("music"), PathBuf::from("music/podcasts")];
+        let cwd = std::env::current_dir().unwrap();
+        let root = |file: &str| pattern_root(Path::new(file), &roots);
+        assert_eq!(root("music/Artist/song.mp3"), cwd.join("music"));
+        assert_eq!(root("music/podcasts/Show/episode.mp3"), cwd.join("music/podcasts"));
+        assert_eq!(root(&cwd.join("music/song.mp3").to_string_lossy()), cwd.join("music"));
+        // Files given on their own only have their name read
+        assert_eq!(root("/elsewhere/Artist/song.mp3"), PathBuf::from("/elsewhere/Artist"));
+        assert_eq!(root("song.mp3"), PathBuf::from("."));
+    }
+
+    #[test]
+    fn cue_file_falls_back_to_converted_rip() {
+        let dir = ScratchDir::new("cue-fallback");
+        let cue = dir.join("album.cue");
+        fs::write(
+            &cue,
+            "FILE \"album.wav\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\n\
+             FILE \"bonus.wav\" WAVE\nTRACK 02 AUDIO\nINDEX 01 00:00:00\n",
+        )
+        .unwrap();
+        fs::write(dir.join("album.flac"), b"").unwrap();
+        fs::write(dir.join("album.log"), b"").unwrap();
+
+        let sheet = load_cue_sheet(&cue).unwrap();
+        assert_eq!(sheet.files[0].path, dir.join("album.flac"));
+        // Nothing to fall back to: the sheet keeps the name it gives
+        assert_eq!(sheet.files[1].path, dir.join("bonus.wav"));
+
+        // The file named by the sheet wins when it exists
+        fs::write(dir.join("album.wav"), b"").unwrap();
+        let sheet = load_cue_sheet(&cue).unwrap();
+        assert_eq!(sheet.files[0].path, dir.join("album.wav"));
+    }
+
+    #[test]
+    fn playlists_title_untagged_files() {
+        let dir = ScratchDir::new("playlist-titles");
+        fs::write(dir.join("song.mp3"), b"").unwrap();
+        fs::write(dir.join("one-second.wav"), wav(&[0; 44_100])).unwrap();
+        let playlist = dir.join("mix.m3u");
+        fs::write(
+            &playlist,
+            "#EXTM3U\n#EXTINF:200,Mr. Blue Sky\nsong.mp3\n\
+             #EXTINF:5,Short One\none-second.wav\nmissing.mp3\n",
+        )
+        .unwrap();
+
+        let sources = [playlist.to_string_lossy().to_string()];
+        let songs = load_sources(&sources, &mut LibraryIndex::in_memory(), &[], false);
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0].tags().title.as_deref(), Some("Mr. Blue Sky"));
+        assert_eq!(songs[0].duration(), Some(Duration::from_secs(200)));
+        // The length the file gives wins over the playlist's
+        assert_eq!(songs[1].tags().title.as_deref(), Some("Short One"));
+        assert_eq!(songs[1].duration(), Some(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    metadata::{self, TrackInfo, TrackTags},
+    storage,
+    track_id::track_id,
+};
+
+const INDEX_FILE: &str = "library.json";
+
+/// What is remembered about a file between launches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    size: u64,
+    mtime_ms: u64,
+    duration_ms: Option<u64>,
+    tags: TrackTags,
+    codec: Option<String>,
+    /// When the file was first seen, in seconds since the epoch
+    added: u64,
+    file_id: Option<String>,
+}
+
+impl IndexEntry {
+    fn info(&self) -> TrackInfo {
+        TrackInfo {
+            duration: self.duration_ms.map(Duration::from_millis),
+            tags: self.tags.clone(),
+            codec: self.codec.clone(),
+            file_id: self.file_id.clone(),
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Path a file is indexed under, the same however it was reached (`music/a.mp3`,
+/// `./music/a.mp3` or through a symlink). Files already gone are resolved by their folder.
+fn key(path: &Path) -> PathBuf {
+    if let Ok(resolved) = fs::canonicalize(path) {
+        return resolved;
+    }
+    let in_parent = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            fs::canonicalize(parent).ok().map(|parent| parent.join(name))
+        }
+        _ => None,
+    };
+    in_parent
+        .or_else(|| std::path::absolute(path).ok())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Cache of probed files, so only new or modified files are probed again on startup
+pub struct LibraryIndex {
+    file: Option<PathBuf>,
+    entries: HashMap<PathBuf, IndexEntry>,
+    dirty: bool,
+}
+
+impl LibraryIndex {
+    /// Index kept in memory only, leaving the user's cache alone
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self {
+            file: None,
+            entries: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    pub fn load() -> Self {
+        let file = storage::cache_file(INDEX_FILE);
+        let entries = file.as_deref().map(storage::load_json).unwrap_or_default();
+
+        Self {
+            file,
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// Duration, tags, codec and identity of a file, probed again only if its size or mtime changed
+    pub fn probe(&mut self, path: &Path) -> TrackInfo {
+        let Ok(file_metadata) = fs::metadata(path) else {
+            return TrackInfo::default();
+        };
+        let size = file_metadata.len();
+        let mtime_ms = file_metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_millis() as u64);
+        let key = key(path);
+
+        if let Some(entry) = self.entries.get(&key)
+            && entry.size == size
+            && entry.mtime_ms == mtime_ms
+        {
+            return entry.info();
+        }
+
+        let mut info = metadata::probe(path);
+        info.file_id = track_id(path);
+        let added = self
+            .entries
+            .get(&key)
+            .map_or_else(|| unix_secs(SystemTime::now()), |entry| entry.added);
+
+        self.entries.insert(
+            key,
+            IndexEntry {
+                size,
+                mtime_ms,
+                duration_ms: info.duration.map(|d| d.as_millis() as u64),
+                tags: info.tags.clone(),
+                codec: info.codec.clone(),
+                added,
+                file_id: info.file_id.clone(),
+            },
+        );
+        self.dirty = true;
+        info
+    }
+
+    /// When a file was first seen, in seconds since the epoch
+    pub fn added(&self, path: &Path) -> Option<u64> {
+        self.entries.get(&key(path)).map(|entry| entry.added)
+    }
+
+    /// Keep what is known about a file that was moved, including when it was added
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(entry) = self.entries.remove(&key(from)) {
+            self.entries.insert(key(to), entry);
+            self.dirty = true;
+        }
+    }
+
+    /// Write the index if anything changed, forgetting files that no longer exist
+    pub fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        self.entries.retain(|path, _| path.exists());
+        if let Some(file) = &self.file {
+            let _ = storage::save_json(file, &self.entries);
+        }
+        self.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ScratchDir;
+
+    fn index() -> LibraryIndex {
+        LibraryIndex::in_memory()
+    }
+
+    /// `path` written relative to the working directory, with a leading `./`
+    fn relative(path: &Path) -> PathBuf {
+        let cwd = std::env::current_dir().unwrap();
+        let mut relative = PathBuf::from(".");
+        for _ in cwd.components().skip(1) {
+            relative.push("..");
+        }
+        relative.join(path.strip_prefix("/").unwrap())
+    }
+
+    #[test]
+    fn files_are_found_however_their_path_is_written() {
+        let scratch = ScratchDir::new("index");
+        fs::create_dir_all(scratch.join("music")).unwrap();
+        let dir = fs::canonicalize(&*scratch).unwrap();
+        let song = dir.join("music").join("song.mp3");
+        fs::write(&song, b"not really audio").unwrap();
+
+        let mut index = index();
+        index.probe(&relative(&song));
+        let added = index.added(&song);
+        assert!(added.is_some());
+        assert_eq!(index.added(&dir.join("music/./song.mp3")), added);
+        assert_eq!(index.added(&dir.join("music/../music/song.mp3")), added);
+
+        // Already known, whichever way it is reached
+        index.probe(&song);
+        index.probe(&dir.join("./music/song.mp3"));
+        assert_eq!(index.entries.len(), 1);
+
+        // Only the folder is left to resolve the old path of a moved file
+        let moved = dir.join("moved.mp3");
+        fs::rename(&song, &moved).unwrap();
+        index.rename(&dir.join("music/../music/song.mp3"), &relative(&moved));
+        assert_eq!(index.added(&moved), added);
+        assert_eq!(index.added(&song), None);
+    }
+}
@@ -0,0 +1,152 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use crate::{jukebox_state::SongItem, library};
+
+/// How long the directories must stay quiet before rescanning, so files being copied are complete
+const SETTLE_TIME: Duration = Duration::from_millis(800);
+
+/// Watches the music directories and reports when the library should be rescanned
+pub struct LibraryWatcher {
+    // Dropping the watcher stops the notifications
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    last_change: Option<Instant>,
+    /// Subdirectories are watched too
+    recursive: bool,
+}
+
+impl LibraryWatcher {
+    /// Watch the directories among the sources, with their subdirectories when `recursive`
+    /// is set. `None` if there are none or watching failed
+    pub fn new(sources: &[String], recursive: bool) -> Option<Self> {
+        let roots: Vec<&Path> = sources
+            .iter()
+            .map(Path::new)
+            .filter(|path| path.is_dir())
+            .collect();
+        if roots.is_empty() {
+            return None;
+        }
+
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).ok()?;
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        for root in roots {
+            let _ = watcher.watch(root, mode);
+        }
+
+        Some(Self {
+            _watcher: watcher,
+            events,
+            last_change: None,
+            recursive,
+        })
+    }
+
+    /// True once relevant files changed and things have settled down since
+    pub fn poll(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            if let Ok(event) = event
+                && is_relevant(&event, self.recursive)
+            {
+                self.last_change = Some(Instant::now());
+            }
+        }
+
+        match self.last_change {
+            Some(changed_at) if changed_at.elapsed() >= SETTLE_TIME => {
+                self.last_change = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn is_relevant(event: &Event, recursive: bool) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event.paths.iter().any(|path| {
+            library::is_supported(path)
+                || path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+                // Folders moved in or out, removed ones can no longer be told apart from files
+                || (recursive && (path.is_dir() || path.extension().is_none()))
+        })
+}
+
+/// Difference between two scans of the library, by song title
+#[derive(Debug, Default)]
+pub struct LibraryChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl LibraryChanges {
+    pub fn between(old: &[SongItem], new: &[SongItem]) -> Self {
+        let old_songs: HashMap<_, _> = old.iter().map(|song| (song_key(song), song)).collect();
+        let new_songs: HashMap<_, _> = new.iter().map(|song| (song_key(song), song)).collect();
+
+        let mut changes = Self::default();
+        for song in new {
+            match old_songs.get(&song_key(song)) {
+                None => changes.added.push(song.display_title()),
+                Some(&previous)
+                    if previous.clone().with_position(0) != song.clone().with_position(0) =>
+                {
+                    changes.updated.push(song.display_title())
+                }
+                Some(_) => {}
+            }
+        }
+        for song in old {
+            if !new_songs.contains_key(&song_key(song)) {
+                changes.removed.push(song.display_title());
+            }
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+
+    /// One line announcing the changes, naming the song when only one changed
+    pub fn summary(&self) -> String {
+        let groups = [
+            ("Added", &self.added),
+            ("Removed", &self.removed),
+            ("Updated", &self.updated),
+        ];
+
+        groups
+            .iter()
+            .filter(|(_, titles)| !titles.is_empty())
+            .map(|(label, titles)| match titles.as_slice() {
+                [title] => format!("{}: {}", label, title),
+                titles => format!("{} {} songs", label, titles.len()),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Songs are the same across scans when they share a file and an offset in it, or a stream URL
+pub fn song_key(song: &SongItem) -> (PathBuf, Option<String>, Duration) {
+    (
+        song.as_path().to_path_buf(),
+        song.url().map(str::to_string),
+        song.bounds().0,
+    )
+}
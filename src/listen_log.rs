@@ -0,0 +1,216 @@
+use chrono::{Datelike, Local, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{jukebox_state::SongItem, storage};
+
+const LOG_FILE: &str = "listens.jsonl";
+/// Entries in each top list
+const TOP_COUNT: usize = 10;
+
+const UNKNOWN_ARTIST: &str = "Unknown Artist";
+
+/// One playback of a song, appended to the log when it stops
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Listen {
+    /// When playback started, in seconds since the epoch
+    pub at: u64,
+    /// Time actually listened, in seconds
+    pub listened_secs: u64,
+    pub track_id: Option<String>,
+    pub path: PathBuf,
+    pub title: String,
+    #[serde(default)]
+    pub artist: Option<String>,
+    #[serde(default)]
+    pub album: Option<String>,
+}
+
+impl Listen {
+    pub fn new(song: &SongItem, at: u64, listened_secs: u64) -> Self {
+        let tags = song.tags();
+        Self {
+            at,
+            listened_secs,
+            track_id: song.track_id(),
+            path: song.as_path().to_path_buf(),
+            title: tags.title.clone().unwrap_or_else(|| song.display_title()),
+            artist: tags.artist.clone().or_else(|| song.performer().map(str::to_string)),
+            album: tags.album.clone(),
+        }
+    }
+
+    fn track_name(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} - {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+}
+
+/// Append-only log of every listen, kept in the data directory
+pub struct ListenLog {
+    file: Option<PathBuf>,
+}
+
+impl ListenLog {
+    pub fn open() -> Self {
+        Self {
+            file: storage::data_file(LOG_FILE),
+        }
+    }
+
+    /// Every listen logged so far, oldest first
+    pub fn load(&self) -> Vec<Listen> {
+        self.file
+            .as_deref()
+            .map(storage::load_json_lines)
+            .unwrap_or_default()
+    }
+
+    pub fn record(&self, listen: &Listen) {
+        if let Some(file) = &self.file {
+            // Losing a listen is not worth interrupting playback
+            let _ = storage::append_json_line(file, listen);
+        }
+    }
+}
+
+/// Time span covered by a report
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Period {
+    Week,
+    Month,
+    #[default]
+    All,
+}
+
+impl Period {
+    pub fn next(self) -> Self {
+        match self {
+            Period::Week => Period::Month,
+            Period::Month => Period::All,
+            Period::All => Period::Week,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Period::Week => "Last 7 days",
+            Period::Month => "Last 30 days",
+            Period::All => "All time",
+        }
+    }
+
+    /// First second included in the period
+    fn start(self, now: u64) -> u64 {
+        const DAY: u64 = 24 * 60 * 60;
+        match self {
+            Period::Week => now.saturating_sub(7 * DAY),
+            Period::Month => now.saturating_sub(30 * DAY),
+            Period::All => 0,
+        }
+    }
+}
+
+/// Track, artist or album in a top list
+#[derive(Debug, Clone, Serialize)]
+pub struct Ranked {
+    pub name: String,
+    pub listens: usize,
+    pub listened_secs: u64,
+}
+
+/// Listening statistics over a period
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub period: &'static str,
+    pub listens: usize,
+    pub listened_secs: u64,
+    pub top_tracks: Vec<Ranked>,
+    pub top_artists: Vec<Ranked>,
+    pub top_albums: Vec<Ranked>,
+    /// Seconds listened in each hour of the day, local time
+    pub by_hour: [u64; 24],
+    /// Seconds listened on each day of the week, Monday first
+    pub by_weekday: [u64; 7],
+}
+
+impl Report {
+    pub fn build(listens: &[Listen], period: Period, now: u64) -> Self {
+        let start = period.start(now);
+        let listens: Vec<&Listen> = listens.iter().filter(|listen| listen.at >= start).collect();
+
+        let mut by_hour = [0; 24];
+        let mut by_weekday = [0; 7];
+        for listen in &listens {
+            if let Some(time) = Local.timestamp_opt(listen.at as i64, 0).single() {
+                by_hour[time.hour() as usize] += listen.listened_secs;
+                by_weekday[time.weekday().num_days_from_monday() as usize] += listen.listened_secs;
+            }
+        }
+
+        Self {
+            period: period.label(),
+            listens: listens.len(),
+            listened_secs: listens.iter().map(|listen| listen.listened_secs).sum(),
+            // Tracks are told apart by identity, so renamed files keep their history
+            top_tracks: top(&listens, |listen| {
+                let key = listen
+                    .track_id
+                    .clone()
+                    .unwrap_or_else(|| listen.path.display().to_string());
+                Some((key, listen.track_name()))
+            }),
+            top_artists: top(&listens, |listen| {
+                let artist = listen.artist.as_deref().unwrap_or(UNKNOWN_ARTIST);
+                Some((artist.to_lowercase(), artist.to_string()))
+            }),
+            top_albums: top(&listens, |listen| {
+                let album = listen.album.as_deref()?;
+                let artist = listen.artist.as_deref().unwrap_or(UNKNOWN_ARTIST);
+                let key = format!("{}/{}", artist, album).to_lowercase();
+                Some((key, format!("{} ({})", album, artist)))
+            }),
+            by_hour,
+            by_weekday,
+        }
+    }
+}
+
+/// Most listened entries, grouped by the key and named as `group` says
+fn top(listens: &[&Listen], group: impl Fn(&Listen) -> Option<(String, String)>) -> Vec<Ranked> {
+    let mut groups: HashMap<String, Ranked> = HashMap::new();
+    for listen in listens {
+        let Some((key, name)) = group(listen) else {
+            continue;
+        };
+        let entry = groups.entry(key).or_insert(Ranked {
+            name,
+            listens: 0,
+            listened_secs: 0,
+        });
+        entry.listens += 1;
+        entry.listened_secs += listen.listened_secs;
+    }
+
+    let mut ranked: Vec<Ranked> = groups.into_values().collect();
+    ranked.sort_by(|a, b| {
+        b.listens
+            .cmp(&a.listens)
+            .then(b.listened_secs.cmp(&a.listened_secs))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    ranked.truncate(TOP_COUNT);
+    ranked
+}
+
+/// Listening time as `1h 05m` or `12m`
+pub fn format_hours(secs: u64) -> String {
+    let minutes = secs / 60;
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
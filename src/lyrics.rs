@@ -0,0 +1,308 @@
+use id3::{Tag, frame::TimestampFormat};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+
+use crate::{jukebox_state::SongItem, metadata};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LyricLine {
+    /// When the line starts, `None` for unsynchronised lyrics
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Lyrics {
+    /// Ordered by time when synchronised
+    pub lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+    pub fn is_synced(&self) -> bool {
+        self.lines.first().is_some_and(|line| line.time.is_some())
+    }
+
+    /// Index of the line being sung at `position`
+    pub fn current_line(&self, position: Duration) -> Option<usize> {
+        if !self.is_synced() {
+            return None;
+        }
+        self.lines
+            .iter()
+            .rposition(|line| line.time.is_some_and(|time| time <= position))
+    }
+
+    /// Parse LRC lyrics (`[mm:ss.xx]text`), or plain text when there are no timestamps
+    pub fn parse(content: &str) -> Self {
+        let mut offset_ms: i64 = 0;
+        let mut synced = Vec::new();
+        let mut plain = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            // `[offset:+500]` shifts every line, positive values make them come earlier
+            if let Some(value) = line
+                .strip_prefix("[offset:")
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                offset_ms = value.trim().parse().unwrap_or(0);
+                continue;
+            }
+
+            let mut rest = line;
+            let mut times = Vec::new();
+            while let Some(tag) = rest.strip_prefix('[')
+                && let Some(end) = tag.find(']')
+            {
+                match parse_timestamp(&tag[..end]) {
+                    Some(time) => times.push(time),
+                    // Other tags such as `[ar:Artist]` are not lyrics
+                    None if times.is_empty() => break,
+                    None => {}
+                }
+                rest = &tag[end + 1..];
+            }
+
+            if times.is_empty() {
+                if !line.starts_with('[') {
+                    plain.push(LyricLine {
+                        time: None,
+                        text: line.to_string(),
+                    });
+                }
+                continue;
+            }
+            for time in times {
+                synced.push(LyricLine {
+                    time: Some(time),
+                    text: rest.trim().to_string(),
+                });
+            }
+        }
+
+        if synced.is_empty() {
+            // Drop leading and trailing blank lines
+            while plain.last().is_some_and(|line| line.text.is_empty()) {
+                plain.pop();
+            }
+            let first = plain.iter().position(|line| !line.text.is_empty());
+            return Self {
+                lines: plain.split_off(first.unwrap_or(plain.len())),
+            };
+        }
+
+        for line in &mut synced {
+            line.time = line.time.map(|time| shift(time, -offset_ms));
+        }
+        synced.sort_by_key(|line| line.time);
+        Self { lines: synced }
+    }
+}
+
+fn shift(time: Duration, ms: i64) -> Duration {
+    let shifted = time.as_millis() as i64 + ms;
+    Duration::from_millis(shifted.max(0) as u64)
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss.xxx`
+fn parse_timestamp(tag: &str) -> Option<Duration> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().replace(':', ".").parse().ok()?;
+    if !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+    Some(Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+enum Entry {
+    Loading,
+    Loaded(Option<Arc<Lyrics>>),
+}
+
+/// Lyrics loaded in the background and kept in memory, by file
+#[derive(Default)]
+pub struct LyricsCache {
+    entries: Arc<Mutex<HashMap<PathBuf, Entry>>>,
+}
+
+impl LyricsCache {
+    /// Lyrics of a song if loaded, starting to load them on the first request
+    pub fn get(&self, song: &SongItem) -> Option<Arc<Lyrics>> {
+        if song.is_stream() {
+            return None;
+        }
+
+        let path = song.as_path().to_path_buf();
+        let mut entries = self.entries.lock().ok()?;
+        match entries.get(&path) {
+            Some(Entry::Loaded(lyrics)) => return lyrics.clone(),
+            Some(Entry::Loading) => return None,
+            None => {}
+        }
+        entries.insert(path.clone(), Entry::Loading);
+
+        let shared = self.entries.clone();
+        thread::spawn(move || {
+            let lyrics = load(&path).map(Arc::new);
+            if let Ok(mut entries) = shared.lock() {
+                entries.insert(path, Entry::Loaded(lyrics));
+            }
+        });
+        None
+    }
+}
+
+/// A `.lrc` file next to the audio file, then SYLT frames, then the lyrics tag
+fn load(path: &Path) -> Option<Lyrics> {
+    let lyrics = fs::read_to_string(path.with_extension("lrc"))
+        .ok()
+        .map(|content| Lyrics::parse(&content))
+        .filter(|lyrics| !lyrics.lines.is_empty())
+        .or_else(|| read_sylt(path))
+        .or_else(|| lyrics_tag(path).map(|content| Lyrics::parse(&content)))?;
+
+    (!lyrics.lines.is_empty()).then_some(lyrics)
+}
+
+/// USLT frame, Vorbis `LYRICS` comment or MP4 `©lyr` atom
+fn lyrics_tag(path: &Path) -> Option<String> {
+    let mut probed = metadata::open_format(path)?;
+
+    let find = |revision: &MetadataRevision| {
+        revision
+            .tags()
+            .iter()
+            .find(|tag| tag.std_key == Some(StandardTagKey::Lyrics))
+            .map(|tag| tag.value.to_string())
+    };
+    let from_container = probed.format.metadata().current().and_then(find);
+    from_container.or_else(|| probed.metadata.get()?.current().and_then(find))
+}
+
+/// Synchronised lyrics from an ID3v2 SYLT frame, which symphonia does not read
+fn read_sylt(path: &Path) -> Option<Lyrics> {
+    let tag = Tag::read_from_path(path).ok()?;
+    // Only millisecond timestamps, MPEG frame timestamps are rare
+    let sylt = tag
+        .synchronised_lyrics()
+        .find(|sylt| sylt.timestamp_format == TimestampFormat::Ms)?;
+
+    let mut lines: Vec<LyricLine> = sylt
+        .content
+        .iter()
+        .map(|(ms, text)| LyricLine {
+            time: Some(Duration::from_millis(*ms as u64)),
+            // Lines often start with a newline marking a new line of the song
+            text: text.trim().to_string(),
+        })
+        .collect();
+    lines.sort_by_key(|line| line.time);
+    Some(Lyrics { lines })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use id3::{
+        TagLike, Version,
+        frame::{SynchronisedLyrics, SynchronisedLyricsType},
+    };
+    use crate::testing::ScratchDir;
+
+    fn line(ms: u64, text: &str) -> LyricLine {
+        LyricLine {
+            time: Some(Duration::from_millis(ms)),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_timestamped_lines_in_order() {
+        let lyrics = Lyrics::parse(
+            "[ar:Artist]\n[ti:Title]\n\
+             [00:12.50]Second\n\
+             [00:01.00] First \n\
+             [01:02.345]Third\n\
+             [00:20]\n",
+        );
+        assert!(lyrics.is_synced());
+        assert_eq!(
+            lyrics.lines,
+            [line(1000, "First"), line(12_500, "Second"), line(20_000, ""), line(62_345, "Third")]
+        );
+    }
+
+    #[test]
+    fn repeated_timestamps_repeat_the_line() {
+        let lyrics = Lyrics::parse("[00:10.00][00:30.00]Chorus\n[00:20.00]Verse");
+        assert_eq!(
+            lyrics.lines,
+            [line(10_000, "Chorus"), line(20_000, "Verse"), line(30_000, "Chorus")]
+        );
+    }
+
+    #[test]
+    fn offset_shifts_every_line() {
+        let lyrics = Lyrics::parse("[offset:+500]\n[00:00.20]Early\n[00:02.00]Late");
+        assert_eq!(lyrics.lines, [line(0, "Early"), line(1500, "Late")]);
+
+        let lyrics = Lyrics::parse("[offset:-250]\n[00:01.00]Line");
+        assert_eq!(lyrics.lines, [line(1250, "Line")]);
+    }
+
+    #[test]
+    fn text_without_timestamps_is_plain_lyrics() {
+        let lyrics = Lyrics::parse("\n\n[ar:Artist]\nFirst line\n\nSecond line\n\n");
+        assert!(!lyrics.is_synced());
+        let texts: Vec<&str> = lyrics.lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, ["First line", "", "Second line"]);
+        assert_eq!(lyrics.current_line(Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn invalid_timestamps_are_not_lyrics() {
+        assert_eq!(parse_timestamp("01:02.5"), Some(Duration::from_millis(62_500)));
+        assert_eq!(parse_timestamp("1:02:50"), Some(Duration::from_millis(62_500)));
+        assert_eq!(parse_timestamp("00:60.00"), None);
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+        assert_eq!(parse_timestamp("0102"), None);
+        assert!(Lyrics::parse("[xx:yy]Text").lines.is_empty());
+    }
+
+    #[test]
+    fn current_line_follows_the_position() {
+        let lyrics = Lyrics::parse("[00:01.00]One\n[00:03.00]Two");
+        assert_eq!(lyrics.current_line(Duration::from_millis(500)), None);
+        assert_eq!(lyrics.current_line(Duration::from_secs(1)), Some(0));
+        assert_eq!(lyrics.current_line(Duration::from_secs(10)), Some(1));
+    }
+
+    #[test]
+    fn reads_sylt_frames_and_prefers_lrc_files() {
+        let dir = ScratchDir::new("lyrics");
+        let path = dir.join("song.mp3");
+        fs::copy("example_music/intensity-by-audio-club-343637.mp3", &path).unwrap();
+
+        let mut tag = Tag::new();
+        tag.add_frame(SynchronisedLyrics {
+            lang: "eng".to_string(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: vec![(2500, "\nSecond".to_string()), (1000, "First".to_string())],
+        });
+        tag.write_to_path(&path, Version::Id3v24).unwrap();
+        assert_eq!(load(&path).unwrap().lines, [line(1000, "First"), line(2500, "Second")]);
+
+        fs::write(path.with_extension("lrc"), "[00:05.00]From the file").unwrap();
+        assert_eq!(load(&path).unwrap().lines, [line(5000, "From the file")]);
+    }
+}
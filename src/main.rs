@@ -1,34 +1,252 @@
 use ratatui::{prelude::*};
 use crossterm::{execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
-use std::{io, error::Error, path::PathBuf};
-use clap::Parser;
+use std::{io, error::Error, path::PathBuf, time::Duration};
+use clap::{Parser, Subcommand};
 
-use crate::screen::main_loop::run_app;
+use crate::{
+    config::Config,
+    listen_log::Period,
+    path_pattern::PathPattern,
+    scrobbler::ScrobbleConfig,
+    screen::main_loop::run_app,
+};
 
 mod jukebox_state;
 mod canvas_state;
 mod screen;
+mod config;
+mod storage;
+mod track_id;
+mod bookmarks;
+mod audio;
+mod cue;
+mod library;
+mod playlist_file;
+mod stream;
+mod spectrum;
+mod beat;
+mod waveform;
+mod level_meter;
+mod metadata;
+mod library_index;
+mod library_watcher;
+mod sort;
+mod search;
+mod browser;
+mod cover_art;
+mod lyrics;
+mod saved_playlists;
+mod query;
+mod cli;
+mod play_stats;
+mod listen_log;
+mod scrobbler;
+mod tag_writer;
+mod ogg_tags;
+mod mp4_tags;
+mod tag_editor;
+mod path_pattern;
+mod organizer;
+mod duplicates;
+mod check;
+#[cfg(test)]
+mod testing;
 
 #[derive(Parser)]
 #[command(name = "jukebox-cli")]
 #[command(about = "A terminal-based music jukebox application")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Path to the music directory
-    #[arg(help = "Directory containing MP3 files. If not provided, defaults to a sample directory.")]
-    path: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Music directories, audio files, playlists or stream URLs
+    #[arg(help = "Directories containing audio files (MP3, FLAC, OGG, WAV, M4A) and CUE sheets, M3U/PLS playlists or http:// stream URLs. If not provided, defaults to a sample directory.")]
+    sources: Vec<String>,
+
+    /// Tracks at least this long (in minutes) remember where playback was left
+    #[arg(long, default_value_t = 20, value_name = "MINUTES")]
+    bookmark_threshold: u64,
+
+    /// ListenBrainz user token, listens are submitted when set
+    #[arg(long, env = "LISTENBRAINZ_TOKEN", hide_env_values = true, value_name = "TOKEN")]
+    listenbrainz_token: Option<String>,
+
+    /// Root URL of the ListenBrainz compatible API to submit listens to
+    #[arg(long, env = "LISTENBRAINZ_URL", default_value = scrobbler::DEFAULT_URL, value_name = "URL")]
+    listenbrainz_url: String,
+
+    /// Guess missing tags from the file path, e.g. "{artist}/{album}/{track} - {title}".
+    /// Repeat for several patterns, the first one the path fits is used
+    #[arg(long = "pattern", value_name = "PATTERN")]
+    patterns: Vec<PathPattern>,
+
+    /// Read the subdirectories of the music directories too
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Layout offered when organizing the music directories from the jukebox (`O`)
+    #[arg(long, default_value = organizer::DEFAULT_PATTERN, value_name = "PATTERN")]
+    organize_pattern: PathPattern,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the songs matching a query, such as `artist:"Daft Punk" duration<5m year>=2000 !genre:live`
+    Query {
+        /// Terms as field:value, field<value, ... (fields: title, artist, album, albumartist,
+        /// genre, codec, path, year, track, duration), `!` negates a term, `OR` separates alternatives
+        query: String,
+
+        /// Music directories, audio files or playlists to search, defaults to the sample directory
+        sources: Vec<String>,
+
+        /// Save the matches as a playlist (.m3u8, .pls or .xspf) instead of printing their paths
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Guess missing tags from the file path, as for the jukebox
+        #[arg(long = "pattern", value_name = "PATTERN")]
+        patterns: Vec<PathPattern>,
+
+        /// Read the subdirectories of the music directories too
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Show the tags the --pattern options guess from the path of each file
+    Patterns {
+        /// Music directories, audio files or playlists to look at, defaults to the sample directory
+        sources: Vec<String>,
+
+        /// Pattern to try, e.g. "{artist}/{album}/{track} - {title}", repeat for several
+        #[arg(long = "pattern", value_name = "PATTERN")]
+        patterns: Vec<PathPattern>,
+
+        /// Read the subdirectories of the music directories too
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Move the files of music directories to folders and names made from their tags,
+    /// keeping saved playlists, bookmarks and the library index pointing to them
+    Organize {
+        /// Music directories to organize, subdirectories included
+        #[arg(required = true)]
+        sources: Vec<String>,
+
+        /// Path of each file in its music directory, the extension is kept
+        #[arg(long, default_value = organizer::DEFAULT_PATTERN, value_name = "PATTERN")]
+        pattern: PathPattern,
+
+        /// Print the moves without making them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Guess the tags files lack from their current path first, like --pattern for the jukebox
+        #[arg(long, value_name = "PATTERN")]
+        tags_from: Vec<PathPattern>,
+    },
+    /// List the files holding the same track: identical audio, or the same artist and title
+    /// with about the same length. Duplicates are moved to quarantine from the jukebox (`D`)
+    Duplicates {
+        /// Music directories, audio files or playlists to look at, defaults to the sample directory
+        sources: Vec<String>,
+
+        /// Guess missing tags from the file path, as for the jukebox
+        #[arg(long = "pattern", value_name = "PATTERN")]
+        patterns: Vec<PathPattern>,
+
+        /// Read the subdirectories of the music directories too
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Decode every file in full and report those that cannot be played to the end: decode
+    /// errors, truncated or empty audio, missing durations and extensions not matching the
+    /// content. Exits with an error when any file has problems
+    Check {
+        /// Music directories, audio files or playlists to check
+        #[arg(required = true)]
+        sources: Vec<String>,
+
+        /// Print a report of every file as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Read the subdirectories of the music directories too
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Show top tracks, artists and albums and the total listening time from the listen log
+    Stats {
+        /// Time span to report on
+        #[arg(long, value_enum, default_value_t = Period::All)]
+        period: Period,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
-    let music_path = args.path;
-    
+    if let Some(command) = args.command {
+        let result = match command {
+            Command::Query {
+                query,
+                sources,
+                output,
+                patterns,
+                recursive,
+            } => cli::query(&query, &sources, &patterns, recursive, output.as_deref()),
+            Command::Patterns {
+                sources,
+                patterns,
+                recursive,
+            } => cli::patterns(&sources, &patterns, recursive),
+            Command::Organize {
+                sources,
+                pattern,
+                dry_run,
+                tags_from,
+            } => cli::organize(&sources, &pattern, &tags_from, dry_run),
+            Command::Duplicates {
+                sources,
+                patterns,
+                recursive,
+            } => cli::duplicates(&sources, &patterns, recursive),
+            Command::Check {
+                sources,
+                json,
+                recursive,
+            } => cli::check(&sources, recursive, json),
+            Command::Stats { period, json } => cli::stats(period, json),
+        };
+        if let Err(err) = result {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let sources = args.sources;
+    let config = Config {
+        bookmark_threshold: Duration::from_secs(args.bookmark_threshold * 60),
+        scrobble: args.listenbrainz_token.map(|token| ScrobbleConfig {
+            url: args.listenbrainz_url,
+            token,
+        }),
+        patterns: args.patterns,
+        recursive: args.recursive,
+        organize_pattern: args.organize_pattern,
+    };
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let res = run_app(&mut terminal, music_path);
+    let res = run_app(&mut terminal, sources, &config);
 
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
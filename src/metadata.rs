@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::{fs::File, path::Path, time::Duration};
+use symphonia::core::{
+    io::MediaSourceStream,
+    meta::{MetadataRevision, StandardTagKey},
+    probe::{Hint, ProbeResult},
+};
+
+/// Tags read from ID3v2, Vorbis comments or MP4 atoms
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+}
+
+impl TrackTags {
+    /// Fill the fields still missing from `other`, such as tags guessed from the path
+    pub fn fill_missing(&mut self, other: TrackTags) {
+        self.title = self.title.take().or(other.title);
+        self.artist = self.artist.take().or(other.artist);
+        self.album = self.album.take().or(other.album);
+        self.album_artist = self.album_artist.take().or(other.album_artist);
+        self.track_number = self.track_number.or(other.track_number);
+        self.year = self.year.or(other.year);
+        self.genre = self.genre.take().or(other.genre);
+    }
+
+    /// Fill missing fields from a metadata revision, earlier revisions win
+    fn merge(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let Some(key) = tag.std_key else {
+                continue;
+            };
+            let value = tag.value.to_string().trim().to_string();
+            if value.is_empty() {
+                continue;
+            }
+
+            match key {
+                StandardTagKey::TrackTitle => set_if_none(&mut self.title, value),
+                StandardTagKey::Artist => set_if_none(&mut self.artist, value),
+                StandardTagKey::Album => set_if_none(&mut self.album, value),
+                StandardTagKey::AlbumArtist => set_if_none(&mut self.album_artist, value),
+                StandardTagKey::Genre => set_if_none(&mut self.genre, value),
+                // "3/12" style track numbers
+                StandardTagKey::TrackNumber => {
+                    let number = value.split('/').next().and_then(|n| n.trim().parse().ok());
+                    self.track_number = self.track_number.or(number);
+                }
+                // Dates can be "2001", "2001-05-12"...
+                StandardTagKey::Date | StandardTagKey::OriginalDate => {
+                    let year = value.get(..4).and_then(|y| y.parse().ok());
+                    self.year = self.year.or(year);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn set_if_none(field: &mut Option<String>, value: String) {
+    if field.is_none() {
+        *field = Some(value);
+    }
+}
+
+/// Everything learned by probing an audio file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackInfo {
+    pub duration: Option<Duration>,
+    pub tags: TrackTags,
+    pub codec: Option<String>,
+    /// Content identity of the file (see `track_id`), filled in by the library index
+    pub file_id: Option<String>,
+}
+
+/// Probe an audio file with symphonia for its duration, tags and codec
+pub fn probe(path: &Path) -> TrackInfo {
+    probe_file(path).unwrap_or_default()
+}
+
+/// Open a file with the symphonia probe, hinted by its extension
+pub fn open_format(path: &Path) -> Option<ProbeResult> {
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .ok()
+}
+
+fn probe_file(path: &Path) -> Option<TrackInfo> {
+    let mut probed = open_format(path)?;
+
+    let mut info = TrackInfo::default();
+
+    // Container metadata (Vorbis comments, MP4 atoms) first, then ID3 tags read while probing
+    if let Some(revision) = probed.format.metadata().current() {
+        info.tags.merge(revision);
+    }
+    if let Some(metadata) = probed.metadata.get()
+        && let Some(revision) = metadata.current()
+    {
+        info.tags.merge(revision);
+    }
+
+    if let Some(track) = probed.format.tracks().iter().next() {
+        info.codec = symphonia::default::get_codecs()
+            .get_codec(track.codec_params.codec)
+            .map(|codec| codec.short_name.to_string());
+
+        if let Some(time_base) = track.codec_params.time_base
+            && let Some(n_frames) = track.codec_params.n_frames
+        {
+            let duration_secs = n_frames as f64 / time_base.denom as f64 * time_base.numer as f64;
+            info.duration = Some(Duration::from_secs_f64(duration_secs));
+        }
+    }
+
+    Some(info)
+}
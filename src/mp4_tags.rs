@@ -0,0 +1,327 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+use crate::{
+    metadata::TrackTags,
+    tag_writer::{invalid, read_full},
+};
+
+/// Atoms holding other atoms on the way to the tags and the chunk offsets
+const CONTAINERS: [&[u8; 4]; 8] =
+    [b"moov", b"trak", b"mdia", b"minf", b"stbl", b"udta", b"meta", b"ilst"];
+
+const TITLE: &[u8; 4] = b"\xa9nam";
+const ARTIST: &[u8; 4] = b"\xa9ART";
+const ALBUM: &[u8; 4] = b"\xa9alb";
+const TRACK: &[u8; 4] = b"trkn";
+const YEAR: &[u8; 4] = b"\xa9day";
+const GENRE: &[u8; 4] = b"\xa9gen";
+/// ID3v1 genre number, replaced by the text genre
+const GENRE_ID: &[u8; 4] = b"gnre";
+
+/// `data` atom type of UTF-8 text, track numbers use the implicit type 0
+const UTF8: u32 = 1;
+
+/// Atom tree, only the containers listed above are opened
+struct Atom {
+    kind: [u8; 4],
+    /// Version and flags of `meta`, which is a full box in MP4 files but not in QuickTime
+    prefix: Vec<u8>,
+    content: Content,
+}
+
+enum Content {
+    Data(Vec<u8>),
+    Children(Vec<Atom>),
+}
+
+impl Atom {
+    fn container(kind: &[u8; 4], prefix: Vec<u8>, children: Vec<Atom>) -> Self {
+        Self {
+            kind: *kind,
+            prefix,
+            content: Content::Children(children),
+        }
+    }
+
+    fn data(kind: &[u8; 4], data: Vec<u8>) -> Self {
+        Self {
+            kind: *kind,
+            prefix: Vec::new(),
+            content: Content::Data(data),
+        }
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Vec<Atom>> {
+        match &mut self.content {
+            Content::Children(children) => Some(children),
+            Content::Data(_) => None,
+        }
+    }
+
+    /// Child of this kind, created by `make` when missing
+    fn child_or_insert(&mut self, kind: &[u8; 4], make: impl FnOnce() -> Atom) -> &mut Atom {
+        let children = self.children_mut().expect("container atom");
+        let index = match children.iter().position(|child| &child.kind == kind) {
+            Some(index) => index,
+            None => {
+                children.push(make());
+                children.len() - 1
+            }
+        };
+        &mut children[index]
+    }
+
+    fn to_bytes(&self, out: &mut Vec<u8>) {
+        let mut body = self.prefix.clone();
+        match &self.content {
+            Content::Data(data) => body.extend(data),
+            Content::Children(children) => {
+                for child in children {
+                    child.to_bytes(&mut body);
+                }
+            }
+        }
+        write_header(out, &self.kind, body.len());
+        out.extend(body);
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, kind: &[u8; 4], body_len: usize) {
+    match u32::try_from(body_len + 8) {
+        Ok(size) => {
+            out.extend(size.to_be_bytes());
+            out.extend(kind);
+        }
+        // 64 bit size, after the type
+        Err(_) => {
+            out.extend(1u32.to_be_bytes());
+            out.extend(kind);
+            out.extend((body_len as u64 + 16).to_be_bytes());
+        }
+    }
+}
+
+/// Atom located in the file, not parsed yet
+struct RawAtom<'a> {
+    kind: [u8; 4],
+    body: &'a [u8],
+}
+
+fn split_atoms(data: &[u8]) -> Option<Vec<RawAtom<'_>>> {
+    let mut atoms = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let size = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as u64;
+        let kind: [u8; 4] = data.get(pos + 4..pos + 8)?.try_into().ok()?;
+        let (header, size) = match size {
+            0 => (8, (data.len() - pos) as u64),
+            1 => (16, u64::from_be_bytes(data.get(pos + 8..pos + 16)?.try_into().ok()?)),
+            size => (8, size),
+        };
+        let end = pos.checked_add(usize::try_from(size).ok()?)?;
+        atoms.push(RawAtom {
+            kind,
+            body: data.get(pos + header..end)?,
+        });
+        pos = end;
+    }
+    Some(atoms)
+}
+
+/// Kind and place of the atoms at the top of the file, found from their headers alone
+fn top_level_atoms<R: Read + Seek>(input: &mut R) -> io::Result<Vec<([u8; 4], Range<u64>)>> {
+    let unreadable = || invalid("unreadable MP4 atoms");
+    let len = input.seek(SeekFrom::End(0))?;
+    let mut atoms = Vec::new();
+    let mut pos = 0;
+    while pos < len {
+        input.seek(SeekFrom::Start(pos))?;
+        let mut size = [0; 4];
+        let mut kind = [0; 4];
+        if !read_full(input, &mut size)? || !read_full(input, &mut kind)? {
+            return Err(unreadable());
+        }
+        let size = match u32::from_be_bytes(size) {
+            0 => len - pos,
+            1 => {
+                let mut large_size = [0; 8];
+                if !read_full(input, &mut large_size)? {
+                    return Err(unreadable());
+                }
+                u64::from_be_bytes(large_size)
+            }
+            size => size as u64,
+        };
+        let end = pos.checked_add(size).filter(|&end| end <= len).ok_or_else(unreadable)?;
+        atoms.push((kind, pos..end));
+        pos = end;
+    }
+    Ok(atoms)
+}
+
+fn parse_atoms(data: &[u8]) -> Option<Vec<Atom>> {
+    split_atoms(data)?
+        .into_iter()
+        .map(|RawAtom { kind, body, .. }| {
+            if !CONTAINERS.contains(&&kind) {
+                return Some(Atom::data(&kind, body.to_vec()));
+            }
+            // A `meta` full box starts with version and flags, a QuickTime one with its children
+            let full_box = &kind == b"meta" && body.get(4..8) != Some(b"hdlr");
+            let prefix_len = if full_box { 4 } else { 0 };
+            let children = parse_atoms(body.get(prefix_len..)?)?;
+            Some(Atom::container(&kind, body[..prefix_len].to_vec(), children))
+        })
+        .collect()
+}
+
+/// Copy an MP4 file with its `ilst` tags rewritten.
+///
+/// Only the `moov` atom changes. When it comes before the media data, the
+/// chunk offsets it holds are shifted by its change in size.
+pub fn copy_with_tags<R: Read + Seek>(
+    input: &mut R,
+    output: &mut impl Write,
+    tags: &TrackTags,
+) -> io::Result<()> {
+    let atoms = top_level_atoms(input)?;
+    if atoms.first().is_none_or(|(kind, _)| kind != b"ftyp") {
+        return Err(invalid("not an MP4 file"));
+    }
+    let (_, moov_range) = atoms
+        .into_iter()
+        .find(|(kind, _)| kind == b"moov")
+        .ok_or_else(|| invalid("missing moov atom"))?;
+
+    let mut moov_atom = vec![0; (moov_range.end - moov_range.start) as usize];
+    input.seek(SeekFrom::Start(moov_range.start))?;
+    input.read_exact(&mut moov_atom)?;
+    let moov_body = split_atoms(&moov_atom)
+        .and_then(|atoms| atoms.into_iter().next())
+        .ok_or_else(|| invalid("unreadable moov atom"))?
+        .body;
+    let children = parse_atoms(moov_body).ok_or_else(|| invalid("unreadable moov atom"))?;
+    let mut moov = Atom::container(b"moov", Vec::new(), children);
+    set_tags(&mut moov, tags);
+
+    let mut new_moov = Vec::new();
+    moov.to_bytes(&mut new_moov);
+    let shift = new_moov.len() as i64 - moov_atom.len() as i64;
+    if shift != 0 {
+        shift_chunk_offsets(&mut moov, moov_range.end, shift)?;
+        new_moov.clear();
+        moov.to_bytes(&mut new_moov);
+    }
+
+    input.seek(SeekFrom::Start(0))?;
+    io::copy(&mut input.take(moov_range.start), output)?;
+    output.write_all(&new_moov)?;
+    input.seek(SeekFrom::Start(moov_range.end))?;
+    io::copy(input, output)?;
+    Ok(())
+}
+
+/// Replace the edited items of `moov/udta/meta/ilst`, creating the path when missing
+fn set_tags(moov: &mut Atom, tags: &TrackTags) {
+    let meta = moov
+        .child_or_insert(b"udta", || Atom::container(b"udta", Vec::new(), Vec::new()))
+        .child_or_insert(b"meta", || {
+            Atom::container(b"meta", vec![0; 4], vec![Atom::data(b"hdlr", metadata_handler())])
+        });
+    let ilst = meta.child_or_insert(b"ilst", || Atom::container(b"ilst", Vec::new(), Vec::new()));
+    let items = ilst.children_mut().expect("container atom");
+
+    // The total number of tracks is not edited, keep it
+    let total_tracks = items
+        .iter()
+        .find(|item| &item.kind == TRACK)
+        .and_then(|item| match &item.content {
+            Content::Data(data) => data.get(20..22).map(|total| [total[0], total[1]]),
+            Content::Children(_) => None,
+        })
+        .unwrap_or_default();
+
+    let edited = [TITLE, ARTIST, ALBUM, TRACK, YEAR, GENRE, GENRE_ID];
+    items.retain(|item| !edited.contains(&&item.kind));
+
+    let texts = [
+        (TITLE, tags.title.clone()),
+        (ARTIST, tags.artist.clone()),
+        (ALBUM, tags.album.clone()),
+        (YEAR, tags.year.map(|year| year.to_string())),
+        (GENRE, tags.genre.clone()),
+    ];
+    for (kind, text) in texts {
+        if let Some(text) = text {
+            items.push(Atom::data(kind, data_atom(UTF8, text.as_bytes())));
+        }
+    }
+    if let Some(track) = tags.track_number {
+        let track = u16::try_from(track).unwrap_or(u16::MAX).to_be_bytes();
+        let value = [0, 0, track[0], track[1], total_tracks[0], total_tracks[1], 0, 0];
+        items.push(Atom::data(TRACK, data_atom(0, &value)));
+    }
+}
+
+/// `hdlr` body announcing iTunes style metadata
+fn metadata_handler() -> Vec<u8> {
+    let mut body = vec![0; 8];
+    body.extend(b"mdir");
+    body.extend(b"appl");
+    body.extend([0; 9]);
+    body
+}
+
+/// Body of an `ilst` item: a single `data` atom
+fn data_atom(data_type: u32, value: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_header(&mut body, b"data", 8 + value.len());
+    body.extend(data_type.to_be_bytes());
+    // Locale
+    body.extend([0; 4]);
+    body.extend(value);
+    body
+}
+
+/// Move the `stco`/`co64` chunk offsets pointing past `moov_end` by `shift` bytes
+fn shift_chunk_offsets(atom: &mut Atom, moov_end: u64, shift: i64) -> io::Result<()> {
+    let kind = atom.kind;
+    match &mut atom.content {
+        Content::Children(children) => {
+            for child in children {
+                shift_chunk_offsets(child, moov_end, shift)?;
+            }
+        }
+        Content::Data(data) if &kind == b"stco" || &kind == b"co64" => {
+            let width = if &kind == b"stco" { 4 } else { 8 };
+            let count = data
+                .get(4..8)
+                .map(|count| u32::from_be_bytes([count[0], count[1], count[2], count[3]]) as usize)
+                .ok_or_else(|| invalid("truncated chunk offsets"))?;
+            let entries = data
+                .get_mut(8..8 + count * width)
+                .ok_or_else(|| invalid("truncated chunk offsets"))?;
+            for entry in entries.chunks_exact_mut(width) {
+                let mut bytes = [0; 8];
+                bytes[8 - width..].copy_from_slice(entry);
+                let offset = u64::from_be_bytes(bytes);
+                if offset < moov_end {
+                    continue;
+                }
+                let offset = offset.saturating_add_signed(shift);
+                if width == 4 {
+                    let offset = u32::try_from(offset)
+                        .map_err(|_| invalid("chunk offsets too large to move"))?;
+                    entry.copy_from_slice(&offset.to_be_bytes());
+                } else {
+                    entry.copy_from_slice(&offset.to_be_bytes());
+                }
+            }
+        }
+        Content::Data(_) => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{
+    metadata::TrackTags,
+    tag_writer::{VorbisComments, invalid, read_full},
+};
+
+const CAPTURE_PATTERN: &[u8] = b"OggS";
+const PAGE_HEADER_LEN: usize = 27;
+/// Header type flags
+const CONTINUED: u8 = 0x01;
+const FIRST_PAGE: u8 = 0x02;
+/// Granule position of a page on which no packet ends
+const NO_GRANULE: u64 = u64::MAX;
+const MAX_SEGMENTS: usize = 255;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// CRC-32 of Ogg pages: polynomial 0x04c11db7, no reflection, no final xor
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc(page: &[u8]) -> u32 {
+    page.iter().fold(0, |crc, &byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    })
+}
+
+/// One page of the file, borrowed from it
+struct Page<'a> {
+    header_type: u8,
+    serial: u32,
+    sequence: u32,
+    /// Lacing values, one per segment
+    segments: &'a [u8],
+    body: &'a [u8],
+    /// The whole page, header included
+    bytes: &'a [u8],
+}
+
+impl<'a> Page<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let header = data.get(..PAGE_HEADER_LEN)?;
+        if &header[..4] != CAPTURE_PATTERN {
+            return None;
+        }
+        let count = header[26] as usize;
+        let segments = data.get(PAGE_HEADER_LEN..PAGE_HEADER_LEN + count)?;
+        let body_len: usize = segments.iter().map(|&len| len as usize).sum();
+        let body_start = PAGE_HEADER_LEN + count;
+        let body = data.get(body_start..body_start + body_len)?;
+
+        Some(Self {
+            header_type: header[5],
+            serial: u32::from_le_bytes(header[14..18].try_into().ok()?),
+            sequence: u32::from_le_bytes(header[18..22].try_into().ok()?),
+            segments,
+            body,
+            bytes: &data[..body_start + body_len],
+        })
+    }
+
+    /// The same page with another sequence number and its checksum updated
+    fn renumbered(&self, sequence: u32) -> Vec<u8> {
+        let mut page = self.bytes.to_vec();
+        page[18..22].copy_from_slice(&sequence.to_le_bytes());
+        page[22..26].fill(0);
+        let checksum = crc(&page);
+        page[22..26].copy_from_slice(&checksum.to_le_bytes());
+        page
+    }
+}
+
+/// Bytes of the next page, `None` where the stream ends or holds no whole page
+fn read_page(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut page = vec![0; PAGE_HEADER_LEN];
+    if !read_full(input, &mut page)? || &page[..4] != CAPTURE_PATTERN {
+        return Ok(None);
+    }
+    let count = page[26] as usize;
+    page.resize(PAGE_HEADER_LEN + count, 0);
+    if !read_full(input, &mut page[PAGE_HEADER_LEN..])? {
+        return Ok(None);
+    }
+    let body_len: usize = page[PAGE_HEADER_LEN..].iter().map(|&len| len as usize).sum();
+    let body_start = page.len();
+    page.resize(body_start + body_len, 0);
+    if !read_full(input, &mut page[body_start..])? {
+        return Ok(None);
+    }
+    Ok(Some(page))
+}
+
+/// Header packets of the supported codecs
+enum Codec {
+    /// Identification, comment and setup headers
+    Vorbis,
+    /// `OpusHead` and `OpusTags`
+    Opus,
+}
+
+impl Codec {
+    fn detect(first_packet: &[u8]) -> Option<Self> {
+        if first_packet.starts_with(b"\x01vorbis") {
+            Some(Codec::Vorbis)
+        } else if first_packet.starts_with(b"OpusHead") {
+            Some(Codec::Opus)
+        } else {
+            None
+        }
+    }
+
+    fn header_packets(&self) -> usize {
+        match self {
+            Codec::Vorbis => 3,
+            Codec::Opus => 2,
+        }
+    }
+
+    /// Bytes before the comments in the comment header
+    fn comment_magic(&self) -> &'static [u8] {
+        match self {
+            Codec::Vorbis => b"\x03vorbis",
+            Codec::Opus => b"OpusTags",
+        }
+    }
+}
+
+/// Copy an Ogg Vorbis or Opus file with the comment header rewritten.
+///
+/// The header packets after the first one are paged again, and the audio pages
+/// that follow are renumbered when the header took a different number of pages.
+pub fn copy_with_tags<R: Read + Seek>(
+    input: &mut R,
+    output: &mut impl Write,
+    tags: &TrackTags,
+) -> io::Result<()> {
+    let first_bytes = read_page(input)?.ok_or_else(|| invalid("not an Ogg file"))?;
+    let first = Page::parse(&first_bytes).ok_or_else(|| invalid("not an Ogg file"))?;
+    if first.header_type & FIRST_PAGE == 0 {
+        return Err(invalid("not the start of an Ogg stream"));
+    }
+    let serial = first.serial;
+
+    // Gather the header packets, they always end with their last page
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut packet = Vec::new();
+    let mut codec = None;
+    let mut header_pages = 0;
+    let mut bytes = first_bytes.clone();
+    loop {
+        if header_pages > 0 {
+            bytes = read_page(input)?.ok_or_else(|| invalid("truncated Ogg headers"))?;
+        }
+        let page = Page::parse(&bytes).ok_or_else(|| invalid("truncated Ogg headers"))?;
+        if page.serial != serial {
+            return Err(invalid("multiplexed Ogg streams are not supported"));
+        }
+        let mut offset = 0;
+        for &len in page.segments {
+            packet.extend(&page.body[offset..offset + len as usize]);
+            offset += len as usize;
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+        header_pages += 1;
+
+        // The identification header has the first page to itself
+        if header_pages == 1 && (packets.len() != 1 || !packet.is_empty()) {
+            return Err(invalid("unexpected Ogg header layout"));
+        }
+        if codec.is_none() {
+            codec = Some(Codec::detect(&packets[0]).ok_or_else(|| {
+                invalid("only Vorbis and Opus tags can be written in Ogg files")
+            })?);
+        }
+        if let Some(codec) = &codec
+            && packets.len() >= codec.header_packets()
+        {
+            if packets.len() > codec.header_packets() || !packet.is_empty() {
+                return Err(invalid("audio shares a page with the Ogg headers"));
+            }
+            break;
+        }
+    }
+    let codec = codec.ok_or_else(|| invalid("truncated Ogg headers"))?;
+
+    // Everything after the comments (the Vorbis framing bit, Opus padding) is kept
+    let magic = codec.comment_magic();
+    let comment_packet = &packets[1];
+    let body = comment_packet
+        .strip_prefix(magic)
+        .ok_or_else(|| invalid("missing Ogg comment header"))?;
+    let (mut comments, len) =
+        VorbisComments::parse(body).ok_or_else(|| invalid("unreadable Vorbis comments"))?;
+    comments.set_tags(tags);
+    let mut new_packet = magic.to_vec();
+    new_packet.extend(comments.to_bytes());
+    new_packet.extend(&body[len..]);
+    packets[1] = new_packet;
+
+    // The identification header keeps its page, the other headers start on the next one
+    output.write_all(first.bytes)?;
+    let pages = paginate(&packets[1..], serial, 1);
+    let new_header_pages = pages.len() as u32 + 1;
+    for page in &pages {
+        output.write_all(page)?;
+    }
+
+    let shift = new_header_pages.wrapping_sub(header_pages);
+    if shift != 0 {
+        loop {
+            let pos = input.stream_position()?;
+            let Some(bytes) = read_page(input)? else {
+                // Trailing garbage is not ours to fix
+                input.seek(SeekFrom::Start(pos))?;
+                break;
+            };
+            let page = Page::parse(&bytes).ok_or_else(|| invalid("unreadable Ogg page"))?;
+            if page.serial == serial {
+                output.write_all(&page.renumbered(page.sequence.wrapping_add(shift)))?;
+            } else {
+                output.write_all(page.bytes)?;
+            }
+        }
+    }
+    io::copy(input, output)?;
+    Ok(())
+}
+
+/// Lay packets out on pages numbered from `first_sequence`, the last packet ending its page
+fn paginate(packets: &[Vec<u8>], serial: u32, first_sequence: u32) -> Vec<Vec<u8>> {
+    // Segments of at most 255 bytes, a shorter one (maybe empty) ending each packet
+    let mut segments: Vec<&[u8]> = Vec::new();
+    for packet in packets {
+        segments.extend(packet.chunks(255));
+        if packet.len() % 255 == 0 {
+            segments.push(&[]);
+        }
+    }
+
+    let mut pages = Vec::new();
+    let mut continued = false;
+    for (i, chunk) in segments.chunks(MAX_SEGMENTS).enumerate() {
+        let ends_packet = chunk.iter().any(|segment| segment.len() < 255);
+        let granule = if ends_packet { 0 } else { NO_GRANULE };
+        let header_type = if continued { CONTINUED } else { 0 };
+
+        let mut page = CAPTURE_PATTERN.to_vec();
+        page.push(0);
+        page.push(header_type);
+        page.extend(granule.to_le_bytes());
+        page.extend(serial.to_le_bytes());
+        page.extend((first_sequence + i as u32).to_le_bytes());
+        page.extend([0; 4]);
+        page.push(chunk.len() as u8);
+        page.extend(chunk.iter().map(|segment| segment.len() as u8));
+        for segment in chunk {
+            page.extend(*segment);
+        }
+        let checksum = crc(&page);
+        page[22..26].copy_from_slice(&checksum.to_le_bytes());
+        pages.push(page);
+
+        continued = chunk.last().is_some_and(|segment| segment.len() == 255);
+    }
+    pages
+}
@@ -0,0 +1,376 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{jukebox_state::SongItem, path_pattern::PathPattern, saved_playlists};
+
+/// Layout used when none is given
+pub const DEFAULT_PATTERN: &str = "{albumartist}/{album}/{track:02} {title}";
+
+/// File to move, paths as the library lists them
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Music directory the file is organized in, emptied folders are removed up to it
+    pub root: PathBuf,
+}
+
+/// What organizing the library would do
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub moves: Vec<Move>,
+    /// Files left where they are, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+    /// Files already where the pattern puts them
+    pub in_place: usize,
+}
+
+/// Directories among the sources, files are only organized inside them
+pub fn roots(sources: &[String]) -> Vec<PathBuf> {
+    sources
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// Where each file of the library goes with `pattern`, relative to the music
+/// directory it was found in.
+///
+/// Files never replace one another: a path already taken gets a counter, as in
+/// `01 Intro (2).mp3`. Streams, CUE sheet tracks, whose sheet names the file,
+/// and files outside of the music directories are left alone.
+pub fn plan(songs: &[SongItem], roots: &[PathBuf], pattern: &PathPattern) -> Plan {
+    let mut plan = Plan::default();
+    let mut seen = HashSet::new();
+    let mut taken = HashSet::new();
+
+    let mut songs: Vec<&SongItem> = songs.iter().filter(|song| !song.is_stream()).collect();
+    songs.sort_by(|a, b| a.as_path().cmp(b.as_path()));
+    for song in songs {
+        let from = song.as_path();
+        if !seen.insert(from) {
+            continue;
+        }
+        if song.is_segment() {
+            plan.skipped.push((from.to_path_buf(), "part of a CUE sheet".to_string()));
+            continue;
+        }
+        // The deepest directory holding the file, sources may be nested
+        let Some(root) = roots
+            .iter()
+            .filter(|root| from.starts_with(root))
+            .max_by_key(|root| root.components().count())
+        else {
+            plan.skipped.push((from.to_path_buf(), "not in a music directory".to_string()));
+            continue;
+        };
+
+        let mut tags = song.tags().clone();
+        if tags.title.is_none() {
+            tags.title = from.file_stem().map(|stem| stem.to_string_lossy().to_string());
+        }
+        let relative = match pattern.render(&tags) {
+            Ok(relative) => relative,
+            Err(reason) => {
+                plan.skipped.push((from.to_path_buf(), reason));
+                continue;
+            }
+        };
+
+        let target = with_extension_of(root.join(relative), from);
+        if target == from {
+            plan.in_place += 1;
+            taken.insert(target);
+            continue;
+        }
+        let to = free_path(&target, from, &taken);
+        taken.insert(to.clone());
+        plan.moves.push(Move {
+            from: from.to_path_buf(),
+            to,
+            root: root.clone(),
+        });
+    }
+    plan
+}
+
+/// Append the extension of `file`, names may contain dots of their own
+fn with_extension_of(path: PathBuf, file: &Path) -> PathBuf {
+    let Some(extension) = file.extension() else {
+        return path;
+    };
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// `target`, or the first `name (n).ext` that is neither planned nor on disk
+pub fn free_path(target: &Path, from: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    let is_free = |path: &Path| {
+        // A file differing only in case is the file itself on case-insensitive systems
+        !taken.contains(path) && (!path.exists() || is_same_file(path, from))
+    };
+    if is_free(target) {
+        return target.to_path_buf();
+    }
+
+    let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+    (2..)
+        .map(|n| {
+            let mut name = OsString::from(format!("{} ({})", stem, n));
+            if let Some(extension) = target.extension() {
+                name.push(".");
+                name.push(extension);
+            }
+            target.with_file_name(name)
+        })
+        .find(|path| is_free(path))
+        .expect("some counter is free")
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Carry out the moves of a plan, returning those done and those that failed
+pub fn apply(plan: &Plan) -> (Vec<Move>, Vec<(Move, io::Error)>) {
+    let mut moved = Vec::new();
+    let mut failed = Vec::new();
+    for file in &plan.moves {
+        match move_file(file) {
+            Ok(()) => moved.push(file.clone()),
+            Err(error) => failed.push((file.clone(), error)),
+        }
+    }
+    (moved, failed)
+}
+
+/// Move a file and its `.lrc` lyrics, then remove the folders this left empty
+fn move_file(file: &Move) -> io::Result<()> {
+    // Checked again, the file system may have changed since planning
+    if file.to.exists() && !is_same_file(&file.to, &file.from) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", file.to.display()),
+        ));
+    }
+    if let Some(parent) = file.to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    rename(&file.from, &file.to)?;
+
+    let lyrics = file.from.with_extension("lrc");
+    let new_lyrics = file.to.with_extension("lrc");
+    if lyrics.is_file() && !new_lyrics.exists() {
+        // The song moved already, lyrics left behind are not worth failing for
+        let _ = rename(&lyrics, &new_lyrics);
+    }
+
+    let mut dir = file.from.parent();
+    while let Some(current) = dir
+        && current.starts_with(&file.root)
+        && current != file.root
+        && fs::remove_dir(current).is_ok()
+    {
+        dir = current.parent();
+    }
+    Ok(())
+}
+
+/// Rename, or copy and delete when the target is on another file system
+fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {}
+        result => return result,
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from).inspect_err(|_| {
+        let _ = fs::remove_file(to);
+    })
+}
+
+/// New path of each moved file keyed by its old one, both absolute as saved playlists keep them
+pub fn renamed_paths(moved: &[Move]) -> HashMap<PathBuf, PathBuf> {
+    moved
+        .iter()
+        .map(|file| (saved_playlists::absolute(&file.from), saved_playlists::absolute(&file.to)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metadata::{TrackInfo, TrackTags},
+        testing::ScratchDir,
+    };
+
+    const PATTERN: &str = "{artist}/{album}/{track:02} {title}";
+
+    fn song(path: PathBuf, title: &str, track_number: u32) -> SongItem {
+        let tags = TrackTags {
+            title: Some(title.to_string()),
+            artist: Some("Artist".to_string()),
+            album: Some("Album".to_string()),
+            track_number: Some(track_number),
+            ..TrackTags::default()
+        };
+        let info = TrackInfo {
+            tags,
+            ..TrackInfo::default()
+        };
+        SongItem::new(path, title.to_string(), 0, info)
+    }
+
+    fn organize(songs: &[SongItem], roots: &[PathBuf]) -> Plan {
+        plan(songs, roots, &PathPattern::parse(PATTERN).unwrap())
+    }
+
+    fn targets(plan: &Plan) -> Vec<PathBuf> {
+        plan.moves.iter().map(|file| file.to.clone()).collect()
+    }
+
+    #[test]
+    fn colliding_targets_get_a_counter() {
+        let root = PathBuf::from("/music");
+        let songs = [
+            song(root.join("a.mp3"), "Intro", 1),
+            song(root.join("b.mp3"), "Intro", 1),
+            song(root.join("c.flac"), "Intro", 1),
+        ];
+
+        let plan = organize(&songs, std::slice::from_ref(&root));
+        let album = root.join("Artist/Album");
+        assert_eq!(
+            targets(&plan),
+            [
+                album.join("01 Intro.mp3"),
+                album.join("01 Intro (2).mp3"),
+                album.join("01 Intro.flac"),
+            ]
+        );
+    }
+
+    #[test]
+    fn files_on_disk_are_not_replaced() {
+        let root = ScratchDir::new("organize-taken");
+        let album = root.join("Artist/Album");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(album.join("01 Intro.mp3"), "another song").unwrap();
+        fs::write(album.join("01 Intro (2).mp3"), "yet another").unwrap();
+        fs::write(root.join("intro.mp3"), "intro").unwrap();
+        // Already at its place, and it keeps it
+        fs::write(album.join("02 Song.mp3"), "song").unwrap();
+        let songs = [
+            song(root.join("intro.mp3"), "Intro", 1),
+            song(album.join("02 Song.mp3"), "Song", 2),
+        ];
+
+        let plan = organize(&songs, &[root.to_path_buf()]);
+        assert_eq!(targets(&plan), [album.join("01 Intro (3).mp3")]);
+        assert_eq!(plan.in_place, 1);
+        assert!(plan.skipped.is_empty());
+
+        let (moved, failed) = apply(&plan);
+        assert_eq!(moved.len(), 1);
+        assert!(failed.is_empty());
+        assert_eq!(fs::read_to_string(album.join("01 Intro.mp3")).unwrap(), "another song");
+        assert_eq!(fs::read_to_string(album.join("01 Intro (3).mp3")).unwrap(), "intro");
+
+        // Planned before another file took the place: the move fails, nothing is lost
+        fs::write(root.join("late.mp3"), "late").unwrap();
+        let late = organize(&[song(root.join("late.mp3"), "Late", 3)], &[root.to_path_buf()]);
+        fs::write(album.join("03 Late.mp3"), "first").unwrap();
+        let (moved, failed) = apply(&late);
+        assert!(moved.is_empty());
+        assert_eq!(failed[0].1.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(root.join("late.mp3")).unwrap(), "late");
+        assert_eq!(fs::read_to_string(album.join("03 Late.mp3")).unwrap(), "first");
+    }
+
+    #[test]
+    fn names_differing_in_case_are_renamed() {
+        let root = ScratchDir::new("organize-case");
+        let album = root.join("Artist/Album");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(album.join("01 intro.mp3"), "intro").unwrap();
+
+        let songs = [song(album.join("01 intro.mp3"), "Intro", 1)];
+        let plan = organize(&songs, &[root.to_path_buf()]);
+        assert_eq!(targets(&plan), [album.join("01 Intro.mp3")]);
+        apply(&plan);
+        let names: Vec<OsString> = fs::read_dir(&album)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["01 Intro.mp3"]);
+    }
+
+    #[test]
+    fn files_are_organized_in_the_deepest_music_directory() {
+        let roots = [PathBuf::from("/music"), PathBuf::from("/music/podcasts")];
+        let songs = [
+            song(PathBuf::from("/music/podcasts/episode.mp3"), "Episode", 1),
+            song(PathBuf::from("/music/song.mp3"), "Song", 2),
+            song(PathBuf::from("/elsewhere/other.mp3"), "Other", 3),
+        ];
+
+        let plan = organize(&songs, &roots);
+        assert_eq!(
+            targets(&plan),
+            [
+                PathBuf::from("/music/podcasts/Artist/Album/01 Episode.mp3"),
+                PathBuf::from("/music/Artist/Album/02 Song.mp3"),
+            ]
+        );
+        assert_eq!(plan.moves[0].root, roots[1]);
+        let reason = "not in a music directory".to_string();
+        assert_eq!(plan.skipped, [(songs[2].as_path().to_path_buf(), reason)]);
+    }
+
+    #[test]
+    fn lyrics_follow_and_emptied_folders_are_removed() {
+        let root = ScratchDir::new("organize-lyrics");
+        let old = root.join("old/unsorted");
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("song.mp3"), "song").unwrap();
+        fs::write(old.join("song.lrc"), "[00:01.00]Line").unwrap();
+        let songs = [song(old.join("song.mp3"), "Song", 1)];
+
+        let (moved, failed) = apply(&organize(&songs, &[root.to_path_buf()]));
+        assert!(failed.is_empty());
+        let album = root.join("Artist/Album");
+        assert!(album.join("01 Song.mp3").is_file());
+        assert!(album.join("01 Song.lrc").is_file());
+        // Both emptied folders are gone, the music directory stays
+        assert!(!root.join("old").exists());
+        assert!(root.is_dir());
+
+        let renamed = renamed_paths(&moved);
+        assert_eq!(renamed.get(&old.join("song.mp3")), Some(&album.join("01 Song.mp3")));
+        assert_eq!(renamed.len(), 1);
+    }
+
+    #[test]
+    fn renamed_paths_are_absolute() {
+        let moved = [Move {
+            from: PathBuf::from("music/a.mp3"),
+            to: PathBuf::from("music/Artist/a.mp3"),
+            root: PathBuf::from("music"),
+        }];
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            renamed_paths(&moved),
+            HashMap::from([(cwd.join("music/a.mp3"), cwd.join("music/Artist/a.mp3"))])
+        );
+    }
+}
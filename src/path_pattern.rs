@@ -0,0 +1,447 @@
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::metadata::TrackTags;
+
+/// Characters refused in file names by common file systems, replaced by `_` when naming files
+const ILLEGAL_CHARS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+/// Longest folder or file name written, leaving room for an extension and a counter
+const MAX_NAME_BYTES: usize = 200;
+
+/// Tag filled by a `{field}` placeholder
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Track,
+    Year,
+    Genre,
+    /// `{*}`, matches anything and is thrown away
+    Ignored,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "title" => Field::Title,
+            "artist" => Field::Artist,
+            "album" => Field::Album,
+            "albumartist" => Field::AlbumArtist,
+            "track" => Field::Track,
+            "year" => Field::Year,
+            "genre" => Field::Genre,
+            "*" => Field::Ignored,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Artist => "artist",
+            Field::Album => "album",
+            Field::AlbumArtist => "albumartist",
+            Field::Track => "track",
+            Field::Year => "year",
+            Field::Genre => "genre",
+            Field::Ignored => "*",
+        }
+    }
+
+    fn is_number(self) -> bool {
+        matches!(self, Field::Track | Field::Year)
+    }
+
+    /// Value naming a file, the album artist falling back to the artist
+    fn value(self, tags: &TrackTags) -> Option<String> {
+        match self {
+            Field::Title => tags.title.clone(),
+            Field::Artist => tags.artist.clone(),
+            Field::Album => tags.album.clone(),
+            Field::AlbumArtist => tags.album_artist.clone().or_else(|| tags.artist.clone()),
+            Field::Track => tags.track_number.map(|track| track.to_string()),
+            Field::Year => tags.year.map(|year| year.to_string()),
+            Field::Genre => tags.genre.clone(),
+            Field::Ignored => None,
+        }
+    }
+
+    /// Store a matched value, false when it does not fit the field
+    fn set(self, tags: &mut TrackTags, value: &str) -> bool {
+        let value = value.trim();
+        if value.is_empty() {
+            return false;
+        }
+        let is_number = value.chars().all(|c| c.is_ascii_digit());
+        match self {
+            Field::Title => tags.title = Some(value.to_string()),
+            Field::Artist => tags.artist = Some(value.to_string()),
+            Field::Album => tags.album = Some(value.to_string()),
+            Field::AlbumArtist => tags.album_artist = Some(value.to_string()),
+            Field::Genre => tags.genre = Some(value.to_string()),
+            Field::Track if is_number => tags.track_number = value.parse().ok(),
+            Field::Year if is_number && value.len() == 4 => tags.year = value.parse().ok(),
+            Field::Track | Field::Year => return false,
+            Field::Ignored => {}
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    /// Field with the width numbers are padded to with zeros, as in `{track:02}`
+    Field(Field, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternError(String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// Pattern matched against the end of a file path to guess its tags, such as
+/// `{artist}/{album}/{track} - {title}`.
+///
+/// Each `/` separated part matches one folder, the last one the file name
+/// without its extension. Placeholders are `{title}`, `{artist}`, `{album}`,
+/// `{albumartist}`, `{track}`, `{year}`, `{genre}` and `{*}` for text to skip.
+/// Patterns also name files after their tags, `{track:02}` padding the number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPattern {
+    text: String,
+    /// Pieces of each path component, the file name last
+    components: Vec<Vec<Piece>>,
+}
+
+impl PathPattern {
+    pub fn parse(text: &str) -> Result<Self, PatternError> {
+        let components = text
+            .trim_matches('/')
+            .split('/')
+            .map(parse_component)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            text: text.to_string(),
+            components,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Tags read from the path of a file below `root`, `None` when that part of the path
+    /// does not fit the pattern. Folders above `root` are never read.
+    pub fn extract(&self, path: &Path, root: &Path) -> Option<TrackTags> {
+        let path = std::path::absolute(path).ok()?;
+        let path = path.strip_prefix(std::path::absolute(root).ok()?).ok()?;
+        let mut names: Vec<String> = path
+            .parent()?
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        names.push(path.file_stem()?.to_string_lossy().to_string());
+
+        let first = names.len().checked_sub(self.components.len())?;
+        let mut tags = TrackTags::default();
+        self.components
+            .iter()
+            .zip(&names[first..])
+            .all(|(pieces, name)| match_pieces(pieces, name, &mut tags))
+            .then_some(tags)
+    }
+
+    /// Whether every field can be filled from tags, `{*}` only skips text when reading paths
+    pub fn can_name_files(&self) -> bool {
+        self.components
+            .iter()
+            .flatten()
+            .all(|piece| !matches!(piece, Piece::Field(Field::Ignored, _)))
+    }
+
+    /// Whether the pattern names folders as well as files
+    pub fn has_folders(&self) -> bool {
+        self.components.len() > 1
+    }
+
+    /// Relative path named after the tags, without extension.
+    ///
+    /// Characters file systems refuse are replaced by `_`. Fails with the name
+    /// of the first field the tags lack.
+    pub fn render(&self, tags: &TrackTags) -> Result<PathBuf, String> {
+        let mut path = PathBuf::new();
+        for pieces in &self.components {
+            let mut name = String::new();
+            for piece in pieces {
+                match piece {
+                    Piece::Text(text) => name.push_str(text),
+                    Piece::Field(field, width) => {
+                        let value = field
+                            .value(tags)
+                            .ok_or_else(|| format!("no {} tag", field.name()))?;
+                        let value: String = value
+                            .chars()
+                            .map(|c| {
+                                if ILLEGAL_CHARS.contains(&c) || c.is_control() { '_' } else { c }
+                            })
+                            .collect();
+                        name.push_str(&format!("{:0>width$}", value.trim(), width = width));
+                    }
+                }
+            }
+            path.push(tidy_name(&name));
+        }
+        Ok(path)
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = PatternError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+/// Tags from the first pattern the path below `root` fits, with that pattern
+pub fn infer<'a>(
+    patterns: &'a [PathPattern],
+    path: &Path,
+    root: &Path,
+) -> Option<(&'a PathPattern, TrackTags)> {
+    patterns
+        .iter()
+        .find_map(|pattern| Some((pattern, pattern.extract(path, root)?)))
+}
+
+fn parse_component(text: &str) -> Result<Vec<Piece>, PatternError> {
+    if text.is_empty() {
+        return Err(PatternError("empty folder in pattern".to_string()));
+    }
+
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('{') else {
+            pieces.push(Piece::Text(rest.to_string()));
+            break;
+        };
+        if start > 0 {
+            pieces.push(Piece::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| PatternError(format!("missing '}}' in '{}'", text)))?;
+        let placeholder = &rest[start + 1..start + end];
+        let (name, width) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+        let field = Field::from_name(name)
+            .ok_or_else(|| PatternError(format!("unknown field '{{{}}}'", name)))?;
+        let width = match width {
+            "" => 0,
+            width if field.is_number() => width
+                .parse()
+                .map_err(|_| PatternError(format!("invalid width in '{{{}}}'", placeholder)))?,
+            _ => {
+                return Err(PatternError(format!("only numbers can be padded, not '{{{}}}'", name)));
+            }
+        };
+        // Nothing would tell where the first field stops
+        if let Some(Piece::Field(..)) = pieces.last() {
+            return Err(PatternError(format!("fields need text between them in '{}'", text)));
+        }
+        pieces.push(Piece::Field(field, width));
+        rest = &rest[start + end + 1..];
+    }
+    Ok(pieces)
+}
+
+/// Match a path component, fields taking as little text as the rest allows
+fn match_pieces(pieces: &[Piece], text: &str, tags: &mut TrackTags) -> bool {
+    match pieces.split_first() {
+        None => text.is_empty(),
+        Some((Piece::Text(literal), rest)) => text
+            .strip_prefix(literal.as_str())
+            .is_some_and(|text| match_pieces(rest, text, tags)),
+        Some((Piece::Field(field, _), [])) => field.set(tags, text),
+        Some((Piece::Field(field, _), rest)) => {
+            let ends = text.char_indices().skip(1).map(|(i, _)| i).chain([text.len()]);
+            for end in ends {
+                let mut attempt = tags.clone();
+                if field.set(&mut attempt, &text[..end])
+                    && match_pieces(rest, &text[end..], &mut attempt)
+                {
+                    *tags = attempt;
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+/// Folder or file name that is valid everywhere and not hidden: no leading dot,
+/// no trailing dot or space, not too long and not empty
+fn tidy_name(name: &str) -> String {
+    let mut name = name.trim().trim_end_matches(['.', ' ']).to_string();
+    if name.starts_with('.') {
+        name.replace_range(..1, "_");
+    }
+    if name.len() > MAX_NAME_BYTES {
+        let mut end = MAX_NAME_BYTES;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+        name = name.trim_end_matches(['.', ' ']).to_string();
+    }
+    if name.is_empty() { "_".to_string() } else { name }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(text: &str) -> PathPattern {
+        PathPattern::parse(text).unwrap()
+    }
+
+    fn tags(title: &str, artist: &str, album: &str, track: Option<u32>) -> TrackTags {
+        let text = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        TrackTags {
+            title: text(title),
+            artist: text(artist),
+            album: text(album),
+            track_number: track,
+            ..TrackTags::default()
+        }
+    }
+
+    /// Tags the pieces of `pattern` (a single component) read from `text`
+    fn matched(pattern: &str, text: &str) -> Option<TrackTags> {
+        let pieces = parse_component(pattern).unwrap();
+        let mut tags = TrackTags::default();
+        match_pieces(&pieces, text, &mut tags).then_some(tags)
+    }
+
+    #[test]
+    fn extracts_tags_from_folders_and_file_name() {
+        let path = Path::new("/music/Daft Punk/Discovery/03 - Digital Love.flac");
+        let root = Path::new("/music");
+        assert_eq!(
+            pattern("{artist}/{album}/{track} - {title}").extract(path, root),
+            Some(tags("Digital Love", "Daft Punk", "Discovery", Some(3)))
+        );
+        // Only the end of the path is matched, leading and trailing slashes are ignored
+        assert_eq!(
+            pattern("/{album}/{title}/").extract(path, root),
+            Some(tags("03 - Digital Love", "", "Discovery", None))
+        );
+    }
+
+    #[test]
+    fn extract_fails_on_paths_that_do_not_fit() {
+        let path = Path::new("/Artist/Song.mp3");
+        let root = Path::new("/");
+        assert_eq!(pattern("{genre}/{artist}/{album}/{title}").extract(path, root), None);
+        assert_eq!(pattern("{artist}/{track} - {title}").extract(path, root), None);
+        assert_eq!(pattern("{year} - {title}").extract(Path::new("/99 - Song.mp3"), root), None);
+        assert_eq!(pattern("{title}").extract(path, Path::new("/elsewhere")), None);
+    }
+
+    #[test]
+    fn extract_only_reads_folders_below_the_root() {
+        let path = Path::new("/home/user/Music/Song.mp3");
+        let pattern = pattern("{artist}/{album}/{title}");
+        // The user name and the music folder are not an artist and an album
+        assert_eq!(pattern.extract(path, Path::new("/home/user/Music")), None);
+        assert_eq!(
+            pattern.extract(Path::new("Music/Artist/Album/Song.mp3"), Path::new("Music")),
+            Some(tags("Song", "Artist", "Album", None))
+        );
+
+        let tags = self::pattern("{artist}/{title}")
+            .extract(Path::new("Artist/Song.ogg"), Path::new("."))
+            .unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Artist"));
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+    }
+
+    #[test]
+    fn fields_take_as_little_text_as_the_rest_allows() {
+        assert_eq!(
+            matched("{artist} - {title}", "A - B - C"),
+            Some(tags("B - C", "A", "", None))
+        );
+        // A number field only takes digits, so the track stops before the separator
+        assert_eq!(
+            matched("{track:02} - {title}", "01 - Intro - Part 2"),
+            Some(tags("Intro - Part 2", "", "", Some(1)))
+        );
+        assert_eq!(
+            matched("{track}. {title}", "1. 2. Song"),
+            Some(tags("2. Song", "", "", Some(1)))
+        );
+    }
+
+    #[test]
+    fn matched_values_are_trimmed_and_checked() {
+        assert_eq!(matched("{artist}-{title}", "AC - DC"), Some(tags("DC", "AC", "", None)));
+        assert_eq!(matched("{artist} - {title}", " - Song"), None);
+        assert_eq!(matched("{track} {title}", "A1 Song"), None);
+        assert_eq!(matched("{year} {title}", "12345 Song"), None);
+
+        let tags = matched("{year} - {*} - {title}", "1999 - skip me - Song").unwrap();
+        assert_eq!(tags.year, Some(1999));
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.artist, None);
+    }
+
+    #[test]
+    fn literal_text_must_match_exactly() {
+        assert!(matched("Disc {track}", "Disc 2").is_some());
+        assert!(matched("Disc {track}", "CD 2").is_none());
+        assert!(matched("{title} (live)", "Song (live) x").is_none());
+        assert_eq!(matched("{title}!", "Song!"), Some(tags("Song", "", "", None)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| PathPattern::parse(text).unwrap_err().to_string();
+        assert_eq!(error("{artist}/{name}"), "unknown field '{name}'");
+        assert_eq!(error("{artist"), "missing '}' in '{artist'");
+        assert_eq!(error("{artist}{title}"), "fields need text between them in '{artist}{title}'");
+        assert_eq!(error("{title:02}"), "only numbers can be padded, not '{title}'");
+        assert_eq!(error("{track:x}"), "invalid width in '{track:x}'");
+        assert_eq!(error("{artist}//{title}"), "empty folder in pattern");
+    }
+
+    #[test]
+    fn renders_paths_from_tags() {
+        let tags = tags("What? / Why", "AC/DC", "...Album.", Some(7));
+        let pattern = pattern("{artist}/{album}/{track:02} {title}");
+        assert!(pattern.has_folders());
+        assert_eq!(pattern.render(&tags), Ok(PathBuf::from("AC_DC/_..Album/07 What_ _ Why")));
+        assert_eq!(
+            pattern.render(&TrackTags::default()),
+            Err("no artist tag".to_string())
+        );
+        assert!(!PathPattern::parse("{title}").unwrap().has_folders());
+        assert!(!PathPattern::parse("{*} - {title}").unwrap().can_name_files());
+    }
+}
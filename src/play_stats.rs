@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::storage;
+
+const STATS_FILE: &str = "stats.json";
+
+/// Highest star rating
+pub const MAX_RATING: u8 = 5;
+
+/// Listening statistics and user opinion of a track
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackStats {
+    #[serde(default)]
+    pub plays: u32,
+    /// Seconds since the epoch
+    #[serde(default)]
+    pub last_played: Option<u64>,
+    /// 1 to 5 stars
+    #[serde(default)]
+    pub rating: Option<u8>,
+    #[serde(default)]
+    pub favourite: bool,
+}
+
+/// Play counts, ratings and favourites keyed by track identity, persisted in the data directory
+pub struct PlayStats {
+    file: Option<PathBuf>,
+    entries: HashMap<String, TrackStats>,
+}
+
+impl PlayStats {
+    pub fn load() -> Self {
+        let file = storage::data_file(STATS_FILE);
+        let entries = file
+            .as_deref()
+            .map(storage::load_json)
+            .unwrap_or_default();
+
+        Self { file, entries }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&TrackStats> {
+        self.entries.get(id)
+    }
+
+    /// Count one more play, played now
+    pub fn record_play(&mut self, id: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let stats = self.entries.entry(id.to_string()).or_default();
+        stats.plays += 1;
+        stats.last_played = Some(now);
+        self.save();
+    }
+
+    /// Set the star rating, `None` or 0 clears it
+    pub fn set_rating(&mut self, id: &str, rating: Option<u8>) {
+        let rating = rating
+            .filter(|&stars| stars > 0)
+            .map(|stars| stars.min(MAX_RATING));
+        self.entries.entry(id.to_string()).or_default().rating = rating;
+        self.save();
+    }
+
+    /// Flip the favourite flag, returning whether the track is now a favourite
+    pub fn toggle_favourite(&mut self, id: &str) -> bool {
+        let stats = self.entries.entry(id.to_string()).or_default();
+        stats.favourite = !stats.favourite;
+        let favourite = stats.favourite;
+        self.save();
+        favourite
+    }
+
+    fn save(&self) {
+        if let Some(file) = &self.file {
+            let _ = storage::save_json(file, &self.entries);
+        }
+    }
+}
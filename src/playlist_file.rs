@@ -0,0 +1,592 @@
+use std::{
+    fmt::Write as _,
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::jukebox_state::SongItem;
+
+/// Playlist file extensions that can be loaded
+pub const PLAYLIST_EXTENSIONS: [&str; 4] = ["m3u", "m3u8", "pls", "xspf"];
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| PLAYLIST_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Where a playlist entry points to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    File(PathBuf),
+    Url(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub location: Location,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistEntry {
+    /// Build an entry, resolving relative paths against the playlist folder
+    fn new(location: &str, base_dir: &Path) -> Self {
+        let location = if is_url(location) {
+            Location::Url(location.to_string())
+        } else {
+            let path = match location.strip_prefix("file://") {
+                Some(uri) => percent_decode(uri),
+                None => location.to_string(),
+            };
+            Location::File(base_dir.join(path))
+        };
+
+        Self {
+            location,
+            title: None,
+            duration: None,
+        }
+    }
+}
+
+impl From<&SongItem> for PlaylistEntry {
+    /// CUE tracks point to their whole file, named after the album, as playlists cannot
+    /// describe a part of a file
+    fn from(song: &SongItem) -> Self {
+        let location = match song.url() {
+            Some(url) => Location::Url(url.to_string()),
+            None => Location::File(
+                song.as_path()
+                    .canonicalize()
+                    .unwrap_or_else(|_| song.as_path().to_path_buf()),
+            ),
+        };
+
+        if song.is_segment() {
+            return Self {
+                location,
+                title: song.tags().album.clone(),
+                duration: None,
+            };
+        }
+
+        Self {
+            location,
+            title: Some(song.display_title()),
+            duration: song.duration(),
+        }
+    }
+}
+
+/// Entries for `songs`, where the CUE tracks of a file become a single entry for the file
+pub fn entries_for<'a>(songs: impl IntoIterator<Item = &'a SongItem>) -> Vec<PlaylistEntry> {
+    let mut sheet_files = HashSet::new();
+    songs
+        .into_iter()
+        .filter(|song| !song.is_segment() || sheet_files.insert(song.as_path()))
+        .map(PlaylistEntry::from)
+        .collect()
+}
+
+/// Playlist file formats, told apart by their extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(Format::M3u),
+            "pls" => Some(Format::Pls),
+            "xspf" => Some(Format::Xspf),
+            _ => None,
+        }
+    }
+}
+
+/// Load an M3U/M3U8, PLS or XSPF playlist
+pub fn load(path: &Path) -> Option<Vec<PlaylistEntry>> {
+    let bytes = fs::read(path).ok()?;
+    let content = String::from_utf8_lossy(&bytes);
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    Some(match Format::from_path(path)? {
+        Format::M3u => parse_m3u(&content, base_dir),
+        Format::Pls => parse_pls(&content, base_dir),
+        Format::Xspf => parse_xspf(&content, base_dir),
+    })
+}
+
+/// Write a playlist in the format given by the file extension (M3U8, PLS or XSPF)
+pub fn save(path: &Path, entries: &[PlaylistEntry]) -> io::Result<()> {
+    let format = Format::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unknown playlist format, use .m3u8, .pls or .xspf",
+        )
+    })?;
+
+    let content = match format {
+        Format::M3u => write_m3u(entries),
+        Format::Pls => write_pls(entries),
+        Format::Xspf => write_xspf(entries),
+    };
+    // Written aside first, so a failed write leaves the old playlist whole
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Parse an M3U playlist, reading titles and durations from `#EXTINF` lines
+pub fn parse_m3u(content: &str, base_dir: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut pending_info: Option<(Option<Duration>, Option<String>)> = None;
+
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<seconds>[ attributes],<title>
+            let (length, title) = info.split_once(',').unwrap_or((info, ""));
+            let seconds = length
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.parse::<f64>().ok())
+                .filter(|s| *s > 0.0);
+            let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            pending_info = Some((seconds.map(Duration::from_secs_f64), title));
+        } else if !line.starts_with('#') {
+            let mut entry = PlaylistEntry::new(line, base_dir);
+            if let Some((duration, title)) = pending_info.take() {
+                entry.duration = duration;
+                entry.title = title;
+            }
+            entries.push(entry);
+        }
+    }
+
+    entries
+}
+
+/// Parse a PLS playlist (`FileN=`, `TitleN=`, `LengthN=` keys)
+pub fn parse_pls(content: &str, base_dir: &Path) -> Vec<PlaylistEntry> {
+    let mut numbered: Vec<(u32, PlaylistEntry)> = Vec::new();
+    let mut titles = Vec::new();
+    let mut lengths = Vec::new();
+
+    for line in content.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        let index_of = |prefix: &str| key.strip_prefix(prefix)?.parse::<u32>().ok();
+
+        if let Some(n) = index_of("file") {
+            numbered.push((n, PlaylistEntry::new(value, base_dir)));
+        } else if let Some(n) = index_of("title") {
+            titles.push((n, value.to_string()));
+        } else if let Some(n) = index_of("length") {
+            // Streams use -1 as length
+            if let Ok(seconds) = value.parse::<u64>() {
+                lengths.push((n, Duration::from_secs(seconds)));
+            }
+        }
+    }
+
+    numbered.sort_by_key(|(n, _)| *n);
+    numbered
+        .into_iter()
+        .map(|(n, mut entry)| {
+            entry.title = titles.iter().find(|(i, _)| *i == n).map(|(_, t)| t.clone());
+            entry.duration = lengths.iter().find(|(i, _)| *i == n).map(|(_, d)| *d);
+            entry
+        })
+        .collect()
+}
+
+/// Parse an XSPF playlist, reading `<location>`, `<title>` and `<duration>` of each `<track>`
+pub fn parse_xspf(content: &str, base_dir: &Path) -> Vec<PlaylistEntry> {
+    xml_elements(content, "track")
+        .filter_map(|track| {
+            let location = xml_elements(track, "location").next()?;
+            let mut entry = PlaylistEntry::new(xml_unescape(location).trim(), base_dir);
+            entry.title = xml_elements(track, "title").next().map(xml_unescape);
+            // Durations are in milliseconds
+            entry.duration = xml_elements(track, "duration")
+                .next()
+                .and_then(|ms| ms.trim().parse().ok())
+                .map(Duration::from_millis);
+            Some(entry)
+        })
+        .collect()
+}
+
+/// Contents of each `<name>...</name>` element, attributes ignored
+fn xml_elements<'a>(content: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let mut rest = content;
+
+    std::iter::from_fn(move || {
+        loop {
+            let start = rest.find(&open)?;
+            let after_name = &rest[start + open.len()..];
+            // Skip longer names sharing the prefix, such as <trackList> for <track>
+            if !after_name.starts_with(['>', ' ', '\t', '\n', '\r']) {
+                rest = after_name;
+                continue;
+            }
+            let body_start = after_name.find('>')? + 1;
+            let body = &after_name[body_start..];
+            let end = body.find(&close)?;
+            rest = &body[end + close.len()..];
+            return Some(&body[..end]);
+        }
+    })
+}
+
+/// Replace the predefined entities and the `&#NNN;` and `&#xHH;` character references,
+/// leaving any other `&` as it is
+fn xml_unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(entity) = rest[1..].split_once(';').map(|(entity, _)| entity) else {
+            break;
+        };
+        let character = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "amp" => Some('&'),
+            _ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                unescaped.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode `%20` style escapes of a `file://` URI
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+fn location_string(location: &Location) -> String {
+    match location {
+        Location::Url(url) => url.clone(),
+        Location::File(path) => path.display().to_string(),
+    }
+}
+
+fn write_m3u(entries: &[PlaylistEntry]) -> String {
+    let mut content = String::from("#EXTM3U\n");
+    for entry in entries {
+        let seconds = entry.duration.map_or(-1, |d| d.as_secs() as i64);
+        let title = entry.title.as_deref().unwrap_or_default();
+        let _ = writeln!(content, "#EXTINF:{},{}", seconds, title);
+        let _ = writeln!(content, "{}", location_string(&entry.location));
+    }
+    content
+}
+
+fn write_pls(entries: &[PlaylistEntry]) -> String {
+    let mut content = String::from("[playlist]\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let n = i + 1;
+        let _ = writeln!(content, "File{}={}", n, location_string(&entry.location));
+        if let Some(title) = &entry.title {
+            let _ = writeln!(content, "Title{}={}", n, title);
+        }
+        let seconds = entry.duration.map_or(-1, |d| d.as_secs() as i64);
+        let _ = writeln!(content, "Length{}={}", n, seconds);
+    }
+    let _ = writeln!(content, "NumberOfEntries={}", entries.len());
+    let _ = writeln!(content, "Version=2");
+    content
+}
+
+fn write_xspf(entries: &[PlaylistEntry]) -> String {
+    let mut content = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for entry in entries {
+        let location = match &entry.location {
+            Location::Url(url) => url.clone(),
+            Location::File(path) => format!("file://{}", percent_encode(&path.to_string_lossy())),
+        };
+        let _ = writeln!(content, "    <track>");
+        let _ = writeln!(content, "      <location>{}</location>", xml_escape(&location));
+        if let Some(title) = &entry.title {
+            let _ = writeln!(content, "      <title>{}</title>", xml_escape(title));
+        }
+        if let Some(duration) = entry.duration {
+            let _ = writeln!(content, "      <duration>{}</duration>", duration.as_millis());
+        }
+        let _ = writeln!(content, "    </track>");
+    }
+    content.push_str("  </trackList>\n</playlist>\n");
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metadata::{TrackInfo, TrackTags},
+        testing::ScratchDir,
+    };
+
+    fn sheet_track(title: &str, position: usize, (start, end): (u64, Option<u64>)) -> SongItem {
+        let info = TrackInfo {
+            duration: Some(Duration::from_secs(600)),
+            tags: TrackTags {
+                album: Some("Live at Home".to_string()),
+                ..TrackTags::default()
+            },
+            ..TrackInfo::default()
+        };
+        let range = (Duration::from_secs(start), end.map(Duration::from_secs));
+        let path = PathBuf::from("/music/live.flac");
+        SongItem::segment(path, title.to_string(), None, position, range, &info)
+    }
+
+    #[test]
+    fn cue_tracks_are_exported_once_per_file() {
+        let path = PathBuf::from("/music/song.mp3");
+        let song = SongItem::new(path, "Song".to_string(), 0, TrackInfo::default());
+        let songs = [
+            sheet_track("Intro", 1, (0, Some(300))),
+            song.clone(),
+            sheet_track("Encore", 2, (300, None)),
+            song,
+        ];
+
+        let entries = entries_for(&songs);
+        // Songs queued twice stay twice, the second track of the sheet is dropped
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].location, Location::File(PathBuf::from("/music/live.flac")));
+        assert_eq!(entries[0].title.as_deref(), Some("Live at Home"));
+        assert_eq!(entries[0].duration, None);
+        assert_eq!(entries[1].location, Location::File(PathBuf::from("/music/song.mp3")));
+        assert_eq!(entries[2].location, entries[1].location);
+    }
+
+    #[test]
+    fn save_replaces_the_playlist() {
+        let dir = ScratchDir::new("playlist");
+        let path = dir.join("mix.m3u8");
+        fs::write(&path, "#EXTM3U\nold.mp3\n").unwrap();
+
+        let entries = [PlaylistEntry {
+            location: Location::Url("http://localhost/radio".to_string()),
+            title: Some("Radio".to_string()),
+            duration: None,
+        }];
+        save(&path, &entries).unwrap();
+
+        assert_eq!(load(&path).unwrap(), entries);
+        // Nothing is left from the write next to the playlist
+        assert_eq!(fs::read_dir(&*dir).unwrap().count(), 1);
+        assert!(save(&dir.join("mix.txt"), &entries).is_err());
+    }
+
+    fn file(path: &str) -> Location {
+        Location::File(PathBuf::from(path))
+    }
+
+    #[test]
+    fn m3u_entries() {
+        let content = "\u{feff}#EXTM3U\n\
+            #EXTINF:215 tvg-id=\"x\",Artist - Song, with a comma\n\
+            Artist/song.mp3\n\
+            \n\
+            # A comment\n\
+            /music/absolute.flac\n\
+            #EXTINF:-1,Radio\n\
+            http://localhost/radio\n\
+            file:///music/with%20space%C3%A9.ogg\n";
+        let entries = parse_m3u(content, Path::new("/playlists"));
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].location, file("/playlists/Artist/song.mp3"));
+        assert_eq!(entries[0].title.as_deref(), Some("Artist - Song, with a comma"));
+        assert_eq!(entries[0].duration, Some(Duration::from_secs(215)));
+        // The info only belongs to the entry right after it
+        assert_eq!(entries[1].location, file("/music/absolute.flac"));
+        assert_eq!(entries[1].title, None);
+        assert_eq!(entries[2].location, Location::Url("http://localhost/radio".to_string()));
+        assert_eq!(entries[2].title.as_deref(), Some("Radio"));
+        assert_eq!(entries[2].duration, None);
+        assert_eq!(entries[3].location, file("/music/with spaceé.ogg"));
+    }
+
+    #[test]
+    fn pls_entries_follow_their_numbers() {
+        let content = "[playlist]\n\
+            Title2=Second\n\
+            File2=second.mp3\n\
+            File1=/music/first.mp3\n\
+            Length2=-1\n\
+            Length1=61\n\
+            title1 = First\n\
+            NumberOfEntries=2\n\
+            Version=2\n";
+        let entries = parse_pls(content, Path::new("/playlists"));
+
+        assert_eq!(
+            entries,
+            [
+                PlaylistEntry {
+                    location: file("/music/first.mp3"),
+                    title: Some("First".to_string()),
+                    duration: Some(Duration::from_secs(61)),
+                },
+                PlaylistEntry {
+                    location: file("/playlists/second.mp3"),
+                    title: Some("Second".to_string()),
+                    duration: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn xspf_entries() {
+        let content = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n<trackList>\n\
+            <track>\n\
+              <location>file:///music/Rock%20%26%20Roll.mp3</location>\n\
+              <title>Rock &amp; Roll &lt;Live&gt; &#233;t&#xE9; &#X41;</title>\n\
+              <duration>61500</duration>\n\
+            </track>\n\
+            <track><title>No location</title></track>\n\
+            <track attr=\"1\"><location>relative&apos;s.mp3</location></track>\n\
+            </trackList>\n</playlist>\n";
+        let entries = parse_xspf(content, Path::new("/playlists"));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, file("/music/Rock & Roll.mp3"));
+        assert_eq!(entries[0].title.as_deref(), Some("Rock & Roll <Live> été A"));
+        assert_eq!(entries[0].duration, Some(Duration::from_millis(61_500)));
+        assert_eq!(entries[1].location, file("/playlists/relative's.mp3"));
+        assert_eq!(entries[1].title, None);
+    }
+
+    #[test]
+    fn xml_unescape_decodes_each_reference_once() {
+        assert_eq!(xml_unescape("&amp;lt; &#38;#65;"), "&lt; &#65;");
+        assert_eq!(xml_unescape("&#x1F3B5;&#9835;"), "🎵♫");
+        // Anything else is text
+        let text = "A & B &unknown; &#xD800; &#; &amp";
+        assert_eq!(xml_unescape(text), text);
+    }
+
+    #[test]
+    fn percent_decode_keeps_stray_percent_signs() {
+        assert_eq!(percent_decode("/a%20b/%C3%A9"), "/a b/é");
+        assert_eq!(percent_decode("100%.mp3"), "100%.mp3");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode(&percent_encode("/odd #?%&é.mp3")), "/odd #?%&é.mp3");
+    }
+
+    #[test]
+    fn saved_playlists_load_back() {
+        let dir = ScratchDir::new("playlist-round-trip");
+        let entries = [
+            PlaylistEntry {
+                location: file("/music/Rock & Roll <Live> 100%é.mp3"),
+                title: Some("Rock & Roll <Live> \"été\"".to_string()),
+                duration: Some(Duration::from_secs(61)),
+            },
+            PlaylistEntry {
+                location: file("/music/untitled.flac"),
+                title: None,
+                duration: None,
+            },
+            PlaylistEntry {
+                location: Location::Url("https://localhost/radio?a=1&b=2".to_string()),
+                title: Some("Radio".to_string()),
+                duration: None,
+            },
+        ];
+
+        for name in ["mix.m3u8", "mix.pls", "mix.xspf"] {
+            let path = dir.join(name);
+            save(&path, &entries).unwrap();
+            assert_eq!(load(&path).unwrap(), entries, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+use std::fmt;
+
+use crate::jukebox_state::SongItem;
+
+/// Song property a query term looks at
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Codec,
+    Path,
+    Year,
+    Track,
+    Duration,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "title" => Field::Title,
+            "artist" => Field::Artist,
+            "album" => Field::Album,
+            "albumartist" => Field::AlbumArtist,
+            "genre" => Field::Genre,
+            "codec" => Field::Codec,
+            "path" | "file" => Field::Path,
+            "year" => Field::Year,
+            "track" => Field::Track,
+            "duration" | "length" => Field::Duration,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Field::Year | Field::Track | Field::Duration)
+    }
+
+    fn text(self, song: &SongItem) -> Option<String> {
+        let tags = song.tags();
+        match self {
+            Field::Title => Some(tags.title.clone().unwrap_or_else(|| song.display_title())),
+            Field::Artist => tags.artist.as_deref().or(song.performer()).map(str::to_string),
+            Field::Album => tags.album.clone(),
+            Field::AlbumArtist => tags.album_artist.clone(),
+            Field::Genre => tags.genre.clone(),
+            Field::Codec => song.codec().map(str::to_string),
+            Field::Path => Some(match song.url() {
+                Some(url) => url.to_string(),
+                None => song.as_path().to_string_lossy().to_string(),
+            }),
+            Field::Year | Field::Track | Field::Duration => None,
+        }
+    }
+
+    /// Numeric value, durations in seconds
+    fn number(self, song: &SongItem) -> Option<f64> {
+        let tags = song.tags();
+        match self {
+            Field::Year => tags.year.map(f64::from),
+            Field::Track => tags.track_number.map(f64::from),
+            Field::Duration => song.duration().map(|d| d.as_secs_f64()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    /// `:`, contains for text, equals for numbers
+    Has,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    /// Operator at the start of `text` and its length, longest first
+    fn parse(text: &str) -> Option<(Self, usize)> {
+        const OPS: [(&str, Op); 7] = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("!=", Op::Ne),
+            (":", Op::Has),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        OPS.iter()
+            .find(|(symbol, _)| text.starts_with(symbol))
+            .map(|&(symbol, op)| (op, symbol.len()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    /// Bare word, looked up in the title, artist and album
+    Any(String),
+    Field(Field, Op, Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    negated: bool,
+    term: Term,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError(String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Filter over the library metadata, such as `artist:"Daft Punk" duration<5m year>=2000 !genre:live`.
+///
+/// Terms are `field` + operator + value, all of them must match. `OR` (or `|`)
+/// separates alternatives, `!` or `-` negates a term and bare words are looked
+/// up in the title, artist and album.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    /// Alternatives, each one a list of conditions that must all hold
+    groups: Vec<Vec<Condition>>,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, QueryError> {
+        let mut groups = vec![Vec::new()];
+        for token in tokenize(text)? {
+            if token.quoted.is_none() && (token.text == "OR" || token.text == "|") {
+                groups.push(Vec::new());
+                continue;
+            }
+            groups.last_mut().unwrap().push(parse_condition(&token)?);
+        }
+
+        if groups.iter().any(Vec::is_empty) {
+            return Err(QueryError("empty query".to_string()));
+        }
+        Ok(Self { groups })
+    }
+
+    /// Whether the query uses fields, operators or negations rather than just words
+    pub fn is_structured(&self) -> bool {
+        self.groups.len() > 1
+            || self
+                .groups
+                .iter()
+                .flatten()
+                .any(|condition| condition.negated || matches!(condition.term, Term::Field(..)))
+    }
+
+    pub fn matches(&self, song: &SongItem) -> bool {
+        self.groups.iter().any(|conditions| {
+            conditions
+                .iter()
+                .all(|condition| condition.term.matches(song) != condition.negated)
+        })
+    }
+}
+
+impl Term {
+    fn matches(&self, song: &SongItem) -> bool {
+        match self {
+            Term::Any(word) => [Field::Title, Field::Artist, Field::Album]
+                .into_iter()
+                .filter_map(|field| field.text(song))
+                .chain(std::iter::once(song.display_title()))
+                .any(|text| text.to_lowercase().contains(word)),
+            Term::Field(field, op, Value::Text(value)) => {
+                // Songs without the tag never match, and always match negated
+                let Some(text) = field.text(song) else {
+                    return false;
+                };
+                let text = text.to_lowercase();
+                match op {
+                    Op::Has => text.contains(value),
+                    Op::Eq => text == *value,
+                    Op::Ne => text != *value,
+                    _ => false,
+                }
+            }
+            Term::Field(field, op, Value::Number(value)) => {
+                let Some(number) = field.number(song) else {
+                    return false;
+                };
+                // Durations are compared to the second
+                let number = if *field == Field::Duration { number.floor() } else { number };
+                match op {
+                    Op::Has | Op::Eq => number == *value,
+                    Op::Ne => number != *value,
+                    Op::Lt => number < *value,
+                    Op::Le => number <= *value,
+                    Op::Gt => number > *value,
+                    Op::Ge => number >= *value,
+                }
+            }
+        }
+    }
+}
+
+struct Token {
+    text: String,
+    /// Offset of the quoted part inside `text`, for `artist:"Daft Punk"`
+    quoted: Option<usize>,
+}
+
+/// Split on whitespace, keeping double-quoted parts together
+fn tokenize(text: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut token = Token {
+            text: String::new(),
+            quoted: None,
+        };
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            if c != '"' {
+                token.text.push(c);
+                continue;
+            }
+            token.quoted = Some(token.text.len());
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.text.push(c),
+                    None => return Err(QueryError("missing closing quote".to_string())),
+                }
+            }
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_condition(token: &Token) -> Result<Condition, QueryError> {
+    let mut text = token.text.as_str();
+    let mut quoted = token.quoted;
+    let mut negated = false;
+    // A quoted `"-"` or `"!"` is a word, not a negation
+    if quoted != Some(0)
+        && let Some(rest) = text.strip_prefix(['!', '-'])
+        && !rest.is_empty()
+    {
+        negated = true;
+        text = rest;
+        quoted = quoted.map(|offset| offset - 1);
+    }
+
+    // Field names are letters, the operator must come before any quote
+    let name_len = text
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let operator = Op::parse(&text[name_len..])
+        .filter(|&(_, len)| name_len > 0 && quoted.is_none_or(|offset| offset >= name_len + len));
+
+    let Some((op, op_len)) = operator else {
+        return Ok(Condition {
+            negated,
+            term: Term::Any(text.to_lowercase()),
+        });
+    };
+
+    let name = &text[..name_len];
+    let field = Field::from_name(name)
+        .ok_or_else(|| QueryError(format!("unknown field '{}'", name)))?;
+    let value = &text[name_len + op_len..];
+    if value.is_empty() {
+        return Err(QueryError(format!("missing value after '{}'", &text[..name_len + op_len])));
+    }
+
+    let value = if field.is_numeric() {
+        let number = match field {
+            Field::Duration => parse_duration(value),
+            _ => value.parse().ok(),
+        };
+        Value::Number(number.ok_or_else(|| {
+            QueryError(format!("'{}' is not a valid {}", value, name.to_lowercase()))
+        })?)
+    } else if matches!(op, Op::Has | Op::Eq | Op::Ne) {
+        Value::Text(value.to_lowercase())
+    } else {
+        return Err(QueryError(format!("{} can only be compared with : = !=", name)));
+    };
+
+    Ok(Condition {
+        negated,
+        term: Term::Field(field, op, value),
+    })
+}
+
+/// Seconds of `90`, `90s`, `5m`, `3m30s`, `1h` or `4:30`
+fn parse_duration(value: &str) -> Option<f64> {
+    if let Some((minutes, seconds)) = value.split_once(':') {
+        let minutes: f64 = minutes.parse().ok()?;
+        let seconds: f64 = seconds.parse().ok()?;
+        return Some(minutes * 60.0 + seconds);
+    }
+
+    let mut total = 0.0;
+    let mut number = String::new();
+    for c in value.chars() {
+        let unit = match c {
+            '0'..='9' | '.' => {
+                number.push(c);
+                continue;
+            }
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        total += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        total += number.parse::<f64>().ok()?;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{TrackInfo, TrackTags};
+    use std::{path::PathBuf, time::Duration};
+
+    fn song(path: &str, artist: &str, title: &str, year: i32, secs: u64) -> SongItem {
+        let info = TrackInfo {
+            duration: Some(Duration::from_secs(secs)),
+            tags: TrackTags {
+                title: Some(title.to_string()),
+                artist: Some(artist.to_string()),
+                album: Some("Discovery".to_string()),
+                year: Some(year),
+                genre: Some("Electronic".to_string()),
+                ..TrackTags::default()
+            },
+            codec: Some("mp3".to_string()),
+            file_id: None,
+        };
+        let name = path.rsplit('/').next().unwrap().to_string();
+        SongItem::new(PathBuf::from(path), name, 0, info)
+    }
+
+    fn library() -> Vec<SongItem> {
+        vec![
+            song("music/one.mp3", "Daft Punk", "One More Time", 2000, 320),
+            song("music/digital.mp3", "Daft Punk", "Digital Love", 2001, 298),
+            song("music/live.mp3", "Justice", "D.A.N.C.E. - Live", 2008, 245),
+            song("music/dash.mp3", "The Band", "-", 1999, 60),
+            song("music/bang.mp3", "The Band", "!", 1999, 90),
+        ]
+    }
+
+    /// Titles of the songs matching `query`
+    fn titles(query: &str) -> Vec<String> {
+        let query = Query::parse(query).unwrap();
+        library()
+            .iter()
+            .filter(|song| query.matches(song))
+            .map(|song| song.tags().title.clone().unwrap())
+            .collect()
+    }
+
+    fn error(query: &str) -> String {
+        Query::parse(query).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn words_look_in_title_artist_and_album() {
+        assert_eq!(titles("daft love"), ["Digital Love"]);
+        assert_eq!(titles("DISCOVERY"), titles("discovery"));
+        assert_eq!(titles("discovery").len(), 5);
+        assert!(titles("nothing").is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_words_together() {
+        assert_eq!(titles("artist:\"daft punk\""), ["One More Time", "Digital Love"]);
+        assert_eq!(titles("\"more time\""), ["One More Time"]);
+        assert!(titles("\"time more\"").is_empty());
+        assert_eq!(titles("title=\"digital love\""), ["Digital Love"]);
+        assert_eq!(error("artist:\"daft punk"), "missing closing quote");
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(titles("!artist:\"daft punk\" !band"), ["D.A.N.C.E. - Live"]);
+        assert_eq!(titles("-punk -justice"), ["-", "!"]);
+        assert_eq!(titles("daft -\"one more\""), ["Digital Love"]);
+        // Songs without the tag do not match, so they match negated
+        assert_eq!(titles("!albumartist:x").len(), 5);
+        assert!(titles("albumartist:x").is_empty());
+    }
+
+    #[test]
+    fn dash_and_bang_alone_are_words() {
+        assert_eq!(titles("-"), ["D.A.N.C.E. - Live", "-"]);
+        assert_eq!(titles("!"), ["!"]);
+        assert_eq!(titles("\"-\""), titles("-"));
+        assert_eq!(titles("\"!live\""), Vec::<String>::new());
+        assert_eq!(titles("title=\"-\""), ["-"]);
+        assert_eq!(titles("title=!"), ["!"]);
+    }
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(titles("justice OR title:digital"), ["Digital Love", "D.A.N.C.E. - Live"]);
+        assert_eq!(titles("justice | year=1999 title=!"), ["D.A.N.C.E. - Live", "!"]);
+        // Lowercase or quoted, it is a word
+        assert!(titles("justice or digital").is_empty());
+        assert!(titles("justice \"OR\" digital").is_empty());
+        assert_eq!(error("justice OR"), "empty query");
+        assert_eq!(error("OR justice"), "empty query");
+        assert_eq!(error(""), "empty query");
+    }
+
+    #[test]
+    fn numeric_comparisons() {
+        assert_eq!(titles("year>=2001"), ["Digital Love", "D.A.N.C.E. - Live"]);
+        assert_eq!(titles("year<2000"), ["-", "!"]);
+        assert_eq!(titles("year:2000"), ["One More Time"]);
+        assert_eq!(titles("year!=1999 year<=2001"), ["One More Time", "Digital Love"]);
+        assert_eq!(error("year>soon"), "'soon' is not a valid year");
+        assert_eq!(error("title<b"), "title can only be compared with : = !=");
+    }
+
+    #[test]
+    fn duration_comparisons() {
+        assert_eq!(titles("duration>5m"), ["One More Time"]);
+        assert_eq!(titles("length<=1:30"), ["-", "!"]);
+        assert_eq!(titles("duration=4m58s"), ["Digital Love"]);
+        assert_eq!(titles("duration<1m"), Vec::<String>::new());
+        assert_eq!(parse_duration("1h2m3s"), Some(3723.0));
+        assert_eq!(parse_duration("90"), Some(90.0));
+        assert_eq!(parse_duration("1.5m"), Some(90.0));
+        assert_eq!(parse_duration("4:30"), Some(270.0));
+        assert_eq!(parse_duration("5 min"), None);
+        assert_eq!(error("duration<5x"), "'5x' is not a valid duration");
+    }
+
+    #[test]
+    fn unknown_fields_and_missing_values() {
+        assert_eq!(error("colour:blue"), "unknown field 'colour'");
+        assert_eq!(error("!mood>3"), "unknown field 'mood'");
+        assert_eq!(error("artist:"), "missing value after 'artist:'");
+        // No field name, or an operator inside quotes: a word
+        assert_eq!(titles(":").len(), 0);
+        assert_eq!(titles("\"a:b\"").len(), 0);
+        assert_eq!(titles("path:dash codec=mp3 genre:electro"), ["-"]);
+    }
+
+    #[test]
+    fn structured_queries() {
+        assert!(!Query::parse("daft punk").unwrap().is_structured());
+        assert!(Query::parse("daft -punk").unwrap().is_structured());
+        assert!(Query::parse("year>2000").unwrap().is_structured());
+        assert!(Query::parse("daft OR punk").unwrap().is_structured());
+    }
+}
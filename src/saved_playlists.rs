@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{jukebox_state::SongItem, storage};
+
+const PLAYLISTS_FILE: &str = "playlists.json";
+
+/// Song of a saved playlist: its file, and its offset for CUE sheet tracks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedSong {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub start_ms: u64,
+}
+
+impl SavedSong {
+    pub fn start(&self) -> Duration {
+        Duration::from_millis(self.start_ms)
+    }
+}
+
+impl From<&SongItem> for SavedSong {
+    fn from(song: &SongItem) -> Self {
+        Self {
+            path: absolute(song.as_path()),
+            start_ms: song.bounds().0.as_millis() as u64,
+        }
+    }
+}
+
+/// Paths are saved absolute, so playlists work whatever folder the jukebox is started from
+pub fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// What the playlist shows: the whole library or one of the saved playlists
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum View {
+    #[default]
+    Library,
+    Saved(usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPlaylist {
+    pub name: String,
+    pub songs: Vec<SavedSong>,
+    /// Smart playlists hold the library songs matching this query instead of a song list
+    #[serde(default)]
+    pub query: Option<String>,
+}
+
+impl SavedPlaylist {
+    pub fn is_smart(&self) -> bool {
+        self.query.is_some()
+    }
+}
+
+/// Named playlists made from the library, persisted in the data directory
+pub struct SavedPlaylists {
+    file: Option<PathBuf>,
+    playlists: Vec<SavedPlaylist>,
+}
+
+impl SavedPlaylists {
+    pub fn load() -> Self {
+        let file = storage::data_file(PLAYLISTS_FILE);
+        let playlists = file
+            .as_deref()
+            .map(storage::load_json)
+            .unwrap_or_default();
+
+        Self { file, playlists }
+    }
+
+    pub fn list(&self) -> &[SavedPlaylist] {
+        &self.playlists
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SavedPlaylist> {
+        self.playlists.get(index)
+    }
+
+    /// Add an empty playlist, returning its index
+    pub fn create(&mut self, name: String) -> usize {
+        self.playlists.push(SavedPlaylist {
+            name,
+            songs: Vec::new(),
+            query: None,
+        });
+        self.save();
+        self.playlists.len() - 1
+    }
+
+    /// Add a smart playlist filled by a query, returning its index
+    pub fn create_smart(&mut self, name: String, query: String) -> usize {
+        self.playlists.push(SavedPlaylist {
+            name,
+            songs: Vec::new(),
+            query: Some(query),
+        });
+        self.save();
+        self.playlists.len() - 1
+    }
+
+    pub fn rename(&mut self, index: usize, name: String) {
+        if let Some(playlist) = self.playlists.get_mut(index) {
+            playlist.name = name;
+            self.save();
+        }
+    }
+
+    pub fn delete(&mut self, index: usize) {
+        if index < self.playlists.len() {
+            self.playlists.remove(index);
+            self.save();
+        }
+    }
+
+    pub fn add(&mut self, index: usize, song: SavedSong) {
+        if let Some(playlist) = self.playlists.get_mut(index) {
+            playlist.songs.push(song);
+            self.save();
+        }
+    }
+
+    pub fn remove(&mut self, index: usize, song_index: usize) {
+        if let Some(playlist) = self.playlists.get_mut(index)
+            && song_index < playlist.songs.len()
+        {
+            playlist.songs.remove(song_index);
+            self.save();
+        }
+    }
+
+    /// Exchange two songs of a playlist
+    pub fn swap(&mut self, index: usize, a: usize, b: usize) {
+        if let Some(playlist) = self.playlists.get_mut(index)
+            && a < playlist.songs.len()
+            && b < playlist.songs.len()
+        {
+            playlist.songs.swap(a, b);
+            self.save();
+        }
+    }
+
+    /// Point the songs of moved files to their new path, keyed by the old absolute path
+    pub fn rename_paths(&mut self, renamed: &HashMap<PathBuf, PathBuf>) {
+        let mut changed = false;
+        for song in self.playlists.iter_mut().flat_map(|playlist| &mut playlist.songs) {
+            if let Some(path) = renamed.get(&song.path) {
+                song.path = path.clone();
+                changed = true;
+            }
+        }
+        if changed {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        if let Some(file) = &self.file {
+            let _ = storage::save_json(file, &self.playlists);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use std::time::Duration;
 
 pub fn make_vertical_chunks(area: Rect, proportions: &[u16]) -> Vec<Rect> {
     Layout::default()
@@ -14,4 +15,33 @@ pub fn make_horizontal_chunks(area: Rect, proportions: &[u16]) -> Vec<Rect> {
         .constraints(proportions.iter().map(|&p| Constraint::Percentage(p)).collect::<Vec<_>>())
         .split(area)
         .to_vec()
-}
\ No newline at end of file
+}
+
+/// Rectangle of the given percentage size centered inside `area`
+pub fn centered_rect(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+/// Format duration into a string "MM:SS"
+pub fn format_duration(duration: Duration) -> String {
+    let total_seconds = duration.as_secs();
+    let minutes = total_seconds / 60;
+    let seconds = total_seconds % 60;
+    format!("{:02}:{:02}", minutes, seconds)
+}
@@ -0,0 +1,90 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+};
+
+use crate::{
+    jukebox_state::JukeboxState,
+    screen::block_utils::{centered_rect, format_duration},
+};
+
+/// Ask whether a long song should restart from its bookmark
+pub fn render_resume_prompt(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState) {
+    let Some(pending) = jukebox_state.pending_resume() else {
+        return;
+    };
+
+    let song_name = pending.song.display_title();
+    let text = vec![
+        Line::from(format!(
+            "Resume \"{}\" at {}?",
+            song_name,
+            format_duration(pending.position)
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("y", Style::default().fg(Color::Green)),
+            Span::raw(" - Resume    "),
+            Span::styled("n", Style::default().fg(Color::Yellow)),
+            Span::raw(" - Start over    "),
+            Span::styled("Esc", Style::default().fg(Color::Red)),
+            Span::raw(" - Cancel"),
+        ]),
+    ];
+
+    let popup_area = centered_rect(area, 60, 25);
+    let prompt = Paragraph::new(text)
+        .block(Block::default().title("Bookmark").borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(prompt, popup_area);
+}
+
+/// List the saved bookmarks, with the selected one highlighted
+pub fn render_bookmarks_popup(
+    f: &mut Frame,
+    area: Rect,
+    jukebox_state: &JukeboxState,
+    selected: usize,
+) {
+    let popup_area = centered_rect(area, 60, 60);
+    let block = Block::default()
+        .title("Bookmarks (d - Delete, c - Clear all, Esc - Close)")
+        .borders(Borders::ALL);
+
+    f.render_widget(Clear, popup_area);
+
+    let bookmarks = jukebox_state.bookmarks();
+    if bookmarks.is_empty() {
+        let empty = Paragraph::new("No bookmarks saved").block(block);
+        f.render_widget(empty, popup_area);
+        return;
+    }
+
+    let items: Vec<ListItem> = bookmarks
+        .iter()
+        .map(|(_, bookmark)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format_duration(bookmark.position()),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw("  "),
+                Span::raw(bookmark.title.clone()),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut list_state = ListState::default();
+    list_state.select(Some(selected.min(bookmarks.len() - 1)));
+
+    f.render_stateful_widget(list, popup_area, &mut list_state);
+}
@@ -0,0 +1,101 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::{
+    browser::{Browser, Node},
+    screen::block_utils::{centered_rect, format_duration},
+};
+
+/// Artist / album / track tree of the library
+pub fn render_browser_popup(f: &mut Frame, area: Rect, browser: &Browser) {
+    let popup_area = centered_rect(area, 70, 80);
+    let block = Block::default()
+        .title("Library (Enter - Play, e - Enqueue, ←/→ - Collapse/Expand, Esc - Close)")
+        .borders(Borders::ALL);
+
+    f.render_widget(Clear, popup_area);
+
+    let rows = browser.rows();
+    if rows.is_empty() {
+        let empty = Paragraph::new("No songs in the library").block(block);
+        f.render_widget(empty, popup_area);
+        return;
+    }
+
+    let artists = browser.artists();
+    let dim = Style::default().fg(Color::DarkGray);
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|&node| {
+            let marker = if browser.is_expanded(node) { "▾ " } else { "▸ " };
+            let line = match node {
+                Node::Artist(a) => {
+                    let artist = &artists[a];
+                    Line::from(vec![
+                        Span::raw(marker),
+                        Span::styled(artist.name.clone(), Style::default().fg(Color::Cyan)),
+                        Span::styled(
+                            format!(
+                                "  {} albums, {} tracks, {}",
+                                artist.albums.len(),
+                                artist.track_count(),
+                                format_duration(artist.duration())
+                            ),
+                            dim,
+                        ),
+                    ])
+                }
+                Node::Album(a, b) => {
+                    let album = &artists[a].albums[b];
+                    let year = album.year.map(|year| format!(" ({})", year)).unwrap_or_default();
+                    Line::from(vec![
+                        Span::raw("  "),
+                        Span::raw(marker),
+                        Span::styled(
+                            format!("{}{}", album.title, year),
+                            Style::default().fg(Color::Yellow),
+                        ),
+                        Span::styled(
+                            format!(
+                                "  {} tracks, {}",
+                                album.tracks.len(),
+                                format_duration(album.duration())
+                            ),
+                            dim,
+                        ),
+                    ])
+                }
+                Node::Track(a, b, t) => {
+                    let song = &artists[a].albums[b].tracks[t];
+                    let number = song
+                        .tags()
+                        .track_number
+                        .map(|n| format!("{:02}. ", n))
+                        .unwrap_or_default();
+                    let title = song.tags().title.clone().unwrap_or_else(|| song.display_title());
+                    let duration = song.duration().map(format_duration).unwrap_or_default();
+                    Line::from(vec![
+                        Span::raw("      "),
+                        Span::raw(format!("{}{}", number, title)),
+                        Span::styled(format!("  {}", duration), dim),
+                    ])
+                }
+            };
+            ListItem::new(line)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut list_state = ListState::default();
+    list_state.select(Some(browser.selected()));
+
+    f.render_stateful_widget(list, popup_area, &mut list_state);
+}
@@ -2,7 +2,7 @@ use ratatui::{
     Frame,
     layout::Rect,
     style::{Color, Style},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, Paragraph, Wrap},
     text::{Line, Span},
 };
 
@@ -21,12 +21,45 @@ pub fn render_controls_block(f: &mut Frame, area: Rect) {
             Span::styled("↑/↓", Style::default().fg(Color::Cyan)),
             Span::raw(" - Navigate    "),
             Span::styled("+/-", Style::default().fg(Color::Magenta)),
-            Span::raw(" - Volume"),
+            Span::raw(" - Volume    "),
+            Span::styled("b", Style::default().fg(Color::Yellow)),
+            Span::raw(" - Bookmarks    "),
+            Span::styled("l", Style::default().fg(Color::LightCyan)),
+            Span::raw(" - Library    "),
+            Span::styled("m", Style::default().fg(Color::Green)),
+            Span::raw(" - Playlists (⇧↑/⇧↓ reorder, Del remove)    "),
+            Span::styled("x", Style::default().fg(Color::Gray)),
+            Span::raw(" - Export    "),
+            Span::styled("v", Style::default().fg(Color::LightGreen)),
+            Span::raw(" - Visualizer    "),
+            Span::styled("w", Style::default().fg(Color::LightBlue)),
+            Span::raw(" - Waveform    "),
+            Span::styled("y", Style::default().fg(Color::LightRed)),
+            Span::raw(" - Lyrics    "),
+            Span::styled("o", Style::default().fg(Color::LightMagenta)),
+            Span::raw(" - Sort    "),
+            Span::styled("1-5/f", Style::default().fg(Color::Yellow)),
+            Span::raw(" - Rate/Favourite    "),
+            Span::styled("e", Style::default().fg(Color::LightMagenta)),
+            Span::raw(" - Edit tags    "),
+            Span::styled("O", Style::default().fg(Color::LightBlue)),
+            Span::raw(" - Organize files    "),
+            Span::styled("D", Style::default().fg(Color::LightRed)),
+            Span::raw(" - Duplicates    "),
+            Span::styled("C", Style::default().fg(Color::LightYellow)),
+            Span::raw(" - Check files    "),
+            Span::styled("c", Style::default().fg(Color::LightCyan)),
+            Span::raw(" - Stats columns    "),
+            Span::styled("t", Style::default().fg(Color::LightGreen)),
+            Span::raw(" - Listening stats    "),
+            Span::styled("/", Style::default().fg(Color::LightYellow)),
+            Span::raw(" - Search (n/N next/prev)"),
         ]),
     ];
 
     let controls_paragraph = Paragraph::new(controls)
-        .block(Block::default().title("Controls").borders(Borders::ALL));
+        .block(Block::default().title("Controls").borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
     
     f.render_widget(controls_paragraph, area);
 }
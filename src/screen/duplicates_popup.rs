@@ -0,0 +1,82 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::{
+    duplicates::{self, DuplicateScan, Review},
+    screen::block_utils::{centered_rect, format_duration},
+};
+
+/// Progress of the duplicate search while files are hashed
+pub fn render_duplicate_scan(f: &mut Frame, area: Rect, scan: &DuplicateScan) {
+    let (hashed, total) = scan.progress();
+    let text = vec![
+        Line::from(format!("Hashing audio: {} of {} files", hashed, total)),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("Esc", Style::default().fg(Color::Red)),
+            Span::raw(" - Cancel"),
+        ]),
+    ];
+
+    let popup_area = centered_rect(area, 50, 20);
+    let popup = Paragraph::new(text)
+        .block(Block::default().title("Duplicates").borders(Borders::ALL));
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+}
+
+/// Groups of copies of the same track, the copy to keep marked in each
+pub fn render_duplicates_popup(f: &mut Frame, area: Rect, review: &Review) {
+    let popup_area = centered_rect(area, 80, 80);
+    let block = Block::default()
+        .title("Duplicates (Space - Keep, Enter - Quarantine the others, Esc - Close)")
+        .borders(Borders::ALL);
+
+    f.render_widget(Clear, popup_area);
+
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut items = Vec::new();
+    let mut selected_row = 0;
+    for (g, group) in review.groups().iter().enumerate() {
+        items.push(ListItem::new(Line::from(Span::styled(
+            group.reason.label(),
+            Style::default().fg(Color::Yellow),
+        ))));
+        for (c, copy) in group.copies.iter().enumerate() {
+            if review.selected() == (g, c) {
+                selected_row = items.len();
+            }
+            let marker = if review.keep(g) == c {
+                Span::styled("  keep  ", Style::default().fg(Color::Green))
+            } else {
+                Span::styled("  move  ", Style::default().fg(Color::Red))
+            };
+            let details = format!(
+                "  {} {} {}",
+                copy.song.duration().map(format_duration).unwrap_or_default(),
+                copy.song.codec().unwrap_or("?"),
+                duplicates::format_size(copy.size)
+            );
+            items.push(ListItem::new(Line::from(vec![
+                marker,
+                Span::raw(copy.song.as_path().display().to_string()),
+                Span::styled(details, dim),
+            ])));
+        }
+    }
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut list_state = ListState::default();
+    list_state.select(Some(selected_row));
+
+    f.render_stateful_widget(list, popup_area, &mut list_state);
+}
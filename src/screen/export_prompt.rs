@@ -0,0 +1,48 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+use crate::{jukebox_state::JukeboxState, screen::block_utils::centered_rect};
+
+/// Ask where to export the playlist or the queue, the extension choosing the format
+pub fn render_export_prompt(
+    f: &mut Frame,
+    area: Rect,
+    path: &str,
+    queue: bool,
+    jukebox_state: &JukeboxState,
+) {
+    let (source, count) = if queue {
+        ("queue", jukebox_state.queue().len())
+    } else {
+        ("playlist", jukebox_state.playlist().len())
+    };
+
+    let text = vec![
+        Line::from(format!("Export the {} ({} songs) to:", source, count)),
+        Line::from(Span::styled(format!("{}_", path), Style::default().fg(Color::Yellow))),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(".m3u8 .pls .xspf", Style::default().fg(Color::Cyan)),
+            Span::raw("    "),
+            Span::styled("Tab", Style::default().fg(Color::Green)),
+            Span::raw(" - Playlist/Queue    "),
+            Span::styled("Enter", Style::default().fg(Color::Green)),
+            Span::raw(" - Save    "),
+            Span::styled("Esc", Style::default().fg(Color::Red)),
+            Span::raw(" - Cancel"),
+        ]),
+    ];
+
+    let popup_area = centered_rect(area, 60, 25);
+    let prompt = Paragraph::new(text)
+        .block(Block::default().title("Export").borders(Borders::ALL))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(prompt, popup_area);
+}
@@ -1,20 +1,19 @@
-use crate::{jukebox_state::JukeboxState, screen::block_utils::make_horizontal_chunks};
+use crate::{
+    jukebox_state::JukeboxState,
+    level_meter::{self, ChannelLevel, LevelMeter},
+    stream::StreamStatus,
+    screen::block_utils::{format_duration, make_horizontal_chunks},
+    waveform::{self, Waveform},
+};
 use ratatui::{
     Frame,
     layout::Rect,
     style::{Color, Style},
-    widgets::{Block, Borders, Gauge},
+    text::{Line, Span},
+    widgets::{Block, Borders, Gauge, Paragraph},
 };
 use std::time::Duration;
 
-/// Format duration into a string "MM:SS"
-fn format_duration(duration: Duration) -> String {
-    let total_seconds = duration.as_secs();
-    let minutes = total_seconds / 60;
-    let seconds = total_seconds % 60;
-    format!("{:02}:{:02}", minutes, seconds)
-}
-
 /// Get emoji based on volume level
 fn get_volume_emoji(volume: u8) -> &'static str {
     match volume {
@@ -26,18 +25,38 @@ fn get_volume_emoji(volume: u8) -> &'static str {
     }
 }
 
+/// Block characters used to draw the waveform, in eighths of a row
+const WAVE_EIGHTHS: [&str; 8] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
 /// Disegna il blocco delle informazioni con progress bar e volume
-pub fn render_info_block(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState) {
+pub fn render_info_block(
+    f: &mut Frame,
+    area: Rect,
+    jukebox_state: &JukeboxState,
+    show_waveform: bool,
+    level_meter: &LevelMeter,
+) {
     // Split area into two parts: progress (70%) and volume (30%)
     let chunks = make_horizontal_chunks(area, &[70, 30]);
 
-    render_progress_bar(f, chunks[0], jukebox_state);
+    let waveform = show_waveform
+        .then(|| jukebox_state.current_waveform())
+        .flatten();
+    match waveform {
+        Some(waveform) => render_waveform(f, chunks[0], jukebox_state, &waveform),
+        None => render_progress_bar(f, chunks[0], jukebox_state),
+    }
     
-    render_volume_bar(f, chunks[1], jukebox_state);
+    // Volume gauge with the level meters next to it
+    let volume_chunks = make_horizontal_chunks(chunks[1], &[50, 50]);
+    render_volume_bar(f, volume_chunks[0], jukebox_state);
+    render_level_meters(f, volume_chunks[1], level_meter);
 }
 
 fn render_progress_bar(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState) {
-    if let Some(playing_song) = jukebox_state.currently_playing() {
+    if let Some(status) = jukebox_state.stream_status() {
+        render_stream_status(f, area, jukebox_state, &status);
+    } else if let Some(playing_song) = jukebox_state.currently_playing() {
         let current_pos = jukebox_state.current_playback_position();
         let total_duration = playing_song.duration().unwrap_or(Duration::from_secs(0));
         let progress_ratio = jukebox_state.progress_ratio();
@@ -65,6 +84,79 @@ fn render_progress_bar(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState)
     }
 }
 
+/// Waveform overview of the whole song, with the played part highlighted
+fn render_waveform(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState, waveform: &Waveform) {
+    let total_duration = jukebox_state
+        .currently_playing()
+        .and_then(|song| song.duration())
+        .unwrap_or_default();
+    let title = format!(
+        "Progress {} / {}",
+        format_duration(jukebox_state.current_playback_position()),
+        format_duration(total_duration)
+    );
+    let block = Block::default().title(title).borders(Borders::ALL);
+
+    let inner = block.inner(area);
+    let width = inner.width as usize;
+    let height = inner.height as usize;
+    let played_columns = (jukebox_state.progress_ratio() * width as f32) as usize;
+    let columns = waveform::resample(waveform, width);
+
+    // Build rows from the top; each column is a bar growing from the bottom
+    let lines: Vec<Line> = (0..height)
+        .map(|row| {
+            let row_from_bottom = height - 1 - row;
+            let spans: Vec<Span> = columns
+                .iter()
+                .enumerate()
+                .map(|(x, &peak)| {
+                    let eighths = peak as usize * height * 8 / 255;
+                    let filled = eighths.saturating_sub(row_from_bottom * 8).min(8);
+                    let color = if x < played_columns { Color::Green } else { Color::DarkGray };
+                    let symbol = if filled == 0 { " " } else { WAVE_EIGHTHS[filled - 1] };
+                    Span::styled(symbol, Style::default().fg(color))
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect();
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Streams have no duration: show the live title and the connection state instead
+fn render_stream_status(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState, status: &StreamStatus) {
+    let elapsed = format_duration(jukebox_state.current_playback_position());
+    let title = status
+        .title
+        .clone()
+        .or_else(|| status.station.clone())
+        .or_else(|| jukebox_state.currently_playing().map(|song| song.display_title()))
+        .unwrap_or_default();
+
+    let (label, ratio, color) = if let Some(error) = &status.error {
+        (format!("⚠ {}", error), 0.0, Color::Red)
+    } else if status.buffering {
+        let percent = status.buffer_percent;
+        (format!("Buffering {}%  {}", percent, title), percent as f64 / 100.0, Color::Yellow)
+    } else {
+        (format!("📻 {}  {}", title, elapsed), 1.0, Color::Green)
+    };
+
+    let block_title = match status.reconnects {
+        0 => "Stream".to_string(),
+        n => format!("Stream (reconnected {}x)", n),
+    };
+
+    let stream_bar = Gauge::default()
+        .block(Block::default().title(block_title).borders(Borders::ALL))
+        .gauge_style(Style::default().fg(color))
+        .label(label)
+        .ratio(ratio);
+    f.render_widget(stream_bar, area);
+}
+
 fn render_volume_bar(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState) {
     let volume = jukebox_state.volume();
     let volume_emoji = get_volume_emoji(volume);
@@ -80,3 +172,59 @@ fn render_volume_bar(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState) {
         .ratio(volume_ratio);
     f.render_widget(volume_bar, area);
 }
+
+/// RMS bars with a peak-hold marker per channel, and a clip light in the title
+fn render_level_meters(f: &mut Frame, area: Rect, level_meter: &LevelMeter) {
+    let clipping = level_meter.channels().iter().any(|level| level.is_clipping());
+    let title = if clipping {
+        Line::from(vec![
+            Span::raw("Levels "),
+            Span::styled("CLIP", Style::default().fg(Color::White).bg(Color::Red)),
+        ])
+    } else {
+        Line::from("Levels")
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+
+    let width = block.inner(area).width.saturating_sub(2) as usize;
+    let lines: Vec<Line> = level_meter
+        .channels()
+        .iter()
+        .zip(["L", "R"])
+        .map(|(level, label)| {
+            let mut spans = vec![Span::raw(format!("{} ", label))];
+            spans.extend(meter_cells(level, width));
+            Line::from(spans)
+        })
+        .collect();
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn meter_cells(level: &ChannelLevel, width: usize) -> Vec<Span<'static>> {
+    let db_at = |cell: usize| {
+        level_meter::MIN_DB - level_meter::MIN_DB * (cell + 1) as f32 / width as f32
+    };
+    let peak_cell = (0..width).rev().find(|&cell| level.peak_hold_db >= db_at(cell));
+
+    (0..width)
+        .map(|cell| {
+            let db = db_at(cell);
+            let color = if db > -3.0 {
+                Color::Red
+            } else if db > -12.0 {
+                Color::Yellow
+            } else {
+                Color::Green
+            };
+
+            if level.rms_db >= db {
+                Span::styled("█", Style::default().fg(color))
+            } else if peak_cell == Some(cell) {
+                Span::styled("▌", Style::default().fg(color))
+            } else {
+                Span::styled("·", Style::default().fg(Color::DarkGray))
+            }
+        })
+        .collect()
+}
@@ -2,13 +2,20 @@ use ratatui::{
     layout::Rect, text::{Line}, widgets::{Block, Borders, Paragraph}, Frame
 };
 
-use crate::{canvas_state::CanvasState, jukebox_state::JukeboxState};
+use crate::{
+    canvas_state::{CanvasState, Visualizer},
+    jukebox_state::JukeboxState,
+};
 
 /// Disegna la matrice di caratteri del jukebox
 pub fn render_jukebox_matrix(f: &mut Frame, area: Rect, state: &mut CanvasState, jukebox_state: &JukeboxState) {
     // Update the canvas state with the current jukebox state
-    state.update_notes(area.width, area.height, jukebox_state.is_playing());
+    state.update_notes(area.width, area.height, jukebox_state.is_playing(), jukebox_state.sample_tap());
     state.update_is_playing(jukebox_state.is_playing());
+    state.set_cover(jukebox_state.current_cover());
+    if state.visualizer() == Visualizer::Spectrum {
+        state.update_spectrum(area.width, jukebox_state.sample_tap());
+    }
 
     let canvas = state.get_canvas(area.width, area.height);
 
@@ -0,0 +1,64 @@
+use ratatui::{
+    Frame,
+    layout::{Alignment, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph, Wrap},
+};
+
+use crate::jukebox_state::JukeboxState;
+
+/// Lyrics of the playing song in place of the jukebox, following the music when synchronised
+pub fn render_lyrics_pane(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState) {
+    let block = Block::default().title("Lyrics").borders(Borders::RIGHT);
+
+    let Some(lyrics) = jukebox_state.current_lyrics() else {
+        let message = if jukebox_state.currently_playing().is_some() {
+            "No lyrics found for this song"
+        } else {
+            "Play a song to see its lyrics"
+        };
+        let paragraph = Paragraph::new(message)
+            .block(block)
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        f.render_widget(paragraph, area);
+        return;
+    };
+
+    let position = jukebox_state.current_playback_position();
+    let current = lyrics.current_line(position);
+    let lines: Vec<Line> = lyrics
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let style = match current {
+                Some(current) if i == current => Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                Some(current) if i < current => Style::default().fg(Color::DarkGray),
+                _ => Style::default(),
+            };
+            Line::styled(line.text.clone(), style)
+        })
+        .collect();
+
+    // Keep the current line in the middle, or scroll along with the song when unsynchronised
+    let visible = area.height.saturating_sub(1) as usize;
+    let scroll = match current {
+        Some(current) => current.saturating_sub(visible / 2),
+        None if lyrics.is_synced() => 0,
+        None => {
+            let hidden = lines.len().saturating_sub(visible);
+            (hidden as f32 * jukebox_state.progress_ratio()) as usize
+        }
+    };
+
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .scroll((scroll as u16, 0));
+    f.render_widget(paragraph, area);
+}
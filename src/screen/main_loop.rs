@@ -1,31 +1,133 @@
-use std::io;
-use std::path::{Path, PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crossterm::event;
+use crossterm::event::{self, KeyCode, KeyModifiers};
 use ratatui::{Terminal, prelude::Backend};
 
+use super::bookmarks_popup::{render_bookmarks_popup, render_resume_prompt};
+use super::export_prompt::render_export_prompt;
+use super::browser_popup::render_browser_popup;
 use super::controls_block::render_controls_block;
+use super::duplicates_popup::{render_duplicate_scan, render_duplicates_popup};
 use super::info_block::render_info_block;
+use super::lyrics_pane::render_lyrics_pane;
+use super::organize_popup::render_organize_popup;
 use super::playlist_side::render_playlist_side;
+use super::playlists_popup::render_playlists_popup;
+use super::stats_screen::render_stats_screen;
+use super::tag_editor_popup::render_tag_editor_popup;
+use super::toast::{Toast, render_toast};
 use crate::{
-    canvas_state, jukebox_state,
+    browser::Browser,
+    canvas_state,
+    config::Config,
+    duplicates::{DuplicateScan, Review},
+    jukebox_state::{self, JukeboxState},
+    level_meter::LevelMeter,
+    listen_log::{Period, Report},
+    organizer::Plan,
+    path_pattern::PathPattern,
+    playlist_file,
+    saved_playlists::View,
+    search::Search,
+    tag_editor::TagForm,
+    tag_writer,
     screen::{
         block_utils::{make_horizontal_chunks, make_vertical_chunks},
         jukebox_side::render_jukebox_matrix,
     },
 };
 
+/// Popup currently drawn on top of the main screen
+enum Overlay {
+    None,
+    Bookmarks { selected: usize },
+    Browser(Browser),
+    /// Path typed for exporting the playlist, or the queue when `queue` is set
+    Export { path: String, queue: bool },
+    /// Library and saved playlists, row 0 being the library. `name` is being typed
+    /// for a new playlist, or for the selected one when `renaming`
+    Playlists {
+        selected: usize,
+        name: Option<String>,
+        renaming: bool,
+    },
+    Stats { period: Period, report: Box<Report> },
+    TagEditor(Box<TagForm>),
+    /// Files being hashed in search of duplicates
+    DuplicateScan(DuplicateScan),
+    Duplicates(Box<Review>),
+    /// Layout pattern being typed, with the moves it makes or why it cannot be used
+    Organize {
+        pattern: String,
+        plan: Box<Result<Plan, String>>,
+    },
+}
+
 pub fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
-    music_path: Option<PathBuf>,
+    sources: Vec<String>,
+    config: &Config,
 ) -> io::Result<()> {
     terminal.clear()?;
     terminal.hide_cursor()?;
 
-    let music_path = music_path.unwrap_or_else(|| Path::new("example_music").to_path_buf());
-    let mut jukebox_state = jukebox_state::JukeboxState::new(&music_path);
+    let sources = if sources.is_empty() {
+        vec!["example_music".to_string()]
+    } else {
+        sources
+    };
+    let mut jukebox_state = jukebox_state::JukeboxState::new(&sources, config);
     let mut canvas_state = canvas_state::CanvasState::new();
+    let mut overlay = Overlay::None;
+    let mut show_waveform = true;
+    let mut show_lyrics = false;
+    let mut show_stats = false;
+    let mut level_meter = LevelMeter::new();
+    let mut toast: Option<Toast> = None;
+    let mut search: Option<Search> = None;
     loop {
+        // Pick up files added, removed or modified in the music directories
+        if let Some(changes) = jukebox_state.poll_library() {
+            toast = Some(Toast::new(changes.summary()));
+            if let Overlay::Browser(browser) = &mut overlay {
+                browser.refresh(jukebox_state.library());
+            }
+        }
+        if let Overlay::DuplicateScan(scan) = &overlay
+            && let Some(groups) = scan.poll()
+        {
+            if groups.is_empty() {
+                toast = Some(Toast::new("No duplicates found".to_string()));
+                overlay = Overlay::None;
+            } else {
+                overlay = Overlay::Duplicates(Box::new(Review::new(groups)));
+            }
+        }
+        if let Some(broken) = jukebox_state.poll_check() {
+            let message = match broken {
+                0 => "All files play to the end".to_string(),
+                _ => format!("{} files with problems, marked ⚠ in the playlist", broken),
+            };
+            toast = Some(Toast::new(message));
+        }
+        if let Some(error) = jukebox_state.take_play_error() {
+            toast = Some(Toast::new(error));
+        }
+        if toast.as_ref().is_some_and(Toast::is_expired) {
+            toast = None;
+        }
+        // The playlist may have been refreshed or re-sorted since the last match
+        if let Some(search) = &mut search {
+            search.update(jukebox_state.playlist());
+        }
+
+        // A paused song leaves its last samples in the tap: show silence instead
+        let audible_volume = if jukebox_state.is_playing() { jukebox_state.volume() } else { 0 };
+        level_meter.update(jukebox_state.sample_tap(), audible_volume);
         terminal.draw(|f| {
             let size = f.area();
 
@@ -39,32 +141,505 @@ pub fn run_app<B: Backend>(
             let info_chunk = controls_info_chunk[1]; // Show info block
             let song_chunk = top_chunks[1]; // Show playlist side
 
-            render_info_block(f, info_chunk, &jukebox_state);
-            render_playlist_side(f, song_chunk, &jukebox_state);
-            render_jukebox_matrix(f, jukebox_chunk, &mut canvas_state, &jukebox_state);
+            render_info_block(f, info_chunk, &jukebox_state, show_waveform, &level_meter);
+            render_playlist_side(f, song_chunk, &jukebox_state, search.as_ref(), show_stats);
+            if show_lyrics {
+                render_lyrics_pane(f, jukebox_chunk, &jukebox_state);
+            } else {
+                render_jukebox_matrix(f, jukebox_chunk, &mut canvas_state, &jukebox_state);
+            }
             render_controls_block(f, controls_chunk);
+
+            match &overlay {
+                Overlay::Bookmarks { selected } => {
+                    render_bookmarks_popup(f, size, &jukebox_state, *selected)
+                }
+                Overlay::Browser(browser) => render_browser_popup(f, size, browser),
+                Overlay::Export { path, queue } => {
+                    render_export_prompt(f, size, path, *queue, &jukebox_state)
+                }
+                Overlay::Playlists {
+                    selected,
+                    name,
+                    renaming,
+                } => render_playlists_popup(
+                    f,
+                    size,
+                    &jukebox_state,
+                    *selected,
+                    name.as_deref(),
+                    *renaming,
+                ),
+                Overlay::Stats { report, .. } => render_stats_screen(f, size, report),
+                Overlay::TagEditor(form) => render_tag_editor_popup(f, size, form),
+                Overlay::DuplicateScan(scan) => render_duplicate_scan(f, size, scan),
+                Overlay::Duplicates(review) => render_duplicates_popup(f, size, review),
+                Overlay::Organize { pattern, plan } => {
+                    render_organize_popup(f, size, pattern, plan)
+                }
+                Overlay::None => {}
+            }
+            render_resume_prompt(f, size, &jukebox_state);
+
+            if let Some(toast) = &toast {
+                render_toast(f, size, toast);
+            }
         })?;
 
         // Check if the song has ended
         jukebox_state.handle_song_end();
 
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let event::Event::Key(key) = event::read()? {
-                if key.kind == event::KeyEventKind::Press {
+        if event::poll(std::time::Duration::from_millis(100))?
+            && let event::Event::Key(key) = event::read()?
+            && key.kind == event::KeyEventKind::Press
+        {
+            // The resume prompt takes precedence over everything else
+            if jukebox_state.pending_resume().is_some() {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter => jukebox_state.confirm_resume(true),
+                    KeyCode::Char('n') => jukebox_state.confirm_resume(false),
+                    KeyCode::Esc => jukebox_state.cancel_resume(),
+                    _ => {}
+                }
+                continue;
+            }
+
+            // While typing a search, keys go to the search prompt
+            if let Some(active) = &mut search
+                && active.is_editing()
+            {
+                match key.code {
+                    KeyCode::Esc => search = None,
+                    KeyCode::Enter => active.finish_editing(),
+                    KeyCode::Tab => active.toggle_filter(),
+                    // Keep a structured query as a smart playlist
+                    KeyCode::Char('s')
+                        if key.modifiers.contains(KeyModifiers::CONTROL) && active.is_structured() =>
+                    {
+                        jukebox_state.create_smart_playlist(active.query().to_string());
+                        toast = Some(Toast::new(format!("Saved smart playlist {}", active.query())));
+                    }
+                    KeyCode::Down => step_match(active, &mut jukebox_state, true),
+                    KeyCode::Up => step_match(active, &mut jukebox_state, false),
+                    KeyCode::Backspace | KeyCode::Char(_) => {
+                        match key.code {
+                            KeyCode::Char(c) => active.push(c),
+                            _ => active.pop(),
+                        }
+                        active.update(jukebox_state.playlist());
+                        if let Some(position) = active.best_match() {
+                            jukebox_state.select(position);
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            match &mut overlay {
+                Overlay::Bookmarks { selected } => {
+                    if !handle_bookmarks_key(key.code, selected, &mut jukebox_state) {
+                        overlay = Overlay::None;
+                    }
+                }
+                Overlay::Browser(browser) => match key.code {
+                    KeyCode::Esc | KeyCode::Char('l') | KeyCode::Char('q') => overlay = Overlay::None,
+                    KeyCode::Down => browser.move_selection(1),
+                    KeyCode::Up => browser.move_selection(-1),
+                    KeyCode::Right => browser.expand(),
+                    KeyCode::Left => browser.collapse(),
+                    KeyCode::Char(' ') => browser.toggle(),
+                    KeyCode::Enter => jukebox_state.play_songs(browser.selected_songs()),
+                    KeyCode::Char('e') => {
+                        let songs = browser.selected_songs();
+                        toast = Some(Toast::new(format!("Queued {} songs", songs.len())));
+                        jukebox_state.enqueue(songs);
+                    }
+                    _ => {}
+                },
+                Overlay::Export { path, queue } => match key.code {
+                    KeyCode::Esc => overlay = Overlay::None,
+                    KeyCode::Tab => *queue = !*queue,
+                    KeyCode::Backspace => {
+                        path.pop();
+                    }
+                    KeyCode::Char(c) => path.push(c),
+                    KeyCode::Enter => {
+                        let message = export_playlist(&jukebox_state, path, *queue);
+                        toast = Some(Toast::new(message));
+                        overlay = Overlay::None;
+                    }
+                    _ => {}
+                },
+                Overlay::Playlists {
+                    selected,
+                    name,
+                    renaming,
+                } => {
+                    if let Some(typed) = name {
+                        match key.code {
+                            KeyCode::Esc => *name = None,
+                            KeyCode::Backspace => {
+                                typed.pop();
+                            }
+                            KeyCode::Char(c) => typed.push(c),
+                            KeyCode::Enter => {
+                                let typed = typed.trim().to_string();
+                                if !typed.is_empty() {
+                                    if *renaming {
+                                        jukebox_state.rename_playlist(*selected - 1, typed);
+                                    } else {
+                                        *selected = jukebox_state.create_playlist(typed) + 1;
+                                    }
+                                }
+                                *name = None;
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    let count = jukebox_state.saved_playlists().list().len() + 1;
+                    // Row 0 is the library, the others are saved playlists
+                    let playlist = selected.checked_sub(1);
                     match key.code {
-                        event::KeyCode::Char('q') => break,
-                        event::KeyCode::Char('p') => jukebox_state.play(),
-                        event::KeyCode::Char('s') => jukebox_state.pause(),
-                        event::KeyCode::Char('+') => jukebox_state.add_volume(10),
-                        event::KeyCode::Char('-') => jukebox_state.sub_volume(10),
-                        event::KeyCode::Down => jukebox_state.move_selection(1),
-                        event::KeyCode::Up => jukebox_state.move_selection(-1),
-                        event::KeyCode::Enter => jukebox_state.play(),
+                        KeyCode::Esc | KeyCode::Char('m') | KeyCode::Char('q') => {
+                            overlay = Overlay::None
+                        }
+                        KeyCode::Down => *selected = (*selected + 1) % count,
+                        KeyCode::Up => *selected = (*selected + count - 1) % count,
+                        KeyCode::Enter => {
+                            jukebox_state.set_view(playlist.map_or(View::Library, View::Saved));
+                            overlay = Overlay::None;
+                        }
+                        KeyCode::Char('n') => {
+                            *name = Some(String::new());
+                            *renaming = false;
+                        }
+                        KeyCode::Char('r') if let Some(playlist) = playlist => {
+                            let current = jukebox_state.saved_playlists().get(playlist);
+                            *name = current.map(|playlist| playlist.name.clone());
+                            *renaming = true;
+                        }
+                        KeyCode::Char('d') if let Some(playlist) = playlist => {
+                            jukebox_state.delete_playlist(playlist);
+                            *selected = (*selected).min(count - 2);
+                        }
+                        KeyCode::Char('a') if let Some(playlist) = playlist => {
+                            let song = jukebox_state.current_selection().clone();
+                            let message = match jukebox_state.add_to_playlist(playlist, &song) {
+                                Ok(()) => format!("Added {} to the playlist", song.display_title()),
+                                Err(reason) => reason.to_string(),
+                            };
+                            toast = Some(Toast::new(message));
+                        }
                         _ => {}
                     }
                 }
+                Overlay::Stats { period, report } => match key.code {
+                    KeyCode::Esc | KeyCode::Char('t') | KeyCode::Char('q') => overlay = Overlay::None,
+                    KeyCode::Tab => {
+                        *period = period.next();
+                        **report = stats_report(&jukebox_state, *period);
+                    }
+                    _ => {}
+                },
+                Overlay::TagEditor(form) => match key.code {
+                    KeyCode::Esc => overlay = Overlay::None,
+                    KeyCode::Down | KeyCode::Tab => form.move_selection(1),
+                    KeyCode::Up | KeyCode::BackTab => form.move_selection(-1),
+                    KeyCode::Backspace => form.pop(),
+                    KeyCode::Char(c) => form.push(c),
+                    KeyCode::Enter => {
+                        let saved = form.tags().and_then(|tags| {
+                            jukebox_state
+                                .edit_tags(form.path(), &tags)
+                                .map_err(|error| format!("Saving tags failed: {}", error))
+                        });
+                        match saved {
+                            Ok(()) => {
+                                toast = Some(Toast::new("Tags saved".to_string()));
+                                overlay = Overlay::None;
+                            }
+                            // The form stays open to fix the values
+                            Err(message) => toast = Some(Toast::new(message)),
+                        }
+                    }
+                    _ => {}
+                },
+                // Hashing goes on in the background, its result is dropped
+                Overlay::DuplicateScan(_) => {
+                    if key.code == KeyCode::Esc {
+                        overlay = Overlay::None;
+                    }
+                }
+                Overlay::Duplicates(review) => match key.code {
+                    KeyCode::Esc | KeyCode::Char('D') | KeyCode::Char('q') => {
+                        overlay = Overlay::None
+                    }
+                    KeyCode::Down => review.move_selection(1),
+                    KeyCode::Up => review.move_selection(-1),
+                    KeyCode::Char(' ') => review.keep_selected(),
+                    KeyCode::Enter => {
+                        if let Some((keep, others)) = review.selected_group() {
+                            let others: Vec<PathBuf> =
+                                others.iter().map(|song| song.as_path().to_path_buf()).collect();
+                            let failed = jukebox_state.quarantine(keep.as_path(), &others);
+                            let message = match failed.first() {
+                                None => {
+                                    review.remove_selected();
+                                    format!("Moved {} copies to quarantine", others.len())
+                                }
+                                Some((path, error)) => format!(
+                                    "{} of {} copies not moved: {}: {}",
+                                    failed.len(),
+                                    others.len(),
+                                    path.display(),
+                                    error
+                                ),
+                            };
+                            toast = Some(Toast::new(message));
+                        }
+                        if review.groups().is_empty() {
+                            overlay = Overlay::None;
+                        }
+                    }
+                    _ => {}
+                },
+                Overlay::Organize { pattern, plan } => match key.code {
+                    KeyCode::Esc => overlay = Overlay::None,
+                    KeyCode::Backspace | KeyCode::Char(_) => {
+                        match key.code {
+                            KeyCode::Char(c) => pattern.push(c),
+                            _ => {
+                                pattern.pop();
+                            }
+                        }
+                        **plan = organize_plan(&jukebox_state, pattern);
+                    }
+                    KeyCode::Enter => {
+                        let message = match plan.as_ref() {
+                            Ok(plan) if plan.moves.is_empty() => "Nothing to move".to_string(),
+                            Ok(plan) => {
+                                let failed = jukebox_state.organize(plan);
+                                match failed.first() {
+                                    None => format!("Moved {} files", plan.moves.len()),
+                                    Some((file, error)) => format!(
+                                        "Moved {} files, {} failed: {}: {}",
+                                        plan.moves.len() - failed.len(),
+                                        failed.len(),
+                                        file.from.display(),
+                                        error
+                                    ),
+                                }
+                            }
+                            Err(error) => error.clone(),
+                        };
+                        toast = Some(Toast::new(message));
+                        overlay = Overlay::None;
+                    }
+                    _ => {}
+                },
+                Overlay::None => match key.code {
+                    KeyCode::Char('q') => break,
+                    KeyCode::Char('p') => jukebox_state.play(),
+                    KeyCode::Char('s') => jukebox_state.pause(),
+                    KeyCode::Char('+') => jukebox_state.add_volume(10),
+                    KeyCode::Char('-') => jukebox_state.sub_volume(10),
+                    KeyCode::Char('b') => overlay = Overlay::Bookmarks { selected: 0 },
+                    KeyCode::Char('x') => {
+                        overlay = Overlay::Export {
+                            path: "playlist.m3u8".to_string(),
+                            queue: false,
+                        }
+                    }
+                    KeyCode::Char('l') => {
+                        overlay = Overlay::Browser(Browser::new(jukebox_state.library()))
+                    }
+                    KeyCode::Char('m') => {
+                        let selected = match jukebox_state.view() {
+                            View::Library => 0,
+                            View::Saved(index) => index + 1,
+                        };
+                        overlay = Overlay::Playlists {
+                            selected,
+                            name: None,
+                            renaming: false,
+                        }
+                    }
+                    KeyCode::Char('t') => {
+                        let period = Period::Week;
+                        let report = Box::new(stats_report(&jukebox_state, period));
+                        overlay = Overlay::Stats { period, report };
+                    }
+                    KeyCode::Char('v') => canvas_state.toggle_visualizer(),
+                    KeyCode::Char('w') => show_waveform = !show_waveform,
+                    KeyCode::Char('y') => show_lyrics = !show_lyrics,
+                    KeyCode::Char('o') => jukebox_state.cycle_sort_mode(),
+                    KeyCode::Char('c') => show_stats = !show_stats,
+                    KeyCode::Char('D') => {
+                        let songs = jukebox_state.library().to_vec();
+                        overlay = Overlay::DuplicateScan(DuplicateScan::start(songs));
+                    }
+                    KeyCode::Char('C') => {
+                        let message = if jukebox_state.start_check() {
+                            "Checking the library files"
+                        } else {
+                            "A check is running already"
+                        };
+                        toast = Some(Toast::new(message.to_string()));
+                    }
+                    KeyCode::Char('O') => {
+                        let pattern = config.organize_pattern.as_str().to_string();
+                        let plan = Box::new(organize_plan(&jukebox_state, &pattern));
+                        overlay = Overlay::Organize { pattern, plan };
+                    }
+                    KeyCode::Char('e') => {
+                        let song = jukebox_state.current_selection();
+                        // CUE tracks share their file, their tags live in the CUE sheet
+                        let message = if song.is_stream() || song.is_segment() {
+                            Some("Only whole audio files can be tagged")
+                        } else if !tag_writer::is_writable(song.as_path()) {
+                            Some("Tags of this format cannot be written")
+                        } else {
+                            None
+                        };
+                        match message {
+                            Some(message) => toast = Some(Toast::new(message.to_string())),
+                            None => overlay = Overlay::TagEditor(Box::new(TagForm::new(song))),
+                        }
+                    }
+                    KeyCode::Char(c @ '0'..='5') => {
+                        let stars = c as u8 - b'0';
+                        if jukebox_state.rate_selection(stars) {
+                            let message = match stars {
+                                0 => "Rating cleared".to_string(),
+                                _ => format!("Rated {}", "★".repeat(stars as usize)),
+                            };
+                            toast = Some(Toast::new(message));
+                        }
+                    }
+                    KeyCode::Char('f') => {
+                        if let Some(favourite) = jukebox_state.toggle_favourite_selection() {
+                            let message = if favourite {
+                                "Added to favourites"
+                            } else {
+                                "Removed from favourites"
+                            };
+                            toast = Some(Toast::new(message.to_string()));
+                        }
+                    }
+                    KeyCode::Char('/') => search = Some(Search::new()),
+                    KeyCode::Char('n') if let Some(active) = &search => {
+                        step_match(active, &mut jukebox_state, true)
+                    }
+                    KeyCode::Char('N') if let Some(active) = &search => {
+                        step_match(active, &mut jukebox_state, false)
+                    }
+                    KeyCode::Tab if let Some(active) = &mut search => active.toggle_filter(),
+                    KeyCode::Esc => search = None,
+                    // Reordering and removing only apply to saved playlists
+                    KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        jukebox_state.move_selected_in_view(1)
+                    }
+                    KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                        jukebox_state.move_selected_in_view(-1)
+                    }
+                    KeyCode::Delete => jukebox_state.remove_selected_from_view(),
+                    KeyCode::Down => move_selection(search.as_ref(), &mut jukebox_state, true),
+                    KeyCode::Up => move_selection(search.as_ref(), &mut jukebox_state, false),
+                    KeyCode::Enter => jukebox_state.play(),
+                    _ => {}
+                },
             }
         }
     }
+
+    // Stopping saves the bookmark of the song being played
+    jukebox_state.stop();
     Ok(())
 }
+
+/// Write the playlist (or the queue) to `path`, returning the message to show
+fn export_playlist(jukebox_state: &JukeboxState, path: &str, queue: bool) -> String {
+    let (entries, songs) = if queue {
+        let queue = jukebox_state.queue();
+        (playlist_file::entries_for(queue), queue.len())
+    } else {
+        let playlist = jukebox_state.playlist();
+        (playlist_file::entries_for(playlist), playlist.len())
+    };
+
+    match playlist_file::save(Path::new(path), &entries) {
+        Ok(()) if entries.len() < songs => format!(
+            "Exported {} songs to {}, CUE tracks as their whole file",
+            entries.len(),
+            path
+        ),
+        Ok(()) => format!("Exported {} songs to {}", entries.len(), path),
+        Err(error) => format!("Export failed: {}", error),
+    }
+}
+
+/// Listening statistics over `period`, read from the listen log
+fn stats_report(jukebox_state: &JukeboxState, period: Period) -> Report {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Report::build(&jukebox_state.listen_log().load(), period, now)
+}
+
+/// Moves organizing the music directories with the typed pattern, or why it cannot be used
+fn organize_plan(jukebox_state: &JukeboxState, pattern: &str) -> Result<Plan, String> {
+    let pattern = PathPattern::parse(pattern).map_err(|error| error.to_string())?;
+    if !pattern.can_name_files() {
+        return Err("{*} only skips text when reading paths, it cannot name files".to_string());
+    }
+    // The library would lose sight of the files moved into folders
+    if pattern.has_folders() && !jukebox_state.is_recursive() {
+        return Err("folders are only read with --recursive, use a pattern without /".to_string());
+    }
+    Ok(jukebox_state.plan_organize(&pattern))
+}
+
+/// Select the next (or previous) search match after the selected song
+fn step_match(search: &Search, jukebox_state: &mut JukeboxState, forward: bool) {
+    let current = jukebox_state.current_selection().position();
+    if let Some(position) = search.step(current, forward) {
+        jukebox_state.select(position);
+    }
+}
+
+/// Move the selection, through the matches only when the playlist is filtered
+fn move_selection(search: Option<&Search>, jukebox_state: &mut JukeboxState, forward: bool) {
+    match search {
+        Some(search) if search.is_filtering() => step_match(search, jukebox_state, forward),
+        _ => jukebox_state.move_selection(if forward { 1 } else { -1 }),
+    }
+}
+
+/// Handle a key press in the bookmarks popup, returns false when the popup should close
+fn handle_bookmarks_key(code: KeyCode, selected: &mut usize, jukebox_state: &mut JukeboxState) -> bool {
+    let count = jukebox_state.bookmarks().len();
+    match code {
+        KeyCode::Esc | KeyCode::Char('b') | KeyCode::Char('q') => return false,
+        KeyCode::Down if count > 0 => *selected = (*selected + 1) % count,
+        KeyCode::Up if count > 0 => *selected = (*selected + count - 1) % count,
+        KeyCode::Char('d') => {
+            let id = jukebox_state
+                .bookmarks()
+                .get(*selected)
+                .map(|(id, _)| id.to_string());
+            if let Some(id) = id {
+                jukebox_state.remove_bookmark(&id);
+                *selected = (*selected).min(count.saturating_sub(2));
+            }
+        }
+        KeyCode::Char('c') => {
+            jukebox_state.clear_bookmarks();
+            *selected = 0;
+        }
+        _ => {}
+    }
+    true
+}
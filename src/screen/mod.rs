@@ -3,4 +3,14 @@ pub mod playlist_side;
 pub mod jukebox_side;
 pub mod controls_block;
 pub mod info_block;
-mod block_utils;
\ No newline at end of file
+pub mod bookmarks_popup;
+pub mod toast;
+pub mod browser_popup;
+pub mod lyrics_pane;
+pub mod export_prompt;
+pub mod playlists_popup;
+pub mod stats_screen;
+pub mod tag_editor_popup;
+pub mod organize_popup;
+pub mod duplicates_popup;
+mod block_utils;
@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use crate::{organizer::Plan, screen::block_utils::centered_rect};
+
+/// Pattern being typed and the moves it would make, nothing is moved before `Enter`
+pub fn render_organize_popup(
+    f: &mut Frame,
+    area: Rect,
+    pattern: &str,
+    plan: &Result<Plan, String>,
+) {
+    let popup_area = centered_rect(area, 80, 70);
+
+    let mut text = vec![
+        Line::from(vec![
+            Span::styled("Pattern: ", Style::default().fg(Color::Cyan)),
+            Span::raw(format!("{}_", pattern)),
+        ]),
+        Line::from(""),
+    ];
+    match plan {
+        Ok(plan) => {
+            text.push(Line::from(format!(
+                "{} files to move, {} in place, {} skipped",
+                plan.moves.len(),
+                plan.in_place,
+                plan.skipped.len()
+            )));
+            text.push(Line::from(""));
+
+            // Borders, the lines above and the key help below
+            let rows = (popup_area.height as usize).saturating_sub(2 + text.len() + 2);
+            let shown = if plan.moves.len() > rows { rows.saturating_sub(1) } else { rows };
+            for file in plan.moves.iter().take(shown) {
+                let from = relative(&file.from, &file.root);
+                text.push(Line::from(vec![
+                    Span::styled(from, Style::default().fg(Color::DarkGray)),
+                    Span::raw(" → "),
+                    Span::raw(relative(&file.to, &file.root)),
+                ]));
+            }
+            if plan.moves.len() > shown {
+                text.push(Line::from(format!("… and {} more", plan.moves.len() - shown)));
+            }
+        }
+        Err(error) => text.push(Line::from(Span::styled(
+            error.clone(),
+            Style::default().fg(Color::Red),
+        ))),
+    }
+
+    // Key help at the bottom of the popup
+    let inner_height = popup_area.height.saturating_sub(2) as usize;
+    while text.len() + 1 < inner_height {
+        text.push(Line::from(""));
+    }
+    text.push(Line::from(vec![
+        Span::styled("Enter", Style::default().fg(Color::Green)),
+        Span::raw(" - Move files    "),
+        Span::styled("Esc", Style::default().fg(Color::Red)),
+        Span::raw(" - Cancel"),
+    ]));
+
+    let popup = Paragraph::new(text)
+        .block(Block::default().title("Organize Files").borders(Borders::ALL));
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+}
+
+fn relative(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).display().to_string()
+}
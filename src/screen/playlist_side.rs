@@ -1,8 +1,14 @@
-use crate::jukebox_state::JukeboxState;
+use crate::{
+    jukebox_state::JukeboxState,
+    play_stats::{MAX_RATING, TrackStats},
+    search::Search,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
 use ratatui::{
     Frame,
     layout::Rect,
     style::{Color, Style},
+    text::{Line, Span},
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
 };
 
@@ -22,7 +28,11 @@ const PALETTE: [Color;13] = [
         Color::LightRed,
     ];
 
-fn get_song_list(jukebox_state: &JukeboxState) -> Vec<ListItem> {
+fn get_song_list<'a>(
+    jukebox_state: &'a JukeboxState,
+    search: Option<&Search>,
+    show_stats: bool,
+) -> Vec<ListItem<'a>> {
     
     let selected = jukebox_state.current_selection();
     let playing = jukebox_state.currently_playing();
@@ -30,8 +40,12 @@ fn get_song_list(jukebox_state: &JukeboxState) -> Vec<ListItem> {
         .playlist()
         .iter()
         .enumerate()
+        .filter(|(i, _)| {
+            // When filtering, only the matches are listed
+            !search.is_some_and(|search| search.is_filtering() && search.match_at(*i).is_none())
+        })
         .map(|(i, song)| {
-            let song_name = song.title().split('.').next().unwrap_or("").to_string();
+            let song_name = song.display_title();
             let mut style = Style::default().fg(PALETTE[i % PALETTE.len()]);
             
             if selected == song {
@@ -42,19 +56,120 @@ fn get_song_list(jukebox_state: &JukeboxState) -> Vec<ListItem> {
                 style = style.add_modifier(ratatui::style::Modifier::ITALIC);
                 style = style.bg(PALETTE[(i + 1) % PALETTE.len()]);
             }
-            
-            ListItem::new(song_name).style(style)
+
+            let mut line = match search.and_then(|search| search.match_at(i)) {
+                Some(found) => highlight_matches(&song_name, &found.title_indices),
+                None => Line::from(song_name),
+            };
+            if let Some(problems) = jukebox_state.problems(song) {
+                line.spans.insert(0, Span::styled("⚠ ", Style::default().fg(Color::Red)));
+                let labels: Vec<&str> =
+                    problems.iter().map(|problem| problem.kind.label()).collect();
+                line.spans.push(Span::styled(
+                    format!("  {}", labels.join(", ")),
+                    Style::default().fg(Color::Red),
+                ));
+            }
+            if show_stats {
+                line.spans.extend(stats_spans(jukebox_state.track_stats(song)));
+            }
+            ListItem::new(line).style(style)
         })
         .collect()
 }
 
+/// Favourite mark, rating stars, play count and last play of a song
+fn stats_spans(stats: Option<&TrackStats>) -> Vec<Span<'static>> {
+    let stats = stats.cloned().unwrap_or_default();
+    let dim = Style::default().fg(Color::DarkGray);
+    let rating = stats.rating.unwrap_or(0);
+
+    let mut spans = vec![
+        Span::raw("  "),
+        Span::styled(if stats.favourite { "♥ " } else { "  " }, Style::default().fg(Color::Red)),
+        Span::styled("★".repeat(rating as usize), Style::default().fg(Color::Yellow)),
+        Span::styled("☆".repeat((MAX_RATING - rating) as usize), dim),
+        Span::styled(format!(" {}×", stats.plays), dim),
+    ];
+    if let Some(last_played) = stats.last_played {
+        spans.push(Span::styled(format!(" {}", format_ago(last_played)), dim));
+    }
+    spans
+}
+
+/// Time since `timestamp` (seconds since the epoch) as `5m ago`, `3h ago`, `2d ago`
+fn format_ago(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let elapsed = now.saturating_sub(timestamp);
+    match elapsed {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", elapsed / 60),
+        3600..86400 => format!("{}h ago", elapsed / 3600),
+        _ => format!("{}d ago", elapsed / 86400),
+    }
+}
+
+/// Title with the characters matched by the search highlighted
+fn highlight_matches(title: &str, indices: &[usize]) -> Line<'static> {
+    let highlight = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let spans: Vec<Span> = title
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if indices.contains(&i) {
+                Span::styled(c.to_string(), highlight)
+            } else {
+                Span::raw(c.to_string())
+            }
+        })
+        .collect();
+    Line::from(spans)
+}
+
+/// Search prompt shown under the playlist
+fn render_search_prompt(f: &mut Frame, area: Rect, search: &Search) {
+    let cursor = if search.is_editing() { "_" } else { "" };
+    let mode = if search.is_filtering() { "filter" } else { "jump" };
+    let kind = if search.is_structured() { "query, Ctrl+S save, " } else { "" };
+    let mut spans = vec![
+        Span::styled(format!("/{}{}", search.query(), cursor), Style::default().fg(Color::Yellow)),
+        Span::styled(
+            format!("  {} matches, {}{} (Tab)", search.matches().len(), kind, mode),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ];
+    if let Some(error) = search.error() {
+        spans.push(Span::styled(format!("  {}", error), Style::default().fg(Color::Red)));
+    }
+    let prompt = Line::from(spans);
+    f.render_widget(Paragraph::new(prompt), area);
+}
+
 /// Draw playlist list with the available songs
-pub fn render_playlist_side(f: &mut Frame, area: Rect, jukebox_state: &JukeboxState) {
+pub fn render_playlist_side(
+    f: &mut Frame,
+    area: Rect,
+    jukebox_state: &JukeboxState,
+    search: Option<&Search>,
+    show_stats: bool,
+) {
+    // Keep the last line for the search prompt
+    let area = match search {
+        Some(search) if area.height > 1 => {
+            let prompt_area = Rect { y: area.y + area.height - 1, height: 1, ..area };
+            render_search_prompt(f, prompt_area, search);
+            Rect { height: area.height - 1, ..area }
+        }
+        _ => area,
+    };
+
     // Check if playlist is empty
     if jukebox_state.playlist().is_empty() {
-        let no_songs_msg = Paragraph::new("No songs available\n\nPlease add MP3 files to the music directory and restart the jukebox.")
+        let no_songs_msg = Paragraph::new("No songs available\n\nAdd audio files to the music directory, they will show up here automatically.")
             .block(Block::default()
-                .title("Available Songs")
+                .title(jukebox_state.view_name())
                 .borders(Borders::NONE))
             .style(Style::default().fg(Color::Yellow))
             .wrap(ratatui::widgets::Wrap { trim: true });
@@ -63,11 +178,23 @@ pub fn render_playlist_side(f: &mut Frame, area: Rect, jukebox_state: &JukeboxSt
         return;
     }
     
-    let songs: Vec<ListItem> = get_song_list(jukebox_state);
+    let songs: Vec<ListItem> = get_song_list(jukebox_state, search, show_stats);
     
+    let mut title = format!(
+        "{} (by {}",
+        jukebox_state.view_name(),
+        jukebox_state.sort_mode().label()
+    );
+    if !jukebox_state.queue().is_empty() {
+        title.push_str(&format!(", {} queued", jukebox_state.queue().len()));
+    }
+    if let Some((checked, total)) = jukebox_state.check_progress() {
+        title.push_str(&format!(", checking {}/{}", checked, total));
+    }
+    title.push(')');
     let songs_list = List::new(songs).block(
         Block::default()
-            .title("Available Songs")
+            .title(title)
             .borders(Borders::NONE),
     )
     .highlight_style(Style::default().add_modifier(ratatui::style::Modifier::BOLD));
@@ -75,7 +202,15 @@ pub fn render_playlist_side(f: &mut Frame, area: Rect, jukebox_state: &JukeboxSt
     // Make stateful list to handle selection and scrolling
     let mut list_state = ListState::default();
     let selected_index = jukebox_state.current_selection().position();
-    list_state.select(Some(selected_index));
+    let selected_row = match search {
+        // Only the matches are listed: find the selected song among them
+        Some(search) if search.is_filtering() => search
+            .matches()
+            .iter()
+            .position(|found| found.position == selected_index),
+        _ => Some(selected_index),
+    };
+    list_state.select(selected_row);
     
     f.render_stateful_widget(songs_list, area, &mut list_state);
 }
@@ -0,0 +1,79 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::{
+    jukebox_state::JukeboxState,
+    saved_playlists::View,
+    screen::block_utils::centered_rect,
+};
+
+/// Switcher between the library and the saved playlists.
+///
+/// Row 0 is the library, the saved playlists follow. `name` is the name being
+/// typed for a new playlist, or for the selected one when `renaming`.
+pub fn render_playlists_popup(
+    f: &mut Frame,
+    area: Rect,
+    jukebox_state: &JukeboxState,
+    selected: usize,
+    name: Option<&str>,
+    renaming: bool,
+) {
+    let popup_area = centered_rect(area, 50, 60);
+    let block = Block::default()
+        .title("Playlists (Enter - Show, n - New, r - Rename, d - Delete, a - Add selected song, Esc - Close)")
+        .borders(Borders::ALL);
+
+    f.render_widget(Clear, popup_area);
+
+    let playlists = jukebox_state.saved_playlists().list();
+    let mut rows = vec![(View::Library, "Library".to_string(), String::new())];
+    rows.extend(playlists.iter().enumerate().map(|(i, playlist)| {
+        let count = match &playlist.query {
+            Some(query) => format!("  smart: {}", query),
+            None => format!("  {} songs", playlist.songs.len()),
+        };
+        (View::Saved(i), playlist.name.clone(), count)
+    }));
+
+    let dim = Style::default().fg(Color::DarkGray);
+    let items: Vec<ListItem> = rows
+        .into_iter()
+        .map(|(view, title, count)| {
+            let marker = if view == jukebox_state.view() { "▶ " } else { "  " };
+            ListItem::new(Line::from(vec![
+                Span::styled(marker, Style::default().fg(Color::Green)),
+                Span::raw(title),
+                Span::styled(count, dim),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default();
+    list_state.select(Some(selected));
+    f.render_stateful_widget(list, popup_area, &mut list_state);
+
+    // The name prompt goes on the last line inside the border
+    if let Some(name) = name {
+        let label = if renaming { " Rename to: " } else { " New playlist: " };
+        let prompt_area = Rect {
+            y: popup_area.y + popup_area.height.saturating_sub(2),
+            height: 1,
+            x: popup_area.x + 1,
+            width: popup_area.width.saturating_sub(2),
+        };
+        let prompt = Line::from(vec![
+            Span::raw(label),
+            Span::styled(format!("{}_", name), Style::default().fg(Color::Yellow)),
+        ]);
+        f.render_widget(Paragraph::new(prompt), prompt_area);
+    }
+}
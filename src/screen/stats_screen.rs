@@ -0,0 +1,120 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Bar, BarChart, BarGroup, Block, Borders, Clear, List, ListItem, Paragraph},
+};
+
+use crate::{
+    listen_log::{Ranked, Report, format_hours},
+    screen::block_utils::centered_rect,
+};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Top lists and listening charts from the listen log
+pub fn render_stats_screen(f: &mut Frame, area: Rect, report: &Report) {
+    let popup_area = centered_rect(area, 90, 90);
+    let block = Block::default()
+        .title(format!("Listening Stats - {} (Tab - Period, Esc - Close)", report.period))
+        .borders(Borders::ALL);
+    let inner = block.inner(popup_area);
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(block, popup_area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ])
+        .split(inner);
+
+    let summary = Line::from(vec![
+        Span::styled(format!("{} listens", report.listens), Style::default().fg(Color::Cyan)),
+        Span::raw("    "),
+        Span::styled(
+            format!("{} listened", format_hours(report.listened_secs)),
+            Style::default().fg(Color::Green),
+        ),
+    ]);
+    f.render_widget(Paragraph::new(summary), rows[0]);
+
+    let tops = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 3); 3])
+        .split(rows[1]);
+    render_top(f, tops[0], "Top Tracks", &report.top_tracks);
+    render_top(f, tops[1], "Top Artists", &report.top_artists);
+    render_top(f, tops[2], "Top Albums", &report.top_albums);
+
+    let charts = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+        .split(rows[2]);
+    let hours: Vec<(String, u64)> = report
+        .by_hour
+        .iter()
+        .enumerate()
+        .map(|(hour, &secs)| (format!("{:02}", hour), secs))
+        .collect();
+    let weekdays: Vec<(String, u64)> = report
+        .by_weekday
+        .iter()
+        .zip(WEEKDAYS)
+        .map(|(&secs, day)| (day.to_string(), secs))
+        .collect();
+    render_chart(f, charts[0], "By Hour (minutes)", &hours, Color::Yellow);
+    render_chart(f, charts[1], "By Weekday (minutes)", &weekdays, Color::Magenta);
+}
+
+fn render_top(f: &mut Frame, area: Rect, title: &str, ranked: &[Ranked]) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let items: Vec<ListItem> = ranked
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:>2}. ", i + 1), dim),
+                Span::raw(entry.name.clone()),
+                Span::styled(format!("  {}×", entry.listens), dim),
+            ]))
+        })
+        .collect();
+
+    let block = Block::default().title(title.to_string()).borders(Borders::ALL);
+    if items.is_empty() {
+        f.render_widget(Paragraph::new("Nothing listened yet").style(dim).block(block), area);
+    } else {
+        f.render_widget(List::new(items).block(block), area);
+    }
+}
+
+/// Bar chart of listening time in minutes, the bars shrinking to fit the area
+fn render_chart(f: &mut Frame, area: Rect, title: &str, values: &[(String, u64)], color: Color) {
+    let bars: Vec<Bar> = values
+        .iter()
+        .map(|(label, secs)| {
+            Bar::default()
+                .label(Line::from(label.clone()))
+                .value(secs / 60)
+                .text_value(String::new())
+        })
+        .collect();
+
+    // Bars with one column of gap, as wide as the area allows
+    let inner_width = area.width.saturating_sub(2);
+    let count = values.len().max(1) as u16;
+    let bar_width = (inner_width.saturating_sub(count) / count).max(1);
+
+    let chart = BarChart::default()
+        .block(Block::default().title(title.to_string()).borders(Borders::ALL))
+        .data(BarGroup::default().bars(&bars))
+        .bar_width(bar_width)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(color));
+    f.render_widget(chart, area);
+}
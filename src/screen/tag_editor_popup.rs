@@ -0,0 +1,56 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+use crate::{
+    screen::block_utils::centered_rect,
+    tag_editor::{FIELDS, TagForm},
+};
+
+/// Form editing the tags of a song, the selected field showing a cursor
+pub fn render_tag_editor_popup(f: &mut Frame, area: Rect, form: &TagForm) {
+    let file_name = form
+        .path()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut text = vec![
+        Line::from(Span::styled(file_name, Style::default().fg(Color::DarkGray))),
+        Line::from(""),
+    ];
+    for (i, (label, value)) in FIELDS.iter().zip(form.values()).enumerate() {
+        let selected = i == form.selected();
+        let label_style = if selected {
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::Cyan)
+        };
+        let cursor = if selected { "_" } else { "" };
+        text.push(Line::from(vec![
+            Span::styled(format!("{:>7}: ", label), label_style),
+            Span::raw(format!("{}{}", value, cursor)),
+        ]));
+    }
+    text.push(Line::from(""));
+    text.push(Line::from(vec![
+        Span::styled("↑/↓/Tab", Style::default().fg(Color::Cyan)),
+        Span::raw(" - Field    "),
+        Span::styled("Enter", Style::default().fg(Color::Green)),
+        Span::raw(" - Save    "),
+        Span::styled("Esc", Style::default().fg(Color::Red)),
+        Span::raw(" - Cancel"),
+    ]));
+
+    let popup_area = centered_rect(area, 60, 40);
+    let form = Paragraph::new(text)
+        .block(Block::default().title("Edit Tags").borders(Borders::ALL))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(form, popup_area);
+}
@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+/// Short notification shown in a corner of the screen for a few seconds
+pub struct Toast {
+    message: String,
+    shown_at: Instant,
+}
+
+impl Toast {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            shown_at: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.shown_at.elapsed() >= TOAST_DURATION
+    }
+}
+
+/// Draw the toast in the bottom right corner, sized to its message
+pub fn render_toast(f: &mut Frame, area: Rect, toast: &Toast) {
+    let width = (toast.message.chars().count() as u16 + 4).min(area.width);
+    let height = 3.min(area.height);
+    let toast_area = Rect {
+        x: area.x + area.width - width,
+        y: area.y + area.height - height,
+        width,
+        height,
+    };
+
+    let paragraph = Paragraph::new(toast.message.as_str())
+        .style(Style::default().fg(Color::LightGreen))
+        .block(Block::default().borders(Borders::ALL));
+
+    f.render_widget(Clear, toast_area);
+    f.render_widget(paragraph, toast_area);
+}
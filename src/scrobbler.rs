@@ -0,0 +1,450 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
+};
+use ureq::Agent;
+
+use crate::{jukebox_state::SongItem, storage};
+
+const QUEUE_FILE: &str = "scrobble_queue.json";
+pub const DEFAULT_URL: &str = "https://api.listenbrainz.org";
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often queued listens are retried while the endpoint is unreachable
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Listens per submission, the ListenBrainz limit for imports
+const MAX_BATCH: usize = 1000;
+/// A listen counts after half of the track, or after this long for long tracks
+const MAX_LISTEN_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+
+/// Where to submit listens
+#[derive(Debug, Clone)]
+pub struct ScrobbleConfig {
+    /// Root of a ListenBrainz compatible API, `/1/submit-listens` is appended
+    pub url: String,
+    pub token: String,
+}
+
+/// Track description sent as `track_metadata`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub artist_name: String,
+    pub track_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_name: Option<String>,
+    #[serde(default)]
+    pub additional_info: AdditionalInfo,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AdditionalInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracknumber: Option<u32>,
+    #[serde(default)]
+    pub media_player: String,
+    #[serde(default)]
+    pub submission_client: String,
+    #[serde(default)]
+    pub submission_client_version: String,
+}
+
+impl Track {
+    /// Songs need an artist and a title to be submitted, streams are left out
+    pub fn from_song(song: &SongItem) -> Option<Self> {
+        if song.is_stream() {
+            return None;
+        }
+        let tags = song.tags();
+        let artist = tags.artist.clone().or_else(|| song.performer().map(str::to_string))?;
+        let title = tags.title.clone()?;
+        let client = env!("CARGO_PKG_NAME").to_string();
+
+        Some(Self {
+            artist_name: artist,
+            track_name: title,
+            release_name: tags.album.clone(),
+            additional_info: AdditionalInfo {
+                duration_ms: song.duration().map(|d| d.as_millis() as u64),
+                tracknumber: tags.track_number,
+                media_player: client.clone(),
+                submission_client: client,
+                submission_client_version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+        })
+    }
+}
+
+/// Listened time after which a song counts as listened: half of it, at most 4 minutes
+pub fn listen_threshold(duration: Option<Duration>) -> Duration {
+    duration.map_or(MAX_LISTEN_THRESHOLD, |duration| {
+        (duration / 2).min(MAX_LISTEN_THRESHOLD)
+    })
+}
+
+/// Listen waiting to be submitted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedListen {
+    listened_at: u64,
+    track_metadata: Track,
+}
+
+enum Submission {
+    NowPlaying(Track),
+    /// New listens were queued
+    Flush,
+}
+
+/// Listens waiting for submission, saved to disk whenever they change
+struct Queue {
+    file: Option<PathBuf>,
+    listens: Vec<QueuedListen>,
+}
+
+impl Queue {
+    fn save(&self) {
+        if let Some(file) = &self.file {
+            let _ = storage::save_json(file, &self.listens);
+        }
+    }
+}
+
+/// Submits listens in the background, keeping them on disk until the endpoint accepted them
+pub struct Scrobbler {
+    sender: Sender<Submission>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl Scrobbler {
+    pub fn start(config: ScrobbleConfig) -> Self {
+        let file = storage::data_file(QUEUE_FILE);
+        let listens = file.as_deref().map(storage::load_json).unwrap_or_default();
+        let queue = Arc::new(Mutex::new(Queue { file, listens }));
+
+        let (sender, receiver) = mpsc::channel();
+        let shared = queue.clone();
+        thread::spawn(move || run(config, receiver, shared));
+        Self { sender, queue }
+    }
+
+    pub fn now_playing(&self, track: Track) {
+        let _ = self.sender.send(Submission::NowPlaying(track));
+    }
+
+    /// Submit a listen started at `listened_at` (seconds since the epoch).
+    ///
+    /// The listen is saved right away, so it survives quitting before it was sent.
+    pub fn listen(&self, listened_at: u64, track: Track) {
+        if let Ok(mut queue) = self.queue.lock() {
+            queue.listens.push(QueuedListen {
+                listened_at,
+                track_metadata: track,
+            });
+            queue.save();
+        }
+        let _ = self.sender.send(Submission::Flush);
+    }
+}
+
+/// Why a submission failed
+enum SubmitError {
+    /// The endpoint refused the listens, sending them again would not help
+    Rejected,
+    /// Network or server trouble, worth retrying later
+    Unavailable,
+}
+
+fn agent() -> Agent {
+    Agent::config_builder()
+        .timeout_connect(Some(NETWORK_TIMEOUT))
+        .timeout_recv_response(Some(NETWORK_TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// Worker loop: now playing notifications are sent once, queued listens until accepted
+fn run(config: ScrobbleConfig, receiver: Receiver<Submission>, queue: Arc<Mutex<Queue>>) {
+    let agent = agent();
+
+    // Listens left over from an earlier session go first
+    flush(&agent, &config, &queue);
+
+    loop {
+        match receiver.recv_timeout(RETRY_INTERVAL) {
+            Ok(Submission::NowPlaying(track)) => {
+                // Only worth showing while the song plays, never queued
+                let payload = json!([{ "track_metadata": track }]);
+                let _ = submit(&agent, &config, "playing_now", payload);
+            }
+            Ok(Submission::Flush) | Err(RecvTimeoutError::Timeout) => {
+                flush(&agent, &config, &queue)
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Submit the queued listens in batches, stopping at the first failure.
+///
+/// The queue is not locked while submitting, new listens are only ever appended.
+fn flush(agent: &Agent, config: &ScrobbleConfig, queue: &Mutex<Queue>) {
+    loop {
+        let batch: Vec<QueuedListen> = match queue.lock() {
+            Ok(queue) => queue.listens.iter().take(MAX_BATCH).cloned().collect(),
+            Err(_) => return,
+        };
+        if batch.is_empty() {
+            return;
+        }
+
+        let listen_type = if batch.len() == 1 { "single" } else { "import" };
+        let payload = serde_json::to_value(&batch).unwrap_or_default();
+        match submit(agent, config, listen_type, payload) {
+            Ok(()) => remove_first(queue, batch.len()),
+            Err(SubmitError::Unavailable) => return,
+            // Refused for good, it must not hold up the next ones
+            Err(SubmitError::Rejected) if batch.len() == 1 => remove_first(queue, 1),
+            // A single bad listen fails the whole batch: resubmit them one at a time,
+            // so only the refused ones are dropped
+            Err(SubmitError::Rejected) => {
+                for listen in &batch {
+                    let payload = json!([listen]);
+                    if let Err(SubmitError::Unavailable) =
+                        submit(agent, config, "single", payload)
+                    {
+                        return;
+                    }
+                    remove_first(queue, 1);
+                }
+            }
+        }
+    }
+}
+
+/// Remove the `count` oldest listens, once submitted or refused
+fn remove_first(queue: &Mutex<Queue>, count: usize) {
+    if let Ok(mut queue) = queue.lock() {
+        queue.listens.drain(..count);
+        queue.save();
+    }
+}
+
+fn submit(
+    agent: &Agent,
+    config: &ScrobbleConfig,
+    listen_type: &str,
+    payload: serde_json::Value,
+) -> Result<(), SubmitError> {
+    let url = format!("{}/1/submit-listens", config.url.trim_end_matches('/'));
+    let body = json!({ "listen_type": listen_type, "payload": payload }).to_string();
+
+    let response = agent
+        .post(&url)
+        .header("Authorization", format!("Token {}", config.token))
+        .header("Content-Type", "application/json")
+        .send(body)
+        .map_err(|_| SubmitError::Unavailable)?;
+
+    match response.status().as_u16() {
+        200..=299 => Ok(()),
+        // Bad token, rate limit or server trouble: the listens stay queued
+        401 | 403 | 429 | 500.. => Err(SubmitError::Unavailable),
+        _ => Err(SubmitError::Rejected),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ScratchDir;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        path::Path,
+    };
+
+    type Requests = Arc<Mutex<Vec<serde_json::Value>>>;
+
+    /// Local endpoint answering each request with the status `respond` picks for its
+    /// body, and recording the bodies of the requests
+    fn stub_server(
+        respond: impl Fn(&serde_json::Value) -> u16 + Send + 'static,
+    ) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let body = serde_json::from_slice(&body).unwrap();
+
+                let status = respond(&body);
+                received.lock().unwrap().push(body);
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                (&stream).write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    fn listen(title: &str) -> QueuedListen {
+        QueuedListen {
+            listened_at: 1_700_000_000,
+            track_metadata: Track {
+                artist_name: "Artist".to_string(),
+                track_name: title.to_string(),
+                release_name: None,
+                additional_info: AdditionalInfo::default(),
+            },
+        }
+    }
+
+    /// Queue of the listens titled `titles`, saved in `dir`
+    fn queue(dir: &Path, titles: &[&str]) -> Mutex<Queue> {
+        let queue = Queue {
+            file: Some(dir.join("queue.json")),
+            listens: titles.iter().map(|title| listen(title)).collect(),
+        };
+        queue.save();
+        Mutex::new(queue)
+    }
+
+    /// Titles of the listens left in the queue file
+    fn saved_titles(queue: &Mutex<Queue>) -> Vec<String> {
+        let file = queue.lock().unwrap().file.clone().unwrap();
+        let listens: Vec<QueuedListen> = storage::load_json(&file);
+        listens.into_iter().map(|listen| listen.track_metadata.track_name).collect()
+    }
+
+    fn titles(payload: &serde_json::Value) -> Vec<&str> {
+        payload["payload"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|listen| listen["track_metadata"]["track_name"].as_str().unwrap())
+            .collect()
+    }
+
+    fn config(url: String) -> ScrobbleConfig {
+        ScrobbleConfig {
+            url: format!("{}/", url),
+            token: "secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn accepted_listens_leave_the_queue() {
+        let (url, requests) = stub_server(|_| 200);
+        let dir = ScratchDir::new("scrobble-accept");
+        let queue = queue(&dir, &["One", "Two"]);
+
+        flush(&agent(), &config(url), &queue);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["listen_type"], "import");
+        assert_eq!(titles(&requests[0]), ["One", "Two"]);
+        assert!(queue.lock().unwrap().listens.is_empty());
+        assert!(saved_titles(&queue).is_empty());
+    }
+
+    #[test]
+    fn server_errors_keep_the_listens_for_a_retry() {
+        let down = Arc::new(Mutex::new(true));
+        let server_down = down.clone();
+        let (url, requests) =
+            stub_server(move |_| if *server_down.lock().unwrap() { 503 } else { 200 });
+        let dir = ScratchDir::new("scrobble-retry");
+        let queue = queue(&dir, &["One"]);
+
+        flush(&agent(), &config(url.clone()), &queue);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(saved_titles(&queue), ["One"]);
+
+        // A later session starts from the saved queue
+        let file = queue.lock().unwrap().file.clone();
+        let listens = storage::load_json(file.as_deref().unwrap());
+        let reloaded = Mutex::new(Queue { file, listens });
+
+        *down.lock().unwrap() = false;
+        flush(&agent(), &config(url), &reloaded);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["listen_type"], "single");
+        assert_eq!(titles(&requests[1]), ["One"]);
+        assert!(saved_titles(&reloaded).is_empty());
+    }
+
+    #[test]
+    fn unreachable_endpoint_keeps_the_listens() {
+        // Nothing listens on the port once the listener is dropped
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let dir = ScratchDir::new("scrobble-unreachable");
+        let queue = queue(&dir, &["One", "Two"]);
+
+        flush(&agent(), &config(url), &queue);
+        assert_eq!(saved_titles(&queue), ["One", "Two"]);
+    }
+
+    #[test]
+    fn refused_batch_only_drops_the_refused_listens() {
+        let (url, requests) = stub_server(|body| {
+            if titles(body).contains(&"Bad") { 400 } else { 200 }
+        });
+        let dir = ScratchDir::new("scrobble-refused");
+        let queue = queue(&dir, &["One", "Bad", "Two"]);
+
+        flush(&agent(), &config(url), &queue);
+
+        let requests = requests.lock().unwrap();
+        let sent: Vec<Vec<&str>> = requests.iter().map(titles).collect();
+        assert_eq!(sent, [vec!["One", "Bad", "Two"], vec!["One"], vec!["Bad"], vec!["Two"]]);
+        assert!(saved_titles(&queue).is_empty());
+    }
+
+    #[test]
+    fn server_error_during_resubmission_keeps_the_rest() {
+        let (url, requests) = stub_server(|body| match titles(body)[..] {
+            [_, _, ..] => 400,
+            ["Bad"] => 400,
+            _ => 500,
+        });
+        let dir = ScratchDir::new("scrobble-partial");
+        let queue = queue(&dir, &["Bad", "One", "Two"]);
+
+        flush(&agent(), &config(url), &queue);
+
+        assert_eq!(requests.lock().unwrap().len(), 3);
+        assert_eq!(saved_titles(&queue), ["One", "Two"]);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::{jukebox_state::SongItem, query::Query};
+
+/// Bonus for a query character right after the previous matched one
+const CONSECUTIVE_BONUS: i32 = 8;
+/// Bonus for a query character at the start of a word
+const WORD_START_BONUS: i32 = 6;
+/// Bonus for the query appearing as is in the text
+const SUBSTRING_BONUS: i32 = 20;
+
+/// Song matching the query
+#[derive(Debug, Clone)]
+pub struct SongMatch {
+    pub position: usize,
+    pub score: i32,
+    /// Matched character indices in the displayed title, empty when it matched another field
+    pub title_indices: Vec<usize>,
+}
+
+/// Incremental fuzzy search over the playlist titles, artists and albums.
+///
+/// Queries using fields or operators (`artist:"Daft Punk" year>=2000`) are
+/// evaluated as a [`Query`] instead.
+#[derive(Debug, Default)]
+pub struct Search {
+    query: String,
+    editing: bool,
+    filter: bool,
+    /// Set when the query is evaluated as a structured query
+    structured: bool,
+    /// Why the query could not be parsed, the search falls back to fuzzy matching
+    error: Option<String>,
+    /// Ordered by playlist position
+    matches: Vec<SongMatch>,
+}
+
+impl Search {
+    /// New search, with the prompt open for typing
+    pub fn new() -> Self {
+        Self {
+            editing: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Close the prompt, the matches stay highlighted for next/previous match
+    pub fn finish_editing(&mut self) {
+        self.editing = false;
+    }
+
+    pub fn is_filtering(&self) -> bool {
+        self.filter
+    }
+
+    /// Switch between showing only the matches and jumping between them in the full playlist
+    pub fn toggle_filter(&mut self) {
+        self.filter = !self.filter;
+    }
+
+    pub fn is_structured(&self) -> bool {
+        self.structured
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Match the query against the playlist again
+    pub fn update(&mut self, playlist: &[SongItem]) {
+        self.structured = false;
+        self.error = None;
+        if self.query.is_empty() {
+            self.matches = Vec::new();
+            return;
+        }
+
+        match Query::parse(&self.query) {
+            Ok(query) if query.is_structured() => {
+                self.structured = true;
+                self.matches = playlist
+                    .iter()
+                    .filter(|song| query.matches(song))
+                    .map(|song| SongMatch {
+                        position: song.position(),
+                        score: 0,
+                        title_indices: Vec::new(),
+                    })
+                    .collect();
+                return;
+            }
+            Ok(_) => {}
+            Err(error) => self.error = Some(error.to_string()),
+        }
+        self.matches = playlist
+            .iter()
+            .filter_map(|song| match_song(&self.query, song))
+            .collect();
+    }
+
+    pub fn matches(&self) -> &[SongMatch] {
+        &self.matches
+    }
+
+    pub fn match_at(&self, position: usize) -> Option<&SongMatch> {
+        self.matches
+            .binary_search_by_key(&position, |m| m.position)
+            .ok()
+            .map(|i| &self.matches[i])
+    }
+
+    /// Position of the best scoring match, the first one on ties
+    pub fn best_match(&self) -> Option<usize> {
+        self.matches
+            .iter()
+            .max_by_key(|m| (m.score, std::cmp::Reverse(m.position)))
+            .map(|m| m.position)
+    }
+
+    /// Position of the next (or previous) match after `position`, wrapping around
+    pub fn step(&self, position: usize, forward: bool) -> Option<usize> {
+        let positions: Vec<usize> = self.matches.iter().map(|m| m.position).collect();
+        if forward {
+            positions
+                .iter()
+                .find(|&&p| p > position)
+                .or(positions.first())
+                .copied()
+        } else {
+            positions
+                .iter()
+                .rev()
+                .find(|&&p| p < position)
+                .or(positions.last())
+                .copied()
+        }
+    }
+}
+
+fn match_song(query: &str, song: &SongItem) -> Option<SongMatch> {
+    let tags = song.tags();
+    let title_match = fuzzy_match(query, &song.display_title());
+    let other_score = [tags.artist.as_deref().or(song.performer()), tags.album.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter_map(|field| fuzzy_match(query, field).map(|(score, _)| score))
+        .max();
+
+    let (score, title_indices) = match (title_match, other_score) {
+        // The title is not what matched, so nothing in it is highlighted
+        (Some((title_score, _)), Some(score)) if score > title_score => (score, Vec::new()),
+        (Some((score, indices)), _) => (score, indices),
+        (None, Some(score)) => (score, Vec::new()),
+        (None, None) => return None,
+    };
+
+    Some(SongMatch {
+        position: song.position(),
+        score,
+        title_indices,
+    })
+}
+
+/// Case insensitive subsequence match, returning a score and the matched character indices
+pub fn fuzzy_match(query: &str, text: &str) -> Option<(i32, Vec<usize>)> {
+    // One lowercase character per character, so indices line up with the text
+    let lowercase = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let query: Vec<char> = query.chars().map(lowercase).collect();
+    let text: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text.iter().copied().map(lowercase).collect();
+    if query.is_empty() {
+        return None;
+    }
+
+    // Prefer the query as a whole, then fall back to scattered characters
+    if let Some(start) = lower.windows(query.len()).position(|window| window == query) {
+        let indices: Vec<usize> = (start..start + query.len()).collect();
+        return Some((score(&text, &indices) + SUBSTRING_BONUS, indices));
+    }
+
+    let mut indices = Vec::with_capacity(query.len());
+    let mut next = 0;
+    for c in &query {
+        let found = lower[next..].iter().position(|t| t == c)? + next;
+        indices.push(found);
+        next = found + 1;
+    }
+    Some((score(&text, &indices), indices))
+}
+
+fn score(text: &[char], indices: &[usize]) -> i32 {
+    let mut score = 0;
+    for (i, &index) in indices.iter().enumerate() {
+        if i > 0 && indices[i - 1] + 1 == index {
+            score += CONSECUTIVE_BONUS;
+        }
+        if index == 0 || !text[index - 1].is_alphanumeric() {
+            score += WORD_START_BONUS;
+        }
+    }
+    // Matches spread over a long text rank lower
+    let span = indices.last().unwrap_or(&0) - indices.first().unwrap_or(&0);
+    score - span as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{TrackInfo, TrackTags};
+    use std::path::PathBuf;
+
+    fn song(file_name: &str, artist: &str, position: usize) -> SongItem {
+        let info = TrackInfo {
+            tags: TrackTags {
+                artist: Some(artist.to_string()),
+                ..TrackTags::default()
+            },
+            ..TrackInfo::default()
+        };
+        let path = PathBuf::from("/music").join(file_name);
+        SongItem::new(path, file_name.to_string(), position, info)
+    }
+
+    #[test]
+    fn fuzzy_match_prefers_whole_words_and_runs() {
+        assert_eq!(fuzzy_match("xyz", "Around the World"), None);
+        assert_eq!(fuzzy_match("", "Around the World"), None);
+
+        // The query as is: a run from the start of a word, plus the substring bonus
+        let (score, indices) = fuzzy_match("WORLD", "Around the World").unwrap();
+        assert_eq!(indices, [11, 12, 13, 14, 15]);
+        assert_eq!(score, 4 * CONSECUTIVE_BONUS + WORD_START_BONUS - 4 + SUBSTRING_BONUS);
+        let (scattered, _) = fuzzy_match("atw", "Around the World").unwrap();
+        assert!(score > scattered);
+
+        // Same span, but two of the characters follow each other
+        let (run, indices) = fuzzy_match("hlo", "hxxlo").unwrap();
+        assert_eq!(indices, [0, 3, 4]);
+        let (spread, _) = fuzzy_match("hlo", "hxlxo").unwrap();
+        assert_eq!(run, spread + CONSECUTIVE_BONUS);
+
+        // Characters starting words count more than the same characters inside them
+        let (starts, _) = fuzzy_match("ab", "a b").unwrap();
+        let (inside, _) = fuzzy_match("ab", "xaxb").unwrap();
+        assert!(starts > inside);
+
+        // Indices count characters, not bytes
+        assert_eq!(fuzzy_match("été", "Un Été").unwrap().1, [3, 4, 5]);
+    }
+
+    #[test]
+    fn matches_on_another_field_highlight_nothing_in_the_title() {
+        let by_title = match_song("daft", &song("Daft Dreams.mp3", "Someone", 0)).unwrap();
+        assert_eq!(by_title.title_indices, [0, 1, 2, 3]);
+
+        // The title matches too, scattered, but the artist matches better
+        let by_artist = match_song("daft", &song("Da Funk Tracks.mp3", "Daft Punk", 1)).unwrap();
+        assert!(by_artist.title_indices.is_empty());
+        assert_eq!(by_artist.score, fuzzy_match("daft", "Daft Punk").unwrap().0);
+
+        assert!(match_song("daft", &song("Other.mp3", "Someone", 2)).is_none());
+    }
+
+    #[test]
+    fn best_match_is_the_first_of_the_highest_scores() {
+        let playlist = [
+            song("Homework.mp3", "Someone", 0),
+            song("Da Funk.mp3", "Daft Punk", 1),
+            song("Daft.mp3", "Someone", 2),
+        ];
+        let mut search = Search::new();
+        "daft".chars().for_each(|c| search.push(c));
+        search.update(&playlist);
+
+        let positions: Vec<usize> = search.matches().iter().map(|m| m.position).collect();
+        assert_eq!(positions, [1, 2]);
+        assert_eq!(search.best_match(), Some(1));
+        assert_eq!(search.step(2, true), Some(1));
+    }
+}
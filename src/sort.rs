@@ -0,0 +1,235 @@
+use std::{cmp::Ordering, collections::HashMap, path::PathBuf};
+
+use crate::{jukebox_state::SongItem, library_index::LibraryIndex};
+
+/// Order of the songs in the playlist
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    /// Order of the command line sources, directories sorted by file name
+    #[default]
+    Source,
+    Filename,
+    Title,
+    Artist,
+    /// Album, then track number
+    Album,
+    Duration,
+    /// Most recently added first
+    DateAdded,
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Source => SortMode::Filename,
+            SortMode::Filename => SortMode::Title,
+            SortMode::Title => SortMode::Artist,
+            SortMode::Artist => SortMode::Album,
+            SortMode::Album => SortMode::Duration,
+            SortMode::Duration => SortMode::DateAdded,
+            SortMode::DateAdded => SortMode::Source,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Source => "source order",
+            SortMode::Filename => "file name",
+            SortMode::Title => "title",
+            SortMode::Artist => "artist",
+            SortMode::Album => "album",
+            SortMode::Duration => "duration",
+            SortMode::DateAdded => "date added",
+        }
+    }
+}
+
+/// Sort songs given in source order, leaving their positions to the caller
+pub fn sort_songs(songs: &mut [SongItem], mode: SortMode, index: &LibraryIndex) {
+    match mode {
+        SortMode::Source => {}
+        SortMode::Filename => songs.sort_by(compare_file_names),
+        SortMode::Title => songs.sort_by(|a, b| {
+            natural_cmp(&title(a), &title(b)).then_with(|| compare_file_names(a, b))
+        }),
+        SortMode::Artist => songs.sort_by(|a, b| {
+            compare_missing_last(artist(a), artist(b), natural_cmp)
+                .then_with(|| compare_albums(a, b))
+        }),
+        SortMode::Album => songs.sort_by(compare_albums),
+        SortMode::Duration => songs.sort_by(|a, b| {
+            compare_missing_last(a.duration(), b.duration(), |a, b| a.cmp(&b))
+                .then_with(|| compare_file_names(a, b))
+        }),
+        SortMode::DateAdded => {
+            // Looked up once per file, finding a file in the index resolves its path
+            let dates: HashMap<PathBuf, Option<u64>> = songs
+                .iter()
+                .map(|song| (song.as_path().to_path_buf(), index.added(song.as_path())))
+                .collect();
+            let added = |song: &SongItem| dates.get(song.as_path()).copied().flatten();
+            songs.sort_by(|a, b| {
+                compare_missing_last(added(a), added(b), |a, b| b.cmp(&a))
+                    .then_with(|| compare_file_names(a, b))
+            })
+        }
+    }
+}
+
+fn title(song: &SongItem) -> String {
+    song.tags()
+        .title
+        .clone()
+        .unwrap_or_else(|| song.display_title())
+}
+
+fn artist(song: &SongItem) -> Option<&str> {
+    song.tags().artist.as_deref().or(song.performer())
+}
+
+fn compare_file_names(a: &SongItem, b: &SongItem) -> Ordering {
+    // Streams have no file, their URL stands in for its name
+    let name = |song: &SongItem| match song.url() {
+        Some(url) => url.to_string(),
+        None => song
+            .as_path()
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    natural_cmp(&name(a), &name(b))
+        .then_with(|| a.bounds().0.cmp(&b.bounds().0))
+}
+
+fn compare_albums(a: &SongItem, b: &SongItem) -> Ordering {
+    let (a_tags, b_tags) = (a.tags(), b.tags());
+    compare_missing_last(a_tags.album.as_deref(), b_tags.album.as_deref(), natural_cmp)
+        .then_with(|| {
+            compare_missing_last(a_tags.track_number, b_tags.track_number, |a, b| a.cmp(&b))
+        })
+        .then_with(|| compare_file_names(a, b))
+}
+
+/// Compare optional values, songs missing the value going after the others
+fn compare_missing_last<T>(
+    a: Option<T>,
+    b: Option<T>,
+    compare: impl Fn(T, T) -> Ordering,
+) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Case insensitive comparison treating digit runs as numbers, so "Track 2" < "Track 10"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chunks = Chunks(a);
+    let mut b_chunks = Chunks(b);
+
+    loop {
+        let ordering = match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => compare_chunks(a, b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn compare_chunks(a: &str, b: &str) -> Ordering {
+    let is_number = |chunk: &str| chunk.starts_with(|c: char| c.is_ascii_digit());
+
+    if is_number(a) && is_number(b) {
+        // Compare as numbers of any length: fewer significant digits is smaller
+        let a_digits = a.trim_start_matches('0');
+        let b_digits = b.trim_start_matches('0');
+        a_digits
+            .len()
+            .cmp(&b_digits.len())
+            .then_with(|| a_digits.cmp(b_digits))
+    } else {
+        a.chars()
+            .flat_map(char::to_lowercase)
+            .cmp(b.chars().flat_map(char::to_lowercase))
+    }
+}
+
+/// Splits a string into alternating runs of digits and non-digits
+struct Chunks<'a>(&'a str);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let first = self.0.chars().next()?;
+        let digits = first.is_ascii_digit();
+        let end = self
+            .0
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(self.0.len());
+
+        let (chunk, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn numbers_are_compared_by_value() {
+        assert_eq!(
+            sorted(&["Track 10", "Track 2", "Track 1", "Track 100"]),
+            ["Track 1", "Track 2", "Track 10", "Track 100"]
+        );
+        assert_eq!(sorted(&["10.flac", "9.flac", "09b.flac"]), ["9.flac", "09b.flac", "10.flac"]);
+        // Longer than any integer type
+        let huge = "disc 123456789012345678901234567890";
+        assert_eq!(natural_cmp(huge, "disc 99999999999999999999999999999"), Ordering::Greater);
+    }
+
+    #[test]
+    fn leading_zeros_do_not_change_the_value() {
+        assert_eq!(sorted(&["010", "9", "0001"]), ["0001", "9", "010"]);
+        assert_eq!(natural_cmp("02 - Intro", "2 - Outro"), Ordering::Less);
+        assert_eq!(natural_cmp("Track 0", "Track 00"), Ordering::Less);
+        // Same value, the order stays total
+        assert_ne!(natural_cmp("02", "2"), Ordering::Equal);
+        assert_eq!(natural_cmp("02", "2"), natural_cmp("02", "2"));
+        assert_eq!(natural_cmp("02", "2"), natural_cmp("2", "02").reverse());
+    }
+
+    #[test]
+    fn case_is_ignored_until_names_tie() {
+        assert_eq!(sorted(&["beta", "Alpha", "gamma", "Beta"]), ["Alpha", "Beta", "beta", "gamma"]);
+        assert_eq!(natural_cmp("ÉCLAIR", "éclair 2"), Ordering::Less);
+        assert_eq!(natural_cmp("abc", "ABC"), Ordering::Greater);
+        assert_eq!(natural_cmp("abc", "abc"), Ordering::Equal);
+    }
+
+    #[test]
+    fn shorter_names_come_first() {
+        assert_eq!(
+            sorted(&["Song 1 (Live)", "Song", "Song 1"]),
+            ["Song", "Song 1", "Song 1 (Live)"]
+        );
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+        assert_eq!(natural_cmp("a1", "a"), Ordering::Greater);
+        // Digits and text at the same place: digits sort first
+        assert_eq!(natural_cmp("1 Song", "A Song"), Ordering::Less);
+    }
+}
@@ -0,0 +1,87 @@
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::audio::SampleTap;
+
+const FFT_SIZE: usize = 2048;
+/// Frequency range covered by the bars, spaced logarithmically like human hearing
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16_000.0;
+/// Level shown as an empty bar
+const MIN_DB: f32 = -60.0;
+/// How much a bar can fall per frame, so peaks linger a little
+const FALL_PER_FRAME: f32 = 0.06;
+
+/// Turns the latest tapped samples into per-band levels in `0.0..=1.0`
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    bars: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        // Hann window to limit spectral leakage between bands
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+
+        Self {
+            fft,
+            window,
+            bars: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, tap: &SampleTap, bar_count: usize) -> &[f32] {
+        if self.bars.len() != bar_count {
+            self.bars = vec![0.0; bar_count];
+        }
+
+        let samples = tap.latest_mono(FFT_SIZE);
+        let sample_rate = tap.sample_rate() as f32;
+        if samples.len() < FFT_SIZE || sample_rate == 0.0 {
+            // Nothing playing: let the bars fall back to zero
+            self.bars
+                .iter_mut()
+                .for_each(|bar| *bar = (*bar - FALL_PER_FRAME).max(0.0));
+            return &self.bars;
+        }
+
+        let mut buffer: Vec<Complex<f32>> = samples
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, w)| Complex::new(sample * w, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        // A full scale sine ends up with a magnitude of about N/4 once windowed
+        let scale = 4.0 / FFT_SIZE as f32;
+        let bin_hz = sample_rate / FFT_SIZE as f32;
+        let max_bin = FFT_SIZE / 2 - 1;
+        let ratio = MAX_FREQ / MIN_FREQ;
+
+        for (i, bar) in self.bars.iter_mut().enumerate() {
+            let low = MIN_FREQ * ratio.powf(i as f32 / bar_count as f32);
+            let high = MIN_FREQ * ratio.powf((i + 1) as f32 / bar_count as f32);
+            let first = ((low / bin_hz) as usize).min(max_bin);
+            let last = ((high / bin_hz) as usize).clamp(first, max_bin);
+
+            let magnitude = buffer[first..=last]
+                .iter()
+                .map(|c| c.norm() * scale)
+                .fold(0.0, f32::max);
+            let db = 20.0 * magnitude.max(1e-9).log10();
+            let level = ((db - MIN_DB) / -MIN_DB).clamp(0.0, 1.0);
+
+            *bar = if level > *bar {
+                level
+            } else {
+                (*bar - FALL_PER_FRAME).max(level)
+            };
+        }
+
+        &self.bars
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+const APP_DIR: &str = "jukebox-cli";
+
+/// Path of a file inside the user data directory (e.g. `~/.local/share/jukebox-cli`)
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_DIR).join(name))
+}
+
+/// Path of a file inside the user cache directory (e.g. `~/.cache/jukebox-cli`)
+pub fn cache_file(name: &str) -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join(APP_DIR).join(name))
+}
+
+/// Load a JSON file, falling back to the default value if missing or unreadable
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> T {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Save a value as JSON, writing to a temporary file first so a crash never leaves a truncated file
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let content = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Load a file of one JSON value per line, skipping lines that cannot be read
+pub fn load_json_lines<T: DeserializeOwned>(path: &Path) -> Vec<T> {
+    fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Append a value as one JSON line, so logs grow without rewriting the whole file
+pub fn append_json_line<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut line = serde_json::to_string(value).map_err(io::Error::other)?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())
+}
@@ -0,0 +1,686 @@
+use rodio::{ChannelCount, Sample, SampleRate, Source, source::SeekError};
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder as CodecDecoder, DecoderOptions},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader},
+    io::{MediaSourceStream, ReadOnlySource},
+    meta::MetadataOptions,
+    probe::Hint,
+};
+use ureq::Agent;
+
+/// Bytes buffered before decoding starts (and again after an underrun)
+const PREBUFFER_BYTES: usize = 64 * 1024;
+/// Maximum bytes kept in memory ahead of the decoder
+const MAX_BUFFER_BYTES: usize = 2 * 1024 * 1024;
+const CHUNK_SIZE: usize = 16 * 1024;
+/// Consecutive failed connections before giving up
+const MAX_RECONNECTS: u32 = 5;
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often blocked readers and writers check whether the stream was closed
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the UI shows about a network stream
+#[derive(Debug, Clone, Default)]
+pub struct StreamStatus {
+    /// Station name from the `icy-name` header
+    pub station: Option<String>,
+    /// Current song title from ICY `StreamTitle` metadata
+    pub title: Option<String>,
+    /// True while waiting for enough data to (re)start decoding
+    pub buffering: bool,
+    /// Prebuffer fill level in percent, meaningful while buffering
+    pub buffer_percent: u8,
+    pub reconnects: u32,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    data: VecDeque<u8>,
+    /// No more data will ever be pushed
+    finished: bool,
+    /// The player is no longer interested in this stream
+    closed: bool,
+    prebuffering: bool,
+}
+
+struct Shared {
+    buffer: Mutex<Buffer>,
+    changed: Condvar,
+    status: Mutex<StreamStatus>,
+}
+
+impl Shared {
+    fn update_status(&self, update: impl FnOnce(&mut StreamStatus)) {
+        if let Ok(mut status) = self.status.lock() {
+            update(&mut status);
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.buffer.lock().map_or(true, |buffer| buffer.closed)
+    }
+
+    /// Append downloaded audio, waiting while the buffer is full. Returns false once closed.
+    fn push(&self, bytes: &[u8]) -> bool {
+        let Ok(mut buffer) = self.buffer.lock() else {
+            return false;
+        };
+        while buffer.data.len() >= MAX_BUFFER_BYTES && !buffer.closed {
+            buffer = match self.changed.wait_timeout(buffer, POLL_INTERVAL) {
+                Ok((buffer, _)) => buffer,
+                Err(_) => return false,
+            };
+        }
+        if buffer.closed {
+            return false;
+        }
+
+        buffer.data.extend(bytes);
+        if buffer.prebuffering {
+            let percent = (buffer.data.len() * 100 / PREBUFFER_BYTES).min(100) as u8;
+            self.update_status(|status| status.buffer_percent = percent);
+        }
+        self.changed.notify_all();
+        true
+    }
+
+    fn finish(&self) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.finished = true;
+            self.changed.notify_all();
+        }
+    }
+}
+
+/// Handle kept by the player to observe and close a stream
+pub struct StreamHandle {
+    shared: Arc<Shared>,
+}
+
+impl StreamHandle {
+    pub fn status(&self) -> StreamStatus {
+        self.shared
+            .status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_error(&self, error: String) {
+        self.shared.update_status(|status| status.error = Some(error));
+    }
+
+    /// Stop downloading and make pending reads return end of stream
+    pub fn close(&self) {
+        if let Ok(mut buffer) = self.shared.buffer.lock() {
+            buffer.closed = true;
+            self.shared.changed.notify_all();
+        }
+    }
+}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Blocking reader over the downloaded bytes, fed to the decoder
+pub struct StreamReader {
+    shared: Arc<Shared>,
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buffer = self
+            .shared
+            .buffer
+            .lock()
+            .map_err(|_| io::Error::other("stream buffer poisoned"))?;
+
+        loop {
+            if buffer.closed {
+                return Ok(0);
+            }
+            if buffer.data.is_empty() && !buffer.finished {
+                buffer.prebuffering = true;
+            }
+            if buffer.prebuffering
+                && (buffer.data.len() >= PREBUFFER_BYTES || buffer.finished)
+            {
+                buffer.prebuffering = false;
+            }
+
+            let buffering = buffer.prebuffering;
+            self.shared.update_status(|status| status.buffering = buffering);
+
+            if !buffering {
+                break;
+            }
+            buffer = self
+                .shared
+                .changed
+                .wait_timeout(buffer, POLL_INTERVAL)
+                .map_err(|_| io::Error::other("stream buffer poisoned"))?
+                .0;
+        }
+
+        let count = buf.len().min(buffer.data.len());
+        for (dst, src) in buf.iter_mut().zip(buffer.data.drain(..count)) {
+            *dst = src;
+        }
+        self.shared.changed.notify_all();
+        Ok(count)
+    }
+}
+
+/// Start downloading `url` in the background
+pub fn open(url: &str) -> (StreamHandle, StreamReader) {
+    let shared = Arc::new(Shared {
+        buffer: Mutex::new(Buffer {
+            prebuffering: true,
+            ..Default::default()
+        }),
+        changed: Condvar::new(),
+        status: Mutex::new(StreamStatus {
+            buffering: true,
+            ..Default::default()
+        }),
+    });
+
+    let url = url.to_string();
+    let fetch_shared = shared.clone();
+    thread::spawn(move || fetch(&url, &fetch_shared));
+
+    (
+        StreamHandle {
+            shared: shared.clone(),
+        },
+        StreamReader { shared },
+    )
+}
+
+/// Download loop: follows the stream, strips ICY metadata and reconnects on failures
+fn fetch(url: &str, shared: &Shared) {
+    let agent: Agent = Agent::config_builder()
+        .timeout_connect(Some(NETWORK_TIMEOUT))
+        .timeout_recv_response(Some(NETWORK_TIMEOUT))
+        .build()
+        .into();
+
+    let mut received: u64 = 0;
+    let mut total_len: Option<u64> = None;
+    let mut resumable = false;
+    let mut failures = 0;
+
+    loop {
+        if shared.is_closed() {
+            return;
+        }
+
+        let mut request = agent.get(url).header("Icy-MetaData", "1");
+        if received > 0 && resumable {
+            request = request.header("Range", format!("bytes={}-", received));
+        }
+
+        // Live streams and interrupted downloads are retried, plain files that ended are not
+        let should_retry = match request.call() {
+            Ok(response) => {
+                let header = |name: &str| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(|value| value.to_string())
+                };
+                let metaint = header("icy-metaint").and_then(|v| v.parse::<usize>().ok());
+                let station = header("icy-name");
+                let is_live = metaint.is_some() || station.is_some();
+                resumable = header("accept-ranges").is_some_and(|v| v.contains("bytes"));
+                if received == 0 {
+                    total_len = header("content-length").and_then(|v| v.parse().ok());
+                }
+                shared.update_status(|status| {
+                    status.station = station;
+                    status.error = None;
+                });
+
+                let body = response.into_body().into_reader();
+                let mut reader = IcyReader::new(body, metaint, shared);
+                let mut chunk = vec![0; CHUNK_SIZE];
+                loop {
+                    match reader.read(&mut chunk) {
+                        Ok(0) => break is_live,
+                        Ok(n) => {
+                            if !shared.push(&chunk[..n]) {
+                                return;
+                            }
+                            received += n as u64;
+                            failures = 0;
+                        }
+                        Err(e) => {
+                            shared.update_status(|status| status.error = Some(e.to_string()));
+                            break true;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                shared.update_status(|status| status.error = Some(e.to_string()));
+                true
+            }
+        };
+
+        let complete = total_len.is_some_and(|total| received >= total);
+        if complete || !should_retry {
+            shared.update_status(|status| status.error = None);
+            shared.finish();
+            return;
+        }
+
+        // Restarting a non resumable file from scratch would replay it
+        failures += 1;
+        if failures > MAX_RECONNECTS || (total_len.is_some() && !resumable) {
+            shared.finish();
+            return;
+        }
+
+        shared.update_status(|status| status.reconnects += 1);
+        thread::sleep(Duration::from_secs(failures as u64));
+    }
+}
+
+/// Removes the ICY metadata blocks interleaved every `metaint` bytes of audio
+struct IcyReader<'a, R> {
+    inner: R,
+    metaint: Option<usize>,
+    until_metadata: usize,
+    shared: &'a Shared,
+}
+
+impl<'a, R: Read> IcyReader<'a, R> {
+    fn new(inner: R, metaint: Option<usize>, shared: &'a Shared) -> Self {
+        Self {
+            inner,
+            metaint,
+            until_metadata: metaint.unwrap_or(0),
+            shared,
+        }
+    }
+
+    /// Read the metadata block due next, false when the stream ended right before it
+    fn read_metadata(&mut self) -> io::Result<bool> {
+        let mut length = [0u8; 1];
+        if self.inner.read(&mut length)? == 0 {
+            return Ok(false);
+        }
+
+        let mut metadata = vec![0; length[0] as usize * 16];
+        self.inner.read_exact(&mut metadata)?;
+
+        if let Some(title) = parse_stream_title(&String::from_utf8_lossy(&metadata)) {
+            self.shared.update_status(|status| status.title = Some(title));
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for IcyReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(metaint) = self.metaint else {
+            return self.inner.read(buf);
+        };
+
+        if self.until_metadata == 0 {
+            if !self.read_metadata()? {
+                return Ok(0);
+            }
+            self.until_metadata = metaint;
+        }
+
+        let max = buf.len().min(self.until_metadata);
+        let n = self.inner.read(&mut buf[..max])?;
+        self.until_metadata -= n;
+        Ok(n)
+    }
+}
+
+/// Extract the title from ICY metadata such as `StreamTitle='Artist - Song';StreamUrl='';`
+fn parse_stream_title(metadata: &str) -> Option<String> {
+    let start = metadata.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &metadata[start..];
+    let end = rest.find("';").unwrap_or(rest.trim_end_matches('\0').len());
+    let title = rest[..end].trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// Decodes a network stream progressively with symphonia
+pub struct StreamSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    track_id: u32,
+    samples: Vec<Sample>,
+    pos: usize,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+}
+
+impl StreamSource {
+    /// Probe the stream format; blocks until enough data has been downloaded
+    pub fn new(reader: StreamReader, extension: Option<&str>) -> Result<Self, String> {
+        let mss = MediaSourceStream::new(Box::new(ReadOnlySource::new(reader)), Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = extension {
+            hint.with_extension(ext);
+        }
+
+        let format = symphonia::default::get_probe()
+            .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(|e| format!("Unsupported stream: {}", e))?
+            .format;
+
+        let track = format
+            .default_track()
+            .ok_or_else(|| "Stream has no audio track".to_string())?;
+        let track_id = track.id;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(|e| format!("Unsupported codec: {}", e))?;
+
+        let mut source = Self {
+            format,
+            decoder,
+            track_id,
+            samples: Vec::new(),
+            pos: 0,
+            channels: 2,
+            sample_rate: 44100,
+        };
+
+        // Decode the first packet to learn the real channel count and sample rate
+        if !source.decode_next() {
+            return Err("Stream ended before any audio was decoded".to_string());
+        }
+        Ok(source)
+    }
+
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let Ok(packet) = self.format.next_packet() else {
+                return false;
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    let mut buffer = SampleBuffer::<Sample>::new(decoded.capacity() as u64, spec);
+                    buffer.copy_interleaved_ref(decoded);
+
+                    self.samples.clear();
+                    self.samples.extend_from_slice(buffer.samples());
+                    self.pos = 0;
+                    self.channels = spec.channels.count() as ChannelCount;
+                    self.sample_rate = spec.rate;
+
+                    if !self.samples.is_empty() {
+                        return true;
+                    }
+                }
+                // Corrupted frames are common after a reconnect, skip them
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Iterator for StreamSource {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        if self.pos >= self.samples.len() && !self.decode_next() {
+            return None;
+        }
+        let sample = self.samples[self.pos];
+        self.pos += 1;
+        Some(sample)
+    }
+}
+
+impl Source for StreamSource {
+    fn current_span_len(&self) -> Option<usize> {
+        Some(self.samples.len() - self.pos).filter(|len| *len > 0)
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, _pos: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: "network stream",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Cursor, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    fn shared() -> Shared {
+        Shared {
+            buffer: Mutex::default(),
+            changed: Condvar::new(),
+            status: Mutex::default(),
+        }
+    }
+
+    /// ICY metadata block: its length in 16 byte units, then the padded text
+    fn metadata_block(text: &str) -> Vec<u8> {
+        let mut block = text.as_bytes().to_vec();
+        block.resize(text.len().div_ceil(16) * 16, 0);
+        let mut data = vec![(block.len() / 16) as u8];
+        data.extend(block);
+        data
+    }
+
+    /// Audio bytes of `metaint` interleaved with `blocks`, and the audio alone
+    fn icy_body(metaint: usize, blocks: &[Vec<u8>]) -> (Vec<u8>, Vec<u8>) {
+        let audio: Vec<u8> = (0..metaint * (blocks.len() + 1)).map(|i| i as u8).collect();
+        let mut body = Vec::new();
+        for (i, chunk) in audio.chunks(metaint).enumerate() {
+            body.extend(chunk);
+            if let Some(block) = blocks.get(i) {
+                body.extend(block);
+            }
+        }
+        (body, audio)
+    }
+
+    /// Everything `reader` gives, read `size` bytes at a time
+    fn read_all(mut reader: impl Read, size: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = vec![0; size];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => return data,
+                n => data.extend(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn icy_reader_strips_metadata_blocks() {
+        let blocks = [
+            metadata_block("StreamTitle='Artist - First';StreamUrl='';"),
+            // Empty blocks are common between title changes
+            vec![0],
+            metadata_block("StreamTitle='Artist - Second';"),
+        ];
+        let (body, audio) = icy_body(10, &blocks);
+
+        for size in [1, 3, 10, 64] {
+            let shared = shared();
+            let reader = IcyReader::new(Cursor::new(&body), Some(10), &shared);
+            assert_eq!(read_all(reader, size), audio, "reading {} bytes at a time", size);
+            assert_eq!(shared.status.lock().unwrap().title.as_deref(), Some("Artist - Second"));
+        }
+    }
+
+    #[test]
+    fn icy_reader_passes_plain_streams_through() {
+        let shared = shared();
+        let body: Vec<u8> = (0..100).collect();
+        let reader = IcyReader::new(Cursor::new(&body), None, &shared);
+        assert_eq!(read_all(reader, 7), body);
+        assert_eq!(shared.status.lock().unwrap().title, None);
+    }
+
+    #[test]
+    fn icy_reader_fails_on_truncated_metadata() {
+        let shared = shared();
+        let mut body = vec![1, 2, 3, 4];
+        body.extend([2, b'x']);
+        let mut reader = IcyReader::new(Cursor::new(body), Some(4), &shared);
+        let mut buf = [0; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert!(reader.read(&mut buf).is_err());
+    }
+
+    #[test]
+    fn parses_stream_titles() {
+        assert_eq!(
+            parse_stream_title("StreamTitle='Artist - Song';StreamUrl='http://x';"),
+            Some("Artist - Song".to_string())
+        );
+        assert_eq!(
+            parse_stream_title("StreamTitle='Guns N' Roses - Patience';\0\0"),
+            Some("Guns N' Roses - Patience".to_string())
+        );
+        // Unterminated, padded with zeros
+        assert_eq!(
+            parse_stream_title("StreamTitle='Cut short\0\0\0"),
+            Some("Cut short".to_string())
+        );
+        assert_eq!(parse_stream_title("StreamTitle='  ';"), None);
+        assert_eq!(parse_stream_title("StreamUrl='http://x';"), None);
+        assert_eq!(parse_stream_title(""), None);
+    }
+
+    /// Request line and headers of the next request on `stream`, lowercased
+    fn read_request(stream: &TcpStream) -> Vec<String> {
+        let mut reader = BufReader::new(stream);
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                return lines;
+            }
+            lines.push(line.trim_end().to_ascii_lowercase());
+        }
+    }
+
+    /// Local server answering each connection with the response `respond` makes from
+    /// the request headers, closing the connection after it
+    fn serve(respond: impl Fn(usize, &[String]) -> Vec<u8> + Send + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for (connection, stream) in listener.incoming().flatten().enumerate() {
+                let request = read_request(&stream);
+                let _ = (&stream).write_all(&respond(connection, &request));
+            }
+        });
+        url
+    }
+
+    fn response(status: &str, headers: &[&str], body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+        for header in headers {
+            response.push_str(header);
+            response.push_str("\r\n");
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend(body);
+        response
+    }
+
+    #[test]
+    fn interrupted_download_resumes_where_it_stopped() {
+        let file: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let half = file.len() / 2;
+        let served = file.clone();
+        let url = serve(move |connection, request| {
+            let length = format!("Content-Length: {}", served.len());
+            match connection {
+                // The connection drops halfway through the announced length
+                0 => response("200 OK", &[&length, "Accept-Ranges: bytes"], &served[..half]),
+                _ => {
+                    assert!(request.contains(&format!("range: bytes={}-", half)));
+                    let length = format!("Content-Length: {}", served.len() - half);
+                    response("206 Partial Content", &[&length], &served[half..])
+                }
+            }
+        });
+
+        let (handle, reader) = open(&url);
+        assert_eq!(read_all(reader, CHUNK_SIZE), file);
+        let status = handle.status();
+        assert_eq!(status.reconnects, 1);
+        assert_eq!(status.error, None);
+    }
+
+    #[test]
+    fn live_stream_reconnects_and_reads_titles() {
+        // Each connection gives half of the prebuffer, so playback starts on the second one
+        let metaint = PREBUFFER_BYTES / 4;
+        let url = serve(move |connection, request| {
+            assert!(request.contains(&"icy-metadata: 1".to_string()));
+            let title = format!("StreamTitle='Song {}';", connection);
+            let (body, _) = icy_body(metaint, &[metadata_block(&title)]);
+            let metaint = format!("icy-metaint: {}", metaint);
+            response("200 OK", &[&metaint, "icy-name: Test Radio"], &body)
+        });
+
+        let (handle, mut reader) = open(&url);
+        let mut audio = vec![0; PREBUFFER_BYTES];
+        reader.read_exact(&mut audio).unwrap();
+        let (_, connection_audio) = icy_body(metaint, &[Vec::new()]);
+        assert_eq!(audio, [connection_audio.clone(), connection_audio].concat());
+
+        let status = handle.status();
+        assert_eq!(status.station.as_deref(), Some("Test Radio"));
+        assert_eq!(status.title.as_deref(), Some("Song 1"));
+        assert!(status.reconnects >= 1);
+
+        // The live stream never ends by itself
+        handle.close();
+        assert_eq!(reader.read(&mut audio).unwrap(), 0);
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use crate::{jukebox_state::SongItem, metadata::TrackTags};
+
+/// Fields of the form, in display order
+pub const FIELDS: [&str; 6] = ["Title", "Artist", "Album", "Track", "Year", "Genre"];
+
+/// Tags of a song being edited, one text value per field
+#[derive(Debug, Clone)]
+pub struct TagForm {
+    path: PathBuf,
+    /// Tags as read, for the fields the form does not show
+    original: TrackTags,
+    values: [String; 6],
+    selected: usize,
+}
+
+impl TagForm {
+    pub fn new(song: &SongItem) -> Self {
+        let tags = song.tags().clone();
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+
+        Self {
+            path: song.as_path().to_path_buf(),
+            values: [
+                text(&tags.title),
+                text(&tags.artist),
+                text(&tags.album),
+                tags.track_number.map(|track| track.to_string()).unwrap_or_default(),
+                tags.year.map(|year| year.to_string()).unwrap_or_default(),
+                text(&tags.genre),
+            ],
+            original: tags,
+            selected: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Move to the next (or previous) field, wrapping around
+    pub fn move_selection(&mut self, direction: i32) {
+        let count = FIELDS.len() as i32;
+        self.selected = (self.selected as i32 + direction).rem_euclid(count) as usize;
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.values[self.selected].push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.values[self.selected].pop();
+    }
+
+    /// Tags to write, empty fields removing the tag. Fails on a track or year that is not a number
+    pub fn tags(&self) -> Result<TrackTags, String> {
+        let text = |index: usize| {
+            let value = self.values[index].trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+        let track_number = text(3)
+            .map(|track| track.parse().map_err(|_| format!("'{}' is not a track number", track)))
+            .transpose()?;
+        let year = text(4)
+            .map(|year| year.parse().map_err(|_| format!("'{}' is not a year", year)))
+            .transpose()?;
+
+        Ok(TrackTags {
+            title: text(0),
+            artist: text(1),
+            album: text(2),
+            track_number,
+            year,
+            genre: text(5),
+            ..self.original.clone()
+        })
+    }
+}
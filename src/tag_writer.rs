@@ -0,0 +1,635 @@
+use id3::{Tag, TagLike, Version, frame::Timestamp};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{metadata::TrackTags, mp4_tags, ogg_tags};
+
+/// Extensions of the files whose tags can be written
+const WRITABLE_EXTENSIONS: [&str; 4] = ["mp3", "flac", "ogg", "m4a"];
+
+const FLAC_MAGIC: &[u8] = b"fLaC";
+const FLAC_VORBIS_COMMENT: u8 = 4;
+/// Largest metadata block, its length is stored on 24 bits
+const FLAC_MAX_BLOCK: usize = (1 << 24) - 1;
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Whether `write_tags` knows the tag format of this file
+pub fn is_writable(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| WRITABLE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Write the title, artist, album, track number, year and genre into the file,
+/// as ID3v2 for MP3, Vorbis comments for FLAC and Ogg, or MP4 atoms for M4A.
+///
+/// Fields set to `None` are removed, any other tag is kept. The new file is
+/// written next to the original and renamed over it, so a failure never leaves
+/// a half-written song behind.
+pub fn write_tags(path: &Path, tags: &TrackTags) -> io::Result<()> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let tmp_path = temp_path(path);
+
+    let written = match ext.as_str() {
+        "mp3" => fs::copy(path, &tmp_path).and_then(|_| write_id3(&tmp_path, tags)),
+        "flac" | "ogg" | "m4a" => write_copy(path, &tmp_path, &ext, tags),
+        _ => {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("cannot write tags to .{} files", ext),
+            ));
+        }
+    };
+
+    match written {
+        Ok(()) => fs::rename(&tmp_path, path),
+        Err(error) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(error)
+        }
+    }
+}
+
+/// Copy the file to `tmp_path` with its tags rewritten. Only the headers are read into
+/// memory, the audio is streamed across untouched
+fn write_copy(path: &Path, tmp_path: &Path, ext: &str, tags: &TrackTags) -> io::Result<()> {
+    let input = File::open(path)?;
+    let permissions = input.metadata()?.permissions();
+    let mut input = BufReader::new(input);
+    let mut output = BufWriter::new(File::create(tmp_path)?);
+    match ext {
+        "flac" => copy_flac_with_tags(&mut input, &mut output, tags)?,
+        "ogg" => ogg_tags::copy_with_tags(&mut input, &mut output, tags)?,
+        _ => mp4_tags::copy_with_tags(&mut input, &mut output, tags)?,
+    }
+    let output = output.into_inner().map_err(io::IntoInnerError::into_error)?;
+    output.set_permissions(permissions)
+}
+
+/// Fill `buf` from the reader, `false` when it ends first
+pub fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Hidden sibling of `path` for the rewritten file, unique to this file and process so
+/// that neither `song.mp3` and `song.flac` nor a `song.tmp` of the user collide
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
+/// Update the ID3v2 tag in place, keeping its version and the frames not edited here
+fn write_id3(path: &Path, tags: &TrackTags) -> io::Result<()> {
+    let mut tag = match Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(error) if matches!(error.kind, id3::ErrorKind::NoTag) => {
+            Tag::with_version(Version::Id3v24)
+        }
+        Err(error) => return Err(io::Error::other(error)),
+    };
+    let version = match tag.version() {
+        Version::Id3v22 => Version::Id3v23,
+        version => version,
+    };
+
+    match &tags.title {
+        Some(title) => tag.set_title(title),
+        None => tag.remove_title(),
+    }
+    match &tags.artist {
+        Some(artist) => tag.set_artist(artist),
+        None => tag.remove_artist(),
+    }
+    match &tags.album {
+        Some(album) => tag.set_album(album),
+        None => tag.remove_album(),
+    }
+    match tags.track_number {
+        Some(track) => tag.set_track(track),
+        None => tag.remove_track(),
+    }
+    match &tags.genre {
+        Some(genre) => tag.set_genre(genre),
+        None => tag.remove_genre(),
+    }
+    // ID3v2.4 keeps the year in the recording time, ID3v2.3 in its own frame
+    tag.remove_year();
+    tag.remove_date_recorded();
+    if let Some(year) = tags.year {
+        if version == Version::Id3v24 {
+            tag.set_date_recorded(Timestamp {
+                year,
+                month: None,
+                day: None,
+                hour: None,
+                minute: None,
+                second: None,
+            });
+        } else {
+            tag.set_year(year);
+        }
+    }
+
+    tag.write_to_path(path, version).map_err(io::Error::other)
+}
+
+/// Vorbis comment block, as found in FLAC metadata and Ogg Vorbis/Opus headers
+pub struct VorbisComments {
+    vendor: Vec<u8>,
+    /// `KEY=value` entries in file order
+    comments: Vec<Vec<u8>>,
+}
+
+impl VorbisComments {
+    pub fn new() -> Self {
+        Self {
+            vendor: env!("CARGO_PKG_NAME").as_bytes().to_vec(),
+            comments: Vec::new(),
+        }
+    }
+
+    /// Read a comment block, returning it with the number of bytes it took
+    pub fn parse(data: &[u8]) -> Option<(Self, usize)> {
+        let mut pos = 0;
+        let read_field = |pos: &mut usize| -> Option<Vec<u8>> {
+            let len = u32::from_le_bytes(data.get(*pos..*pos + 4)?.try_into().ok()?) as usize;
+            let field = data.get(*pos + 4..(*pos + 4).checked_add(len)?)?.to_vec();
+            *pos += 4 + len;
+            Some(field)
+        };
+
+        let vendor = read_field(&mut pos)?;
+        let count = u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?);
+        pos += 4;
+        let comments = (0..count)
+            .map(|_| read_field(&mut pos))
+            .collect::<Option<Vec<_>>>()?;
+        Some((Self { vendor, comments }, pos))
+    }
+
+    /// Replace the fields edited by the tag editor, keeping every other comment
+    pub fn set_tags(&mut self, tags: &TrackTags) {
+        const EDITED: [&str; 7] =
+            ["TITLE", "ARTIST", "ALBUM", "TRACKNUMBER", "DATE", "YEAR", "GENRE"];
+        self.comments.retain(|comment| {
+            let key = comment.split(|&byte| byte == b'=').next().unwrap_or_default();
+            !EDITED
+                .iter()
+                .any(|edited| key.eq_ignore_ascii_case(edited.as_bytes()))
+        });
+
+        let fields = [
+            ("TITLE", tags.title.clone()),
+            ("ARTIST", tags.artist.clone()),
+            ("ALBUM", tags.album.clone()),
+            ("TRACKNUMBER", tags.track_number.map(|track| track.to_string())),
+            ("DATE", tags.year.map(|year| year.to_string())),
+            ("GENRE", tags.genre.clone()),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                self.comments.push(format!("{}={}", key, value).into_bytes());
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend((self.vendor.len() as u32).to_le_bytes());
+        bytes.extend(&self.vendor);
+        bytes.extend((self.comments.len() as u32).to_le_bytes());
+        for comment in &self.comments {
+            bytes.extend((comment.len() as u32).to_le_bytes());
+            bytes.extend(comment);
+        }
+        bytes
+    }
+}
+
+/// Copy a FLAC file with its Vorbis comment block replaced, or added after STREAMINFO
+fn copy_flac_with_tags<R: Read + Seek>(
+    input: &mut R,
+    output: &mut impl Write,
+    tags: &TrackTags,
+) -> io::Result<()> {
+    let not_flac = || invalid("not a FLAC file");
+    let truncated = || invalid("truncated FLAC metadata");
+
+    // Some taggers put an ID3v2 tag in front of the stream, it is kept as is
+    let mut header = [0; 10];
+    if !read_full(input, &mut header)? {
+        return Err(not_flac());
+    }
+    let start = if header.starts_with(b"ID3") {
+        let size = header[6..10]
+            .iter()
+            .fold(0, |size, &byte| (size << 7) | (byte & 0x7f) as usize);
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        10 + size + footer
+    } else {
+        0
+    };
+    input.seek(SeekFrom::Start(0))?;
+    let mut prefix = vec![0; start + 4];
+    if !read_full(input, &mut prefix)? || &prefix[start..] != FLAC_MAGIC {
+        return Err(not_flac());
+    }
+
+    // Metadata blocks: one header byte (last flag and type), a 24 bit length, the body
+    let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+    loop {
+        let mut header = [0; 4];
+        if !read_full(input, &mut header)? {
+            return Err(truncated());
+        }
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let mut body = vec![0; len];
+        if !read_full(input, &mut body)? {
+            return Err(truncated());
+        }
+        blocks.push((header[0] & 0x7f, body));
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+
+    let mut comments = match blocks.iter().find(|(kind, _)| *kind == FLAC_VORBIS_COMMENT) {
+        Some((_, body)) => VorbisComments::parse(body)
+            .ok_or_else(|| invalid("unreadable Vorbis comments"))?
+            .0,
+        None => VorbisComments::new(),
+    };
+    comments.set_tags(tags);
+    let comment_block = comments.to_bytes();
+    if comment_block.len() > FLAC_MAX_BLOCK {
+        return Err(invalid("tags too large for a FLAC metadata block"));
+    }
+
+    match blocks.iter().position(|(kind, _)| *kind == FLAC_VORBIS_COMMENT) {
+        Some(index) => blocks[index].1 = comment_block,
+        // STREAMINFO always comes first
+        None => blocks.insert(1.min(blocks.len()), (FLAC_VORBIS_COMMENT, comment_block)),
+    }
+
+    output.write_all(&prefix)?;
+    for (i, (kind, body)) in blocks.iter().enumerate() {
+        let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+        output.write_all(&[kind | last])?;
+        output.write_all(&(body.len() as u32).to_be_bytes()[1..])?;
+        output.write_all(body)?;
+    }
+    // The audio frames follow the metadata
+    io::copy(input, output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metadata,
+        testing::{ScratchDir, flac},
+        track_id::track_id,
+    };
+
+    /// Encoded packets of the default track, as symphonia demuxes them
+    fn packets(path: &Path) -> Vec<Vec<u8>> {
+        let mut format = metadata::open_format(path).expect("readable file").format;
+        let track = format.default_track().expect("audio track").id;
+        let mut packets = Vec::new();
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() == track {
+                packets.push(packet.data.to_vec());
+            }
+        }
+        packets
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    /// Tags symphonia reads back, without the album artist the editor leaves alone
+    fn read_tags(path: &Path) -> TrackTags {
+        TrackTags {
+            album_artist: None,
+            ..metadata::probe(path).tags
+        }
+    }
+
+    /// Write a full set of tags, then remove most of them: symphonia must read each set
+    /// back, the audio packets must not change and no temporary file may be left
+    fn assert_round_trip(path: &Path) {
+        let audio = packets(path);
+        assert!(!audio.is_empty());
+        let id = track_id(path);
+        assert!(id.is_some());
+
+        let tags = TrackTags {
+            title: Some("A title long enough to move the audio further".to_string()),
+            artist: Some("Sigur Rós".to_string()),
+            album: Some("Ágætis byrjun".to_string()),
+            album_artist: None,
+            track_number: Some(7),
+            year: Some(1999),
+            genre: Some("Post-rock".to_string()),
+        };
+        write_tags(path, &tags).unwrap();
+        assert_eq!(read_tags(path), tags);
+        assert_eq!(packets(path), audio);
+
+        let tags = TrackTags {
+            title: Some("T".to_string()),
+            ..TrackTags::default()
+        };
+        write_tags(path, &tags).unwrap();
+        assert_eq!(read_tags(path), tags);
+        assert_eq!(packets(path), audio);
+        // Plays, ratings and bookmarks stay with the track
+        assert_eq!(track_id(path), id);
+
+        let dir = path.parent().unwrap();
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn flac_round_trip() {
+        let dir = ScratchDir::new("flac");
+        let path = dir.join("song.flac");
+        fs::write(&path, flac(&["TITLE=Old", "REPLAYGAIN_TRACK_GAIN=-3 dB"])).unwrap();
+
+        assert_round_trip(&path);
+        assert!(contains(&fs::read(&path).unwrap(), b"REPLAYGAIN_TRACK_GAIN=-3 dB"));
+    }
+
+    #[test]
+    fn flac_behind_id3_round_trip() {
+        let dir = ScratchDir::new("flac-id3");
+        let path = dir.join("song.flac");
+        // ID3v2.4 header with 100 bytes of padding
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x64".to_vec();
+        data.extend([0; 100]);
+        data.extend(flac(&[]));
+        fs::write(&path, data).unwrap();
+
+        assert_round_trip(&path);
+        assert!(fs::read(&path).unwrap().starts_with(b"ID3\x04"));
+    }
+
+    #[test]
+    fn mp3_round_trip() {
+        let dir = ScratchDir::new("mp3");
+        let path = dir.join("song.mp3");
+        let sample = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("example_music/intensity-by-audio-club-343637.mp3");
+        fs::copy(sample, &path).unwrap();
+
+        assert_round_trip(&path);
+    }
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend(kind);
+        atom.extend(body);
+        atom
+    }
+
+    fn full_atom(kind: &[u8; 4], flags: u32, body: &[u8]) -> Vec<u8> {
+        atom(kind, &[&flags.to_be_bytes(), body].concat())
+    }
+
+    fn be32(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_be_bytes()).collect()
+    }
+
+    const MP4_RATE: u32 = 8000;
+    const MATRIX: [u32; 9] = [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x4000_0000];
+
+    /// M4A file of one second of 16 bit PCM in two chunks, tagged with a title and a
+    /// comment. Chunk offsets are 32 or 64 bit, `moov` comes before or after `mdat`
+    fn mp4(moov_first: bool, co64: bool) -> Vec<u8> {
+        let samples = MP4_RATE;
+        let pcm: Vec<u8> = (0..samples)
+            .flat_map(|i| ((i % 500) as i16 * 16).to_le_bytes())
+            .collect();
+
+        let moov = |offset: u32| {
+            let mvhd = full_atom(
+                b"mvhd",
+                0,
+                &[
+                    be32(&[0, 0, MP4_RATE, samples, 0x10000]),
+                    vec![1, 0],
+                    vec![0; 10],
+                    be32(&MATRIX),
+                    vec![0; 24],
+                    be32(&[2]),
+                ]
+                .concat(),
+            );
+            let tkhd = full_atom(
+                b"tkhd",
+                7,
+                &[
+                    be32(&[0, 0, 1, 0, samples, 0, 0]),
+                    vec![0, 0, 0, 0, 1, 0, 0, 0],
+                    be32(&MATRIX),
+                    be32(&[0, 0]),
+                ]
+                .concat(),
+            );
+            let mdhd = full_atom(
+                b"mdhd",
+                0,
+                &[be32(&[0, 0, MP4_RATE, samples]), vec![0x55, 0xc4, 0, 0]].concat(),
+            );
+            let hdlr = full_atom(
+                b"hdlr",
+                0,
+                &[&[0; 4][..], b"soun", &[0; 12], b"SoundHandler\0"].concat(),
+            );
+            let smhd = full_atom(b"smhd", 0, &[0; 4]);
+            let dref = full_atom(b"dref", 0, &[be32(&[1]), full_atom(b"url ", 1, &[])].concat());
+            let dinf = atom(b"dinf", &dref);
+
+            // Sound sample entry: reference index, version, vendor, 1 channel, 16 bits, rate
+            let entry = [
+                vec![0, 0, 0, 0, 0, 0, 0, 1],
+                vec![0; 8],
+                vec![0, 1, 0, 16, 0, 0, 0, 0],
+                be32(&[MP4_RATE << 16]),
+            ]
+            .concat();
+            let stsd = full_atom(b"stsd", 0, &[be32(&[1]), atom(b"sowt", &entry)].concat());
+            let stts = full_atom(b"stts", 0, &be32(&[1, samples, 1]));
+            let stsc = full_atom(b"stsc", 0, &be32(&[1, 1, samples / 2, 1]));
+            let stsz = full_atom(b"stsz", 0, &be32(&[2, samples]));
+            let offsets = [offset, offset + samples];
+            let chunk_offsets = if co64 {
+                let entries: Vec<u8> =
+                    offsets.iter().flat_map(|&o| (o as u64).to_be_bytes()).collect();
+                full_atom(b"co64", 0, &[be32(&[2]), entries].concat())
+            } else {
+                full_atom(b"stco", 0, &be32(&[2, offsets[0], offsets[1]]))
+            };
+            let stbl = atom(b"stbl", &[stsd, stts, stsc, stsz, chunk_offsets].concat());
+            let minf = atom(b"minf", &[smhd, dinf, stbl].concat());
+            let mdia = atom(b"mdia", &[mdhd, hdlr, minf].concat());
+            let trak = atom(b"trak", &[tkhd, mdia].concat());
+
+            let text = |kind: &[u8; 4], value: &[u8]| {
+                atom(kind, &atom(b"data", &[&be32(&[1, 0])[..], value].concat()))
+            };
+            let ilst = [text(b"\xa9nam", b"Old"), text(b"\xa9cmt", b"keep me")].concat();
+            let handler = full_atom(b"hdlr", 0, &[&[0; 4][..], b"mdirappl", &[0; 9]].concat());
+            let meta = full_atom(b"meta", 0, &[handler, atom(b"ilst", &ilst)].concat());
+            atom(b"moov", &[mvhd, trak, atom(b"udta", &meta)].concat())
+        };
+
+        let ftyp = atom(b"ftyp", b"M4A \0\0\0\0M4A mp42isom");
+        let mdat = atom(b"mdat", &pcm);
+        if moov_first {
+            let offset = (ftyp.len() + moov(0).len() + 8) as u32;
+            [ftyp, moov(offset), mdat].concat()
+        } else {
+            let offset = (ftyp.len() + 8) as u32;
+            [ftyp, mdat, moov(offset)].concat()
+        }
+    }
+
+    #[test]
+    fn mp4_round_trip() {
+        for (moov_first, co64) in [(true, false), (true, true), (false, false)] {
+            let dir = ScratchDir::new(&format!("mp4-{}-{}", moov_first, co64));
+            let path = dir.join("song.m4a");
+            fs::write(&path, mp4(moov_first, co64)).unwrap();
+
+            assert_round_trip(&path);
+            assert!(contains(&fs::read(&path).unwrap(), b"keep me"));
+        }
+    }
+
+    const OGG_SERIAL: u32 = 0x1234;
+    const FIRST_PAGE: u8 = 0x02;
+
+    fn ogg_crc(data: &[u8]) -> u32 {
+        data.iter().fold(0, |crc, &byte| {
+            (0..8).fold(crc ^ (byte as u32) << 24, |crc, _| {
+                if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 }
+            })
+        })
+    }
+
+    /// Page holding whole packets
+    fn ogg_page(header_type: u8, granule: u64, sequence: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        let mut page = b"OggS\0".to_vec();
+        page.push(header_type);
+        page.extend(granule.to_le_bytes());
+        page.extend(OGG_SERIAL.to_le_bytes());
+        page.extend(sequence.to_le_bytes());
+        page.extend([0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        for packet in packets {
+            page.extend(*packet);
+        }
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    /// Ogg Vorbis file: the identification header, the comment and setup headers sharing
+    /// the next page, then pages of made up audio packets
+    fn ogg_vorbis() -> Vec<u8> {
+        let mut ident = b"\x01vorbis".to_vec();
+        // Version, mono, 44.1 kHz, no bitrates, block sizes 256 and 2048, framing bit
+        ident.extend([0, 0, 0, 0, 1]);
+        ident.extend(44_100u32.to_le_bytes());
+        ident.extend([0; 12]);
+        ident.extend([0xb8, 0x01]);
+
+        let mut comment = b"\x03vorbis".to_vec();
+        let comments = VorbisComments {
+            vendor: b"test".to_vec(),
+            comments: vec![b"TITLE=Old".to_vec(), b"ENCODER=keep me".to_vec()],
+        };
+        comment.extend(comments.to_bytes());
+        comment.push(0x01);
+        let setup = [&b"\x05vorbis"[..], &[0xaa; 300]].concat();
+
+        let mut data = ogg_page(FIRST_PAGE, 0, 0, &[&ident]);
+        data.extend(ogg_page(0, 0, 1, &[&comment, &setup]));
+        for page in 0..4u8 {
+            let packets: Vec<Vec<u8>> = (0..10u8).map(|i| vec![0, page, i, 0x55, 0x66]).collect();
+            let packets: Vec<&[u8]> = packets.iter().map(Vec::as_slice).collect();
+            let header_type = if page == 3 { 0x04 } else { 0 };
+            data.extend(ogg_page(header_type, 1000 * (page as u64 + 1), 2 + page as u32, &packets));
+        }
+        data
+    }
+
+    /// Number of pages, checking their checksums and that their sequence numbers follow
+    fn check_ogg_pages(data: &[u8]) -> usize {
+        let mut pos = 0;
+        let mut sequence = 0;
+        while pos < data.len() {
+            let page = &data[pos..];
+            assert_eq!(&page[..4], b"OggS");
+            let segments = page[26] as usize;
+            let body: usize = page[27..27 + segments].iter().map(|&len| len as usize).sum();
+            let mut bytes = page[..27 + segments + body].to_vec();
+            let crc = u32::from_le_bytes(bytes[22..26].try_into().unwrap());
+            bytes[22..26].fill(0);
+            assert_eq!(ogg_crc(&bytes), crc);
+            assert_eq!(u32::from_le_bytes(bytes[18..22].try_into().unwrap()), sequence);
+            sequence += 1;
+            pos += bytes.len();
+        }
+        sequence as usize
+    }
+
+    #[test]
+    fn ogg_vorbis_round_trip() {
+        let dir = ScratchDir::new("ogg");
+        let path = dir.join("song.ogg");
+        fs::write(&path, ogg_vorbis()).unwrap();
+        let audio = packets(&path);
+        let pages = check_ogg_pages(&fs::read(&path).unwrap());
+
+        // Comments spanning several pages, the audio pages are renumbered after them
+        let tags = TrackTags {
+            title: Some("a".repeat(150_000)),
+            ..TrackTags::default()
+        };
+        write_tags(&path, &tags).unwrap();
+        assert!(metadata::probe(&path).tags.title == tags.title);
+        assert_eq!(packets(&path), audio);
+        assert!(check_ogg_pages(&fs::read(&path).unwrap()) > pages + 1);
+
+        // Back from several pages to one
+        assert_round_trip(&path);
+        let data = fs::read(&path).unwrap();
+        assert_eq!(check_ogg_pages(&data), pages);
+        assert!(contains(&data, b"ENCODER=keep me"));
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Empty directory for the files of one test, removed with everything in it when dropped
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("jukebox-cli-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// WAV file of 16 bit mono PCM at 44.1 kHz
+pub fn wav(samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut data = b"RIFF".to_vec();
+    data.extend((36 + data_len).to_le_bytes());
+    data.extend(b"WAVEfmt ");
+    data.extend(16u32.to_le_bytes());
+    // PCM, 1 channel, the sample rate, the byte rate, 2 bytes per frame, 16 bits per sample
+    data.extend(1u16.to_le_bytes());
+    data.extend(1u16.to_le_bytes());
+    data.extend(44_100u32.to_le_bytes());
+    data.extend(88_200u32.to_le_bytes());
+    data.extend(2u16.to_le_bytes());
+    data.extend(16u16.to_le_bytes());
+    data.extend(b"data");
+    data.extend(data_len.to_le_bytes());
+    data.extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+    data
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 }
+        })
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| {
+            if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 }
+        })
+    })
+}
+
+const FLAC_BLOCK_SIZE: usize = 4096;
+const FLAC_FRAMES: usize = 3;
+
+/// FLAC stream of a few mono frames stored verbatim, with comments and padding
+pub fn flac(comments: &[&str]) -> Vec<u8> {
+    flac_stream(comments, (FLAC_BLOCK_SIZE * FLAC_FRAMES) as u64)
+}
+
+/// FLAC stream whose header leaves the sample count unknown
+pub fn flac_without_length() -> Vec<u8> {
+    flac_stream(&[], 0)
+}
+
+fn flac_stream(comments: &[&str], samples: u64) -> Vec<u8> {
+    let mut streaminfo = Vec::new();
+    streaminfo.extend((FLAC_BLOCK_SIZE as u16).to_be_bytes());
+    streaminfo.extend((FLAC_BLOCK_SIZE as u16).to_be_bytes());
+    // Frame sizes unknown
+    streaminfo.extend([0; 6]);
+    // 44.1 kHz on 20 bits, 1 channel, 16 bits per sample, then the sample count
+    let format = (44_100u64 << 44) | (15 << 36) | samples;
+    streaminfo.extend(format.to_be_bytes());
+    // No MD5
+    streaminfo.extend([0; 16]);
+
+    // Vorbis comments: the vendor, then each comment, all behind little-endian lengths
+    let mut comment_block = 4u32.to_le_bytes().to_vec();
+    comment_block.extend(b"test");
+    comment_block.extend((comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        comment_block.extend((comment.len() as u32).to_le_bytes());
+        comment_block.extend(comment.as_bytes());
+    }
+    // Block types of the stream info, the Vorbis comments and the padding
+    let blocks = [
+        (0, streaminfo),
+        (4, comment_block),
+        (1, vec![0; 64]),
+    ];
+
+    let mut data = b"fLaC".to_vec();
+    for (i, (kind, body)) in blocks.iter().enumerate() {
+        let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+        data.push(kind | last);
+        data.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        data.extend(body);
+    }
+    for frame in 0..FLAC_FRAMES {
+        // Sync code, 4096 samples at 44.1 kHz, mono, 16 bits, the frame number
+        let mut bytes = vec![0xff, 0xf8, 0xc9, 0x08, frame as u8];
+        bytes.push(crc8(&bytes));
+        // Verbatim subframe
+        bytes.push(0x02);
+        for i in 0..FLAC_BLOCK_SIZE {
+            let sample = ((frame * FLAC_BLOCK_SIZE + i) % 1000) as i16 * 16;
+            bytes.extend(sample.to_be_bytes());
+        }
+        let crc = crc16(&bytes);
+        bytes.extend(crc.to_be_bytes());
+        data.extend(bytes);
+    }
+    data
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use symphonia::core::formats::FormatReader;
+
+use crate::metadata;
+
+/// Bytes of encoded audio hashed from the start of the track
+const SAMPLE_SIZE: usize = 256 * 1024;
+
+pub const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+pub fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |acc, &b| (acc ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Compute a stable identity for a track.
+///
+/// The identity only depends on the encoded audio, not on the tags nor on the
+/// container layout, so it survives tag edits (by this program or any other),
+/// renames and moves, and it is stable across program versions. Only the start
+/// of the audio is read, along with the length of the track, so recordings that
+/// open the same way (a podcast jingle, a few seconds of silence) are told apart.
+pub fn track_id(path: &Path) -> Option<String> {
+    let mut format = metadata::open_format(path)?.format;
+    let track = format.default_track()?;
+    let (track, frames) = (track.id, track.codec_params.n_frames);
+
+    let (hash, hashed) = hash_stream(format.as_mut(), track, SAMPLE_SIZE);
+    if hashed == 0 {
+        return None;
+    }
+    // Without a length in the container, the packets are read to the end to measure it
+    let length = frames.unwrap_or_else(|| (hashed + count_bytes(format.as_mut(), track)) as u64);
+    Some(format!("{:016x}", fnv1a(hash, &length.to_le_bytes())))
+}
+
+/// FNV hash of the packets of the default track, up to about `limit` bytes of them
+pub fn hash_packets(path: &Path, limit: usize) -> Option<String> {
+    let mut format = metadata::open_format(path)?.format;
+    let track = format.default_track()?.id;
+
+    let (hash, hashed) = hash_stream(format.as_mut(), track, limit);
+    (hashed > 0).then(|| format!("{:016x}", hash))
+}
+
+/// FNV hash of the next packets of `track` and their size, reading up to about `limit` bytes.
+/// Reading stops at the end of the stream, or at data that cannot be read.
+fn hash_stream(format: &mut dyn FormatReader, track: u32, limit: usize) -> (u64, usize) {
+    let mut hash = FNV_OFFSET;
+    let mut hashed = 0;
+    while hashed < limit
+        && let Ok(packet) = format.next_packet()
+    {
+        if packet.track_id() == track {
+            hash = fnv1a(hash, &packet.data);
+            hashed += packet.data.len();
+        }
+    }
+    (hash, hashed)
+}
+
+/// Size of the packets of `track` left in the stream
+fn count_bytes(format: &mut dyn FormatReader, track: u32) -> usize {
+    let mut bytes = 0;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() == track {
+            bytes += packet.data.len();
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ScratchDir, wav};
+    use std::fs;
+
+    /// Samples of a few seconds of music after a second of silence
+    fn recording(seconds: usize, seed: usize) -> Vec<i16> {
+        let mut samples = vec![0; 44_100];
+        samples.extend((0..seconds * 44_100).map(|i| ((i * seed) % 2000) as i16 - 1000));
+        samples
+    }
+
+    #[test]
+    fn tracks_opening_the_same_way_differ() {
+        let dir = ScratchDir::new("track-id");
+        let intro = dir.join("intro.wav");
+        let episode = dir.join("episode.wav");
+        let copy = dir.join("copy.wav");
+        // The same first 256 KB, then the episodes go on for different lengths
+        let mut samples = recording(3, 7);
+        fs::write(&intro, wav(&samples)).unwrap();
+        samples.extend(recording(5, 11));
+        fs::write(&episode, wav(&samples)).unwrap();
+        fs::write(&copy, wav(&samples)).unwrap();
+
+        assert_eq!(hash_packets(&intro, SAMPLE_SIZE), hash_packets(&episode, SAMPLE_SIZE));
+        assert_ne!(track_id(&intro), track_id(&episode));
+        assert!(track_id(&episode).is_some());
+        assert_eq!(track_id(&episode), track_id(&copy));
+    }
+}
@@ -0,0 +1,136 @@
+use rodio::{Decoder, Source};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{audio::Segment, jukebox_state::SongItem, storage};
+
+/// Number of columns stored for a track, resampled to the screen width when drawn
+const RESOLUTION: usize = 1000;
+/// Length of the windows peaks are measured over while decoding
+const WINDOW: Duration = Duration::from_millis(20);
+
+/// Peak level per column, `255` being full scale
+pub type Waveform = Arc<Vec<u8>>;
+
+enum Entry {
+    Computing,
+    Ready(Waveform),
+    /// The file could not be decoded, retried once its modification time changes
+    Failed(Option<SystemTime>),
+}
+
+/// Waveform overviews computed in the background, cached in memory and on disk
+#[derive(Default)]
+pub struct Waveforms {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl Waveforms {
+    /// Waveform of a song if ready, starting its computation on the first request
+    pub fn get(&self, song: &SongItem) -> Option<Waveform> {
+        if song.is_stream() {
+            return None;
+        }
+
+        let (start, end) = song.bounds();
+        let key = format!("{}@{}", song.as_path().display(), start.as_millis());
+
+        let mut entries = self.entries.lock().ok()?;
+        match entries.get(&key) {
+            Some(Entry::Computing) => return None,
+            Some(Entry::Ready(waveform)) => return Some(waveform.clone()),
+            Some(Entry::Failed(modified)) if *modified == modified_time(song.as_path()) => {
+                return None;
+            }
+            _ => {}
+        }
+        entries.insert(key.clone(), Entry::Computing);
+
+        let song = song.clone();
+        let shared = self.entries.clone();
+        thread::spawn(move || {
+            // Taken before decoding, a file replaced meanwhile is decoded again
+            let modified = modified_time(song.as_path());
+            let entry = match load_or_compute(&song, start, end) {
+                Some(waveform) => Entry::Ready(Arc::new(waveform)),
+                None => Entry::Failed(modified),
+            };
+            if let Ok(mut entries) = shared.lock() {
+                entries.insert(key, entry);
+            }
+        });
+        None
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn load_or_compute(song: &SongItem, start: Duration, end: Option<Duration>) -> Option<Vec<u8>> {
+    let cache = song
+        .track_id()
+        .and_then(|id| storage::cache_file(&format!("waveforms/{}.json", id)));
+
+    if let Some(cache) = &cache {
+        let cached: Vec<u8> = storage::load_json(cache);
+        if !cached.is_empty() {
+            return Some(cached);
+        }
+    }
+
+    let waveform = compute(song.as_path(), start, end)?;
+    if let Some(cache) = &cache {
+        let _ = storage::save_json(cache, &waveform);
+    }
+    Some(waveform)
+}
+
+/// Decode the whole track and keep the peak of each window
+fn compute(path: &Path, start: Duration, end: Option<Duration>) -> Option<Vec<u8>> {
+    let decoder = Decoder::try_from(File::open(path).ok()?).ok()?;
+    let source = Segment::new(decoder, start, end);
+
+    let samples_per_window = (source.sample_rate() as f64
+        * source.channels() as f64
+        * WINDOW.as_secs_f64())
+    .max(1.0) as usize;
+
+    let mut windows = Vec::new();
+    let mut peak: f32 = 0.0;
+    for (i, sample) in source.enumerate() {
+        peak = peak.max(sample.abs());
+        if (i + 1) % samples_per_window == 0 {
+            windows.push(peak);
+            peak = 0.0;
+        }
+    }
+    windows.push(peak);
+
+    let peaks = resample(&windows, RESOLUTION);
+    Some(peaks.iter().map(|peak| (peak.min(1.0) * 255.0) as u8).collect())
+}
+
+/// Reduce (or stretch) `values` to `columns` values, keeping the maximum of each range
+pub fn resample<T: Copy + PartialOrd + Default>(values: &[T], columns: usize) -> Vec<T> {
+    if values.is_empty() {
+        return vec![T::default(); columns];
+    }
+
+    (0..columns)
+        .map(|column| {
+            let first = (column * values.len() / columns).min(values.len() - 1);
+            let last = ((column + 1) * values.len() / columns).clamp(first + 1, values.len());
+            values[first..last]
+                .iter()
+                .copied()
+                .fold(T::default(), |max, v| if v > max { v } else { max })
+        })
+        .collect()
+}